@@ -0,0 +1,65 @@
+extern crate ares;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ares::Context;
+
+/// Counts the allocations made through it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const CALLS: i64 = 1000;
+
+/// Each iteration of `loop` makes two calls to lambdas and four calls to
+/// foreign functions.
+const SETUP: &'static str = "
+    (define f (lambda (a b) (+ a b) (- a b)))
+    (define loop (lambda (n) (if (= n 0) 0 (let (x (f n 1)) (loop (- n 1))))))";
+
+/// How many allocations an iteration of `loop` makes.
+fn per_iteration(compile: bool) -> f64 {
+    let mut ctx = Context::new_empty();
+    ctx.set_compile_lambdas(compile);
+    ares::stdlib::load_all(&mut ctx);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str(SETUP).unwrap();
+
+    let mut count = |n: i64| {
+        let program = ares::parse(&format!("(loop {})", n), ctx.interner_mut()).unwrap().remove(0);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        ctx.eval(&program).unwrap();
+        ALLOCATIONS.load(Ordering::Relaxed) - before
+    };
+    // Take away the allocations that every evaluation makes.
+    let base = count(0);
+    let total = count(CALLS);
+    (total - base) as f64 / CALLS as f64
+}
+
+fn main() {
+    println!("allocations per iteration (two lambda calls, four foreign calls)");
+    println!("{:<10} {:>8}", "walked", per_iteration(false));
+    println!("{:<10} {:>8}", "compiled", per_iteration(true));
+}
@@ -0,0 +1,70 @@
+extern crate ares;
+
+use std::time::{Duration, Instant};
+
+use ares::Context;
+
+const RUNS: usize = 5;
+
+const WORKLOADS: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("fib",
+     "(define fib (lambda (n) (if (= n 0) 0 (if (= n 1) 1 (+ (fib (- n 1)) (fib (- n 2)))))))",
+     "(fib 20)"),
+    ("tail loop",
+     "(define loop (lambda (n acc) (if (= n 0) acc (loop (- n 1) (+ acc n)))))",
+     "(loop 200000 0)"),
+    ("lists",
+     "(define range (lambda (n) (build-list (lambda (push)
+          (define go (lambda (i) (if (= i n) n (let (x (push i)) (go (+ i 1))))))
+          (go 0)))))",
+     "(fold-left (map (filter (range 20000) (lambda (x) (= (- x (* (/ x 2) 2)) 0)))
+                      (lambda (x) (* x 3)))
+                 0
+                 (lambda (a b) (+ a b)))"),
+];
+
+fn context(compile: bool) -> Context<()> {
+    let mut ctx = Context::new_empty();
+    ctx.set_compile_lambdas(compile);
+    ares::stdlib::load_all(&mut ctx);
+    ctx
+}
+
+/// The fastest of a few runs of `program`, along with what it evaluated to.
+fn time(compile: bool, setup: &str, program: &str) -> (Duration, String) {
+    let mut ctx = context(compile);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str(setup).unwrap();
+
+    let mut best = None;
+    let mut result = String::new();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let value = ctx.eval_str(program).unwrap();
+        let elapsed = start.elapsed();
+        if best.map_or(true, |best| elapsed < best) {
+            best = Some(elapsed);
+        }
+        result = ctx.format_value(&value);
+    }
+    (best.unwrap(), result)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn main() {
+    println!("{:<12} {:>13} {:>13} {:>8}", "workload", "walked (ms)", "compiled (ms)", "speedup");
+    for &(name, setup, program) in WORKLOADS {
+        let (walked, walked_result) = time(false, setup, program);
+        let (compiled, compiled_result) = time(true, setup, program);
+        assert_eq!(walked_result, compiled_result);
+        println!("{:<12} {:>13.2} {:>13.2} {:>7.2}x",
+                 name,
+                 millis(walked),
+                 millis(compiled),
+                 millis(walked) / millis(compiled));
+    }
+}
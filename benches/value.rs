@@ -0,0 +1,50 @@
+extern crate ares;
+
+use std::mem::size_of;
+use std::time::Instant;
+
+use ares::{Context, Value};
+
+const CLONES: usize = 1_000_000;
+
+const VALUES: &'static [(&'static str, &'static str)] = &[
+    ("int", "1"),
+    ("string", "\"hello\""),
+    ("list", "(list 1 2 3)"),
+    ("option", "(some 1)"),
+    ("lambda", "(lambda (a b) (+ a b))"),
+    ("foreign fn", "+"),
+];
+
+/// How many nanoseconds it takes to clone and drop `value`.
+fn clone_time(value: &Value) -> f64 {
+    let mut best = None;
+    let mut values = Vec::with_capacity(CLONES);
+    for _ in 0..5 {
+        let start = Instant::now();
+        for _ in 0..CLONES {
+            values.push(value.clone());
+        }
+        values.clear();
+        let elapsed = start.elapsed();
+        let nanos = elapsed.as_secs() as f64 * 1e9 + elapsed.subsec_nanos() as f64;
+        if best.map_or(true, |best| nanos < best) {
+            best = Some(nanos);
+        }
+    }
+    best.unwrap() / CLONES as f64
+}
+
+fn main() {
+    println!("size of Value: {} bytes", size_of::<Value>());
+    println!();
+
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    println!("{:<12} {:>10}", "clone", "ns");
+    for &(name, program) in VALUES {
+        let value = ctx.eval_str(program).unwrap();
+        println!("{:<12} {:>10.2}", name, clone_time(&value));
+    }
+}
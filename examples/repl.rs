@@ -5,14 +5,42 @@ use term_painter::Color::*;
 use term_painter::ToStyle;
 
 fn main() {
-    let mut ctx = ares::Context::new().with_debug();
+    let mut ctx = ares::Context::new().with_debug().with_file_loading();
     let mut dummy = ();
     let mut ctx = ctx.load(&mut dummy);
+    let mut reader = ares::Reader::new();
 
-    while let Some(line) = ares::util::prompt("repl> ") {
-        match ctx.eval_str(&line) {
-            Ok(v)  => println!("{}", Green.paint(ctx.format_value(&v))),
-            Err(e) => println!("{:?}", Red.paint(e))
+    for path in std::env::args().skip(1) {
+        if let Err(e) = ctx.eval_file(&path) {
+            println!("{}", Red.paint(ctx.describe_error(&e)));
+        }
+    }
+
+    loop {
+        let prompt = if reader.is_empty() { "repl> " } else { "  ... " };
+        let line = match ares::util::prompt(prompt) {
+            Some(line) => line,
+            None => break,
+        };
+        reader.push_str(&line);
+
+        let read = ctx.read(&mut reader);
+        if !read.forms.is_empty() {
+            match ctx.eval_forms(read.forms) {
+                Ok(v)  => println!("{}", Green.paint(ctx.format_value(&v))),
+                Err(e) => {
+                    println!("{}", Red.paint(ctx.describe_error(&e)));
+                    if let Some(backtrace) = ctx.backtrace() {
+                        if !backtrace.frames.is_empty() {
+                            println!("{}", ctx.format_backtrace(backtrace));
+                        }
+                    }
+                }
+            }
+        }
+        if let ares::ReadStatus::Error(e) = read.status {
+            println!("{}", Red.paint(e));
+            reader.clear();
         }
     }
 }
@@ -5,7 +5,7 @@ use std::io::{Read, stdin};
 use std::fmt::Write;
 
 fn main() {
-    let mut ctx = Context::new().with_debug();
+    let mut ctx = Context::new().with_debug().with_file_loading();
 
     ctx.set_fn("print", free_fn("print", |args| {
         let mut buf = String::new();
@@ -1,7 +1,9 @@
 use std::any::Any;
+use std::io;
 
 use Value;
 use parse::ParseError;
+use eval::Jump;
 
 pub type AresResult<T> = Result<T, AresError>;
 
@@ -47,6 +49,32 @@ pub enum AresError {
     MultiValueDefine,
     UnwrapNone,
 
+    /// Evaluation went deeper than the limit set on the context.
+    DepthLimitExceeded(usize),
+    /// Evaluation took all of the steps that the context allowed.
+    OutOfFuel,
+    /// Evaluation ran past the context's deadline.
+    Timeout,
+    /// More memory was allocated than the context allows, in bytes.
+    MemoryLimitExceeded(usize),
+    /// Evaluation was stopped through an `InterruptHandle`.
+    Interrupted,
+
+    /// A continuation was called after the evaluation that it continues
+    /// finished.
+    DeadContinuation,
+    /// A continuation was called.  Foreign functions that evaluate things
+    /// should pass this on, so that it gets to the evaluation that the
+    /// continuation continues.
+    ContinuationCalled(Jump),
+    /// A value raised by `throw`.
+    Thrown(Value),
+
+    IoError {
+        path: String,
+        error: io::Error,
+    },
+
     UserError(Box<Any>),
 }
 
@@ -0,0 +1,110 @@
+use std::rc::Rc;
+
+use Value;
+use parse::Span;
+
+use super::Procedure;
+use super::StepState;
+use super::context::{LoadedContext, State};
+
+/// One of the calls that was running when an error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The name of the function, if it has one.
+    pub name: Option<String>,
+    /// The arguments that the function was called with.  Calls that were
+    /// still evaluating their arguments only have the ones evaluated so far.
+    pub args: Vec<Value>,
+    /// Where the call is in the source, if that is known.
+    pub span: Option<Span>,
+}
+
+/// The calls that were running when an error happened, innermost first.
+///
+/// Tail calls replace the call that made them, so they don't show up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+    // How big the step stack was below each frame, so that foreign functions
+    // that fail after an error in a nested evaluation go in the right place.
+    heights: Vec<usize>,
+}
+
+/// A call to a lambda whose bodies are being evaluated.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Call {
+    pub procedure: Rc<Procedure>,
+    pub args: Vec<Value>,
+    pub form: Option<Rc<Vec<Value>>>,
+}
+
+/// Builds the backtrace for the error that is happening now out of the step
+/// stack, unless there already is one.
+pub fn record_backtrace<S: ?Sized + State>(ctx: &mut LoadedContext<S>) {
+    if ctx.error_backtrace.is_some() {
+        return;
+    }
+    let mut backtrace = Backtrace::default();
+    for (height, state) in ctx.stack.iter().enumerate().rev() {
+        let frame = match *state {
+            StepState::PopEnv(Some(ref call)) => {
+                Frame {
+                    name: call.procedure.name.clone(),
+                    args: call.args.clone(),
+                    span: call.form.as_ref().and_then(|form| ctx.spans().get(form)),
+                }
+            }
+            StepState::ArgCollecting { ref function, ref evaluated, ref form, .. } => {
+                Frame {
+                    name: function_name(function, ctx),
+                    args: evaluated.clone(),
+                    span: ctx.spans().get(form),
+                }
+            }
+            StepState::Compiled(ref activation) => {
+                for (function, args, form) in activation.pending_calls() {
+                    backtrace.frames.push(Frame {
+                        name: function_name(function, ctx),
+                        args: args.to_vec(),
+                        span: ctx.spans().get(form),
+                    });
+                    backtrace.heights.push(height);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        backtrace.frames.push(frame);
+        backtrace.heights.push(height);
+    }
+    ctx.error_backtrace = Some(backtrace);
+}
+
+/// Adds a call that failed to the backtrace.  `height` is how big the step
+/// stack was when the call was made.
+pub fn record_failed_call<S: ?Sized + State>(name: Option<String>,
+                                             args: &[Value],
+                                             form: Option<&Rc<Vec<Value>>>,
+                                             height: usize,
+                                             ctx: &mut LoadedContext<S>) {
+    record_backtrace(ctx);
+    let frame = Frame {
+        name: name,
+        args: args.to_vec(),
+        span: form.and_then(|form| ctx.spans().get(form)),
+    };
+    let backtrace = ctx.error_backtrace.as_mut().unwrap();
+    let at = backtrace.heights.iter().position(|&h| h < height).unwrap_or(backtrace.heights.len());
+    backtrace.frames.insert(at, frame);
+    backtrace.heights.insert(at, height);
+}
+
+fn function_name<S: ?Sized + State>(function: &Value, ctx: &LoadedContext<S>) -> Option<String> {
+    match *function {
+        Value::Lambda(ref procedure, _) => procedure.name.clone(),
+        Value::ForeignFn(ref function) => Some(function.name.clone()),
+        Value::Keyword(_) => Some(ctx.format_value(function)),
+        _ => None,
+    }
+}
@@ -0,0 +1,353 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use {Value, AresError, AresResult};
+use intern::Symbol;
+use parse::Span;
+use util::RcSlice;
+
+use super::{StepState, Environment, Backtrace, cleanup_stack, push_env, push_bodies};
+use super::continuation;
+use super::context::{LoadedContext, State};
+
+/// The kinds of error that a `catch` can be for, as they are written after
+/// the `:`.
+const KINDS: &'static [&'static str] = &["parse",
+                                         "no-program",
+                                         "type",
+                                         "arity",
+                                         "unexecutable",
+                                         "args-list",
+                                         "conversion",
+                                         "undefined-name",
+                                         "invalid-state",
+                                         "unquote",
+                                         "macro-reference",
+                                         "set",
+                                         "ast-function-pass",
+                                         "already-defined",
+                                         "define",
+                                         "unwrap-none",
+                                         "dead-continuation",
+                                         "io",
+                                         "user",
+                                         "thrown"];
+
+/// The `catch` and `finally` clauses of a `try`.
+pub struct Handlers {
+    catches: Vec<Catch>,
+    finally: Option<RcSlice>,
+}
+
+/// `(catch :kind name bodies*)`, or `(catch name bodies*)` for errors of
+/// any kind.
+struct Catch {
+    kind: Option<&'static str>,
+    name: Symbol,
+    bodies: RcSlice,
+}
+
+/// What a `try` gives once its `finally` is done.
+#[derive(Clone)]
+pub enum Outcome {
+    Value(Value),
+    // Taken when it is raised again, which can only happen once even if a
+    // continuation goes back into the `finally`.
+    Error(Rc<RefCell<Option<Failure>>>),
+}
+
+/// An error that is waiting on a `finally`, along with where it happened.
+pub struct Failure {
+    error: AresError,
+    span: Option<Span>,
+    backtrace: Option<Backtrace>,
+}
+
+/// The kind of `error`, or `None` if it can't be caught.
+pub fn kind(error: &AresError) -> Option<&'static str> {
+    let kind = match *error {
+        AresError::ParseError(_) => "parse",
+        AresError::NoProgram => "no-program",
+        AresError::UnexpectedType { .. } => "type",
+        AresError::UnexpectedArity { .. } => "arity",
+        AresError::UnexecutableValue(_) |
+        AresError::ExecuteEmptyList => "unexecutable",
+        AresError::UnexpectedArgsList(_) => "args-list",
+        AresError::IllegalConversion { .. } => "conversion",
+        AresError::UndefinedName(_) => "undefined-name",
+        AresError::InvalidState(_) |
+        AresError::InvalidForeignFunctionState => "invalid-state",
+        AresError::InvalidUnquotation => "unquote",
+        AresError::MacroReference => "macro-reference",
+        AresError::NoNameSet |
+        AresError::NoValueSet => "set",
+        AresError::AstFunctionPass => "ast-function-pass",
+        AresError::AlreadyDefined(_) => "already-defined",
+        AresError::NoNameDefine |
+        AresError::NoValueDefine |
+        AresError::MultiValueDefine => "define",
+        AresError::UnwrapNone => "unwrap-none",
+        AresError::DeadContinuation => "dead-continuation",
+        AresError::IoError { .. } => "io",
+        AresError::UserError(_) => "user",
+        AresError::Thrown(_) => "thrown",
+        // Running into a limit ends the evaluation, and continuations being
+        // called aren't errors.
+        AresError::DepthLimitExceeded(_) |
+        AresError::OutOfFuel |
+        AresError::Timeout |
+        AresError::MemoryLimitExceeded(_) |
+        AresError::Interrupted |
+        AresError::ContinuationCalled(_) => return None,
+    };
+    Some(kind)
+}
+
+/// Starts a `try`, given its arguments: the bodies, then the `catch`
+/// clauses, then maybe a `finally`.
+pub fn start<S: ?Sized>(args: RcSlice, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    let mut body_len = None;
+    let mut catches = vec![];
+    let mut finally = None;
+    for (at, arg) in args.iter().enumerate() {
+        let (name, clause) = match clause(arg, ctx) {
+            Some(clause) => clause,
+            None if body_len.is_none() => continue,
+            None => return Err(malformed(arg, "a catch or finally clause")),
+        };
+        if body_len.is_none() {
+            body_len = Some(at);
+        }
+        if finally.is_some() {
+            return Err(malformed(arg, "nothing after the finally clause"));
+        }
+
+        if name == "finally" {
+            if clause.len() < 2 {
+                return Err(malformed(arg, "(finally bodies+)"));
+            }
+            finally = Some(RcSlice::from_rc(clause.clone()).slice(1, clause.len()));
+            continue;
+        }
+
+        let mut next = 1;
+        let kind = match clause.get(1) {
+            Some(&Value::Keyword(keyword)) => {
+                next += 1;
+                let name = ctx.interner().lookup(keyword);
+                match KINDS.iter().find(|&&kind| Some(kind) == name) {
+                    Some(&kind) => Some(kind),
+                    None => return Err(malformed(&clause[1], "an error kind")),
+                }
+            }
+            _ => None,
+        };
+        let name = match clause.get(next) {
+            Some(&Value::Symbol(name)) if clause.len() > next + 1 => name,
+            _ => return Err(malformed(arg, "(catch :kind? name bodies+)")),
+        };
+        catches.push(Catch {
+            kind: kind,
+            name: name,
+            bodies: RcSlice::from_rc(clause.clone()).slice(next + 1, clause.len()),
+        });
+    }
+
+    let body_len = body_len.unwrap_or(args.len());
+    if body_len == 0 {
+        return Err(AresError::UnexpectedArity {
+            found: 0,
+            expected: "at least 1 body".into(),
+        });
+    }
+    ctx.stack.push(StepState::Try {
+        handlers: Rc::new(Handlers {
+            catches: catches,
+            finally: finally,
+        }),
+        catching: false,
+    });
+    push_bodies(args.slice(0, body_len), ctx);
+    Ok(())
+}
+
+/// Carries on with a `try` now that `value` has been computed.
+pub fn resume<S: ?Sized>(state: StepState, value: Value, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    match state {
+        StepState::Try { handlers, .. } => {
+            match handlers.finally {
+                Some(ref finally) => {
+                    ctx.stack.push(StepState::Finally(Outcome::Value(value)));
+                    push_bodies(finally.clone(), ctx);
+                }
+                None => ctx.stack.push(StepState::Complete(value)),
+            }
+        }
+        // What the `finally` gives is thrown away.
+        StepState::Finally(Outcome::Value(result)) => ctx.stack.push(StepState::Complete(result)),
+        StepState::Finally(Outcome::Error(failure)) => {
+            let failure = match failure.borrow_mut().take() {
+                Some(failure) => failure,
+                None => {
+                    let message = "the error of this finally was already raised".into();
+                    return Err(AresError::InvalidState(message));
+                }
+            };
+            ctx.error_span = failure.span;
+            ctx.error_backtrace = failure.backtrace;
+            return Err(failure.error);
+        }
+        other => panic!("catch::resume(..): not a try: {:?}", other),
+    }
+    Ok(())
+}
+
+/// Passes `error` to the innermost `try` of the evaluation that starts at
+/// `bottom` on the stack that catches it or has a `finally`, or to the
+/// continuation that it is a call to.  Errors that nothing here is for are
+/// given back.
+pub fn unwind<S: ?Sized>(error: AresError, bottom: usize, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    let kind = kind(&error);
+    if kind.is_none() && !continuation::is_jump(&error) {
+        return Err(error);
+    }
+    // A continuation of this evaluation only leaves the `try`s above where
+    // it carries on from.
+    let limit = continuation::destination(&error, bottom, &ctx.stack).unwrap_or(bottom + 1);
+
+    for at in (limit..ctx.stack.len()).rev() {
+        let (handlers, catching) = match ctx.stack[at] {
+            StepState::Try { ref handlers, catching } => (handlers.clone(), catching),
+            _ => continue,
+        };
+        let catch = match kind {
+            Some(kind) if !catching => {
+                handlers.catches.iter().position(|catch| catch.kind.map_or(true, |k| k == kind))
+            }
+            _ => None,
+        };
+        if catch.is_none() && handlers.finally.is_none() {
+            continue;
+        }
+
+        cleanup_stack(at, ctx);
+        match catch {
+            Some(catch) => {
+                ctx.error_span = None;
+                ctx.error_backtrace = None;
+                let value = try!(error_value(error, kind.unwrap(), ctx));
+                ctx.stack.push(StepState::Try {
+                    handlers: handlers.clone(),
+                    catching: true,
+                });
+                let catch = &handlers.catches[catch];
+                let mut bindings = HashMap::new();
+                bindings.insert(catch.name, value);
+                push_env(Environment::new_with_data(ctx.env().clone(), bindings), None, ctx);
+                push_bodies(catch.bodies.clone(), ctx);
+            }
+            None => {
+                let failure = Failure {
+                    error: error,
+                    span: ctx.error_span.take(),
+                    backtrace: ctx.error_backtrace.take(),
+                };
+                let outcome = Outcome::Error(Rc::new(RefCell::new(Some(failure))));
+                ctx.stack.push(StepState::Finally(outcome));
+                push_bodies(handlers.finally.clone().unwrap(), ctx);
+            }
+        }
+        return Ok(());
+    }
+    continuation::resume(error, bottom, ctx)
+}
+
+/// If `value` is a `catch` or `finally` clause, which one it is and the
+/// clause.
+fn clause<S: ?Sized>(value: &Value, ctx: &LoadedContext<S>) -> Option<(&'static str, Rc<Vec<Value>>)>
+    where S: State
+{
+    let clause = match *value {
+        Value::List(ref clause) => clause,
+        _ => return None,
+    };
+    let name = match clause.first() {
+        Some(&Value::Symbol(symbol)) => ctx.interner().lookup(symbol),
+        _ => return None,
+    };
+    match name {
+        Some("catch") => Some(("catch", clause.clone())),
+        Some("finally") => Some(("finally", clause.clone())),
+        _ => None,
+    }
+}
+
+fn malformed(value: &Value, expected: &str) -> AresError {
+    AresError::UnexpectedType {
+        value: value.clone(),
+        expected: expected.into(),
+    }
+}
+
+/// `error` as a map that scripts can look at.  `:kind` is its kind, and the
+/// other keys depend on the kind.
+fn error_value<S: ?Sized>(error: AresError,
+                          kind: &'static str,
+                          ctx: &mut LoadedContext<S>)
+                          -> AresResult<Value>
+    where S: State
+{
+    let mut entries = vec![("kind", Value::Keyword(ctx.interner_mut().intern(kind)))];
+    match error {
+        AresError::ParseError(error) => entries.push(("message", Value::string(error.to_string()))),
+        AresError::UnexpectedType { value, expected } => {
+            entries.push(("value", value));
+            entries.push(("expected", Value::string(expected)));
+        }
+        AresError::UnexpectedArity { found, expected } => {
+            entries.push(("found", Value::Int(found as i64)));
+            entries.push(("expected", Value::string(expected)));
+        }
+        AresError::UnexecutableValue(value) |
+        AresError::UnexpectedArgsList(value) |
+        AresError::Thrown(value) => entries.push(("value", value)),
+        AresError::IllegalConversion { value, into } => {
+            entries.push(("value", value));
+            entries.push(("into", Value::string(into)));
+        }
+        AresError::UndefinedName(name) |
+        AresError::AlreadyDefined(name) => entries.push(("name", Value::string(name))),
+        AresError::InvalidState(message) => entries.push(("message", Value::string(message))),
+        AresError::IoError { path, error } => {
+            entries.push(("path", Value::string(path)));
+            entries.push(("message", Value::string(error.to_string())));
+        }
+        AresError::UserError(data) => entries.push(("data", Value::UserData(Rc::new(data)))),
+        _ => {}
+    }
+    let mut m = HashMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        m.insert(Value::Keyword(ctx.interner_mut().intern(key)), value);
+    }
+    try!(ctx.memory().charge_map(m.len()));
+    Ok(Value::Map(Rc::new(m)))
+}
+
+impl ::std::fmt::Debug for Outcome {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        match *self {
+            Outcome::Value(ref value) => formatter.debug_tuple("Value").field(value).finish(),
+            Outcome::Error(ref failure) => {
+                let error = failure.borrow();
+                formatter.debug_tuple("Error").field(&error.as_ref().map(|f| &f.error)).finish()
+            }
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::rc::Rc;
+
+use Value;
+use intern::Symbol;
+use stdlib::core::param_binding;
+
+use super::{Env, SpecialForm, ParamBinding};
+
+/// The bodies of a lambda, compiled so that they can be run by the VM
+/// instead of being walked a step at a time.
+#[derive(Debug)]
+pub struct Chunk {
+    pub ops: Vec<Op>,
+    /// The calls in the bodies.  Ops refer to them by index.
+    pub forms: Vec<Rc<Vec<Value>>>,
+    /// For each op, the innermost call that it is a part of, so that errors
+    /// can say where they happened.
+    pub op_forms: Vec<u32>,
+    /// The lambdas inside of the bodies.
+    pub lambdas: Vec<Rc<Prototype>>,
+    /// At least as many values and calls in progress as the VM ever has
+    /// while running this, so that it can make room for them up front.
+    pub max_values: usize,
+    pub max_calls: usize,
+}
+
+/// A lambda inside of a compiled lambda, compiled ahead of time so that
+/// making it is cheap.
+#[derive(Debug)]
+pub struct Prototype {
+    pub params: ParamBinding,
+    pub slot_names: Rc<Vec<Symbol>>,
+    pub bodies: Rc<Vec<Value>>,
+    pub code: Rc<Chunk>,
+}
+
+/// What the VM can do.  Jumps are to indices into `Chunk::ops`, and forms
+/// are indices into `Chunk::forms`.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Pushes a value that doesn't need evaluating.
+    Const(Value),
+    /// Pushes the value of a variable.
+    Load(Symbol),
+    /// Pushes the value of a variable in a slot of the environment `depth`
+    /// levels up, looking it up by name if the slot can't be used.  `head`
+    /// is whether it is about to be called, like `LoadHead`.
+    LoadSlot { depth: u32, slot: u32, name: Symbol, head: bool },
+    /// Pushes the value of a variable that is about to be called, which can
+    /// be an ast function.
+    LoadHead(Symbol),
+    /// Evaluates a value with the step evaluator.
+    Eval(Value),
+    /// Checks the function that was just pushed.  Functions that take their
+    /// arguments unevaluated are called on `form` straight away, carrying on
+    /// at `end`; for everything else, the arguments are pushed next.
+    Callee { form: u32, end: u32, tail: bool },
+    /// Calls the function under the top `argc` values.
+    Call { argc: u32, tail: bool },
+    /// Pops the function that was just pushed.  If it is `special`, the
+    /// compiled version of the form follows.  Otherwise, `form` is
+    /// evaluated as a call to whatever it is, carrying on at `end`.
+    Special { special: SpecialForm, form: u32, end: u32, tail: bool },
+    /// Pops a condition, jumping if it is false.
+    JumpIfFalse(u32),
+    Jump(u32),
+    /// Pops an operand of `and` or `or`.  If it is `on`, the result is `on`
+    /// and execution jumps to the end of the form.
+    ShortCircuit { on: bool, to: u32 },
+    Pop,
+    /// Starts the environment of a `let`, with slots for the names.
+    EnterLet(Rc<Vec<Symbol>>),
+    /// Pops a value and binds it in a slot of the innermost environment.
+    Bind(u32),
+    /// Ends the environment of a `let`.
+    ExitLet,
+    /// Makes one of `Chunk::lambdas`, closing over the current environment.
+    Closure(u32),
+    /// Pops the value of the call to the lambda.
+    Return,
+}
+
+/// Used for ops that aren't part of any call.
+pub const NO_FORM: u32 = !0;
+
+/// How deep forms are compiled.  Anything deeper is left to the step
+/// evaluator, which doesn't need the native stack.
+const MAX_COMPILE_DEPTH: usize = 128;
+
+/// Compiles the bodies of a lambda whose parameters are in slots named
+/// `slot_names`.  Calls to the special forms that `env` has at the moment get
+/// compiled versions, which check that they are still calling the same thing
+/// when they run.  `dot` is the symbol that comes before rest parameters.
+pub fn compile(bodies: &[Value], slot_names: &Rc<Vec<Symbol>>, env: &Env, dot: Symbol) -> Rc<Chunk> {
+    compile_nested(bodies, vec![slot_names.clone()], env, dot, 0)
+}
+
+fn compile_nested(bodies: &[Value],
+                  scopes: Vec<Rc<Vec<Symbol>>>,
+                  env: &Env,
+                  dot: Symbol,
+                  depth: usize)
+                  -> Rc<Chunk> {
+    let mut compiler = Compiler {
+        env: env,
+        dot: dot,
+        scopes: scopes,
+        chunk: Chunk {
+            ops: vec![],
+            forms: vec![],
+            op_forms: vec![],
+            lambdas: vec![],
+            max_values: 0,
+            max_calls: 0,
+        },
+        values: 0,
+        calls: 0,
+        form: NO_FORM,
+        depth: depth,
+    };
+    compiler.bodies(bodies, true);
+    compiler.emit(Op::Return);
+    Rc::new(compiler.chunk)
+}
+
+struct Compiler<'a> {
+    env: &'a Env,
+    dot: Symbol,
+    // The slot names of the environments that the code runs in, innermost
+    // last.  Anything further out is looked up by name.
+    scopes: Vec<Rc<Vec<Symbol>>>,
+    chunk: Chunk,
+    // The innermost call being compiled.
+    form: u32,
+    // How many values and calls in progress there are after the last op.
+    // Both branches of an `if` are counted, so this can be too many, but
+    // never too few.
+    values: usize,
+    calls: usize,
+    depth: usize,
+}
+
+impl <'a> Compiler<'a> {
+    fn emit(&mut self, op: Op) -> usize {
+        match op {
+            Op::Const(_) | Op::Load(_) | Op::LoadHead(_) | Op::LoadSlot { .. } | Op::Eval(_) |
+            Op::Closure(_) => self.values += 1,
+            Op::Callee { .. } => self.calls += 1,
+            Op::Call { argc, .. } => {
+                self.values -= argc as usize;
+                self.calls -= 1;
+            }
+            Op::Special { .. } | Op::JumpIfFalse(_) | Op::ShortCircuit { .. } | Op::Pop |
+            Op::Bind(_) | Op::Return => self.values -= 1,
+            Op::Jump(_) | Op::EnterLet(_) | Op::ExitLet => {}
+        }
+        self.chunk.max_values = ::std::cmp::max(self.chunk.max_values, self.values);
+        self.chunk.max_calls = ::std::cmp::max(self.chunk.max_calls, self.calls);
+        self.chunk.ops.push(op);
+        self.chunk.op_forms.push(self.form);
+        self.chunk.ops.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.ops.len() as u32
+    }
+
+    /// Points the jump at `at` to the next op.
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+        match self.chunk.ops[at] {
+            Op::Callee { ref mut end, .. } |
+            Op::Special { ref mut end, .. } => *end = here,
+            Op::JumpIfFalse(ref mut to) |
+            Op::Jump(ref mut to) |
+            Op::ShortCircuit { ref mut to, .. } => *to = here,
+            ref other => panic!("patch(..): {:?} doesn't jump", other),
+        }
+    }
+
+    /// Compiles `bodies` one after the other, leaving the value of the last.
+    fn bodies(&mut self, bodies: &[Value], tail: bool) {
+        for (i, body) in bodies.iter().enumerate() {
+            let last = i + 1 == bodies.len();
+            self.expr(body, tail && last);
+            if !last {
+                self.emit(Op::Pop);
+            }
+        }
+    }
+
+    fn expr(&mut self, value: &Value, tail: bool) {
+        match *value {
+            Value::Symbol(symbol) => {
+                self.load(symbol, false);
+            }
+            Value::List(ref form) if !form.is_empty() && self.depth < MAX_COMPILE_DEPTH => {
+                self.depth += 1;
+                let outer = self.form;
+                self.form = self.chunk.forms.len() as u32;
+                self.chunk.forms.push(form.clone());
+                self.call(form, tail);
+                self.form = outer;
+                self.depth -= 1;
+            }
+            // Empty lists and macros are errors, which the step evaluator
+            // reports.
+            Value::List(_) | Value::Lambda(_, true) => {
+                self.emit(Op::Eval(value.clone()));
+            }
+            ref other => {
+                self.emit(Op::Const(other.clone()));
+            }
+        }
+    }
+
+    fn call(&mut self, form: &Rc<Vec<Value>>, tail: bool) {
+        let index = self.form;
+        let args = &form[1..];
+        let special = match form[0] {
+            Value::Symbol(symbol) => {
+                self.load(symbol, true);
+                self.special_form(symbol)
+            }
+            ref head => {
+                self.expr(head, false);
+                None
+            }
+        };
+
+        if let Some(special) = special {
+            if self.can_compile(special, args) {
+                let start = self.emit(Op::Special {
+                    special: special,
+                    form: index,
+                    end: 0,
+                    tail: tail,
+                });
+                self.special(special, args, tail);
+                self.patch(start);
+                return;
+            }
+        }
+
+        let callee = self.emit(Op::Callee {
+            form: index,
+            end: 0,
+            tail: tail,
+        });
+        for arg in args {
+            self.expr(arg, false);
+        }
+        self.emit(Op::Call {
+            argc: args.len() as u32,
+            tail: tail,
+        });
+        self.patch(callee);
+    }
+
+    fn load(&mut self, symbol: Symbol, head: bool) {
+        let op = match self.resolve(symbol) {
+            Some((depth, slot)) => {
+                Op::LoadSlot {
+                    depth: depth,
+                    slot: slot,
+                    name: symbol,
+                    head: head,
+                }
+            }
+            None if head => Op::LoadHead(symbol),
+            None => Op::Load(symbol),
+        };
+        self.emit(op);
+    }
+
+    /// Where the slot for `symbol` is, if it is in one.
+    fn resolve(&self, symbol: Symbol) -> Option<(u32, u32)> {
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .filter_map(|(depth, names)| {
+                names.iter().rposition(|&name| name == symbol).map(|slot| (depth as u32, slot as u32))
+            })
+            .next()
+    }
+
+    /// The special form that `symbol` is at the moment, if any.
+    fn special_form(&self, symbol: Symbol) -> Option<SpecialForm> {
+        match self.env.borrow().get(symbol) {
+            Some(Value::ForeignFn(f)) => f.special,
+            _ => None,
+        }
+    }
+
+    /// Whether there is a compiled version of `special` called with `args`.
+    /// Forms that are used wrongly are left to the special form to report.
+    fn can_compile(&self, special: SpecialForm, args: &[Value]) -> bool {
+        match special {
+            SpecialForm::If => args.len() == 3,
+            SpecialForm::Let => {
+                args.len() >= 2 &&
+                match args[0] {
+                    Value::List(ref bindings) => {
+                        bindings.len() % 2 == 0 &&
+                        bindings.iter().step_by(2).all(|name| match *name {
+                            Value::Symbol(_) => true,
+                            _ => false,
+                        })
+                    }
+                    _ => false,
+                }
+            }
+            SpecialForm::And | SpecialForm::Or => true,
+            SpecialForm::Quote => args.len() == 1,
+            SpecialForm::Lambda => args.len() >= 2 && param_binding(&args[0], self.dot).is_ok(),
+            _ => false,
+        }
+    }
+
+    fn special(&mut self, special: SpecialForm, args: &[Value], tail: bool) {
+        match special {
+            SpecialForm::If => {
+                self.expr(&args[0], false);
+                let otherwise = self.emit(Op::JumpIfFalse(0));
+                self.expr(&args[1], tail);
+                let end = self.emit(Op::Jump(0));
+                self.patch(otherwise);
+                self.expr(&args[2], tail);
+                self.patch(end);
+            }
+            SpecialForm::Let => {
+                let bindings = match args[0] {
+                    Value::List(ref bindings) => bindings,
+                    _ => unreachable!(),
+                };
+                let mut names = vec![];
+                for name in bindings.iter().step_by(2) {
+                    if let Value::Symbol(name) = *name {
+                        if !names.contains(&name) {
+                            names.push(name);
+                        }
+                    }
+                }
+                let names = Rc::new(names);
+                self.emit(Op::EnterLet(names.clone()));
+                // The values can see the names that are bound before them,
+                // and lambdas in them can see all of them, so the slots are
+                // in scope straight away.  Slots that aren't bound yet are
+                // skipped over at runtime.
+                self.scopes.push(names.clone());
+                for pair in bindings.chunks(2) {
+                    self.expr(&pair[1], false);
+                    if let Value::Symbol(name) = pair[0] {
+                        let slot = names.iter().position(|&n| n == name).unwrap();
+                        self.emit(Op::Bind(slot as u32));
+                    }
+                }
+                // Calls in tail position leave the environment themselves.
+                self.bodies(&args[1..], tail);
+                self.scopes.pop();
+                self.emit(Op::ExitLet);
+            }
+            SpecialForm::And | SpecialForm::Or => {
+                let on = special == SpecialForm::Or;
+                let mut jumps = vec![];
+                for arg in args {
+                    self.expr(arg, false);
+                    jumps.push(self.emit(Op::ShortCircuit { on: on, to: 0 }));
+                }
+                self.emit(Op::Const(Value::Bool(!on)));
+                for jump in jumps {
+                    self.patch(jump);
+                }
+            }
+            SpecialForm::Quote => {
+                self.emit(Op::Const(args[0].clone()));
+            }
+            SpecialForm::Lambda => {
+                let bodies: Vec<Value> = args[1..].to_vec();
+                let params = param_binding(&args[0], self.dot).unwrap();
+                let slot_names = Rc::new(params.slot_names());
+                let mut scopes = self.scopes.clone();
+                scopes.push(slot_names.clone());
+                let prototype = Prototype {
+                    params: params,
+                    code: compile_nested(&bodies, scopes, self.env, self.dot, self.depth),
+                    slot_names: slot_names,
+                    bodies: Rc::new(bodies),
+                };
+                self.chunk.lambdas.push(Rc::new(prototype));
+                let index = self.chunk.lambdas.len() as u32 - 1;
+                self.emit(Op::Closure(index));
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -3,26 +3,83 @@ use std::cell::RefCell;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::any::Any;
+use std::io::Read;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use super::{Env, eval, apply, StepState};
-use {Value, AresResult, AresError, parse, stdlib, Environment, ForeignFunction};
+use super::{Env, eval, apply, StepState, Execution, MemoryAccount, MemoryStats,
+            InterruptHandle, Backtrace, InTailPosition};
+use super::vm::Activation;
+use super::continuation::is_jump;
+use {Value, AresResult, AresError, stdlib, Environment, ForeignFunction};
+use parse::{parse_with_macros, parse_source, Span, SpanTable, Reader, ReadResult, ReaderMacros,
+            SourceNames, DEFAULT_MAX_NESTING};
 use intern::SymbolIntern;
 use stdlib::core::macroexpand;
 
 pub struct Context<S: State + ?Sized> {
     env: Env,
     interner: SymbolIntern,
+    spans: SpanTable,
+    source_names: SourceNames,
+    reader_macros: ReaderMacros,
+    max_depth: usize,
+    max_native_depth: usize,
+    max_nesting: usize,
+    compile_lambdas: bool,
+    memory: MemoryAccount,
+    interrupt: InterruptHandle,
     _state: PhantomData<S>,
 }
 
 pub struct LoadedContext<'a, S: State + ?Sized> {
     ctx: &'a mut Context<S>,
-    state: &'a mut S,
+    state: Option<&'a mut S>,
 
     pub env_stack: Vec<Env>,
     pub stack: Vec<StepState>,
+
+    pub(crate) error_span: Option<Span>,
+    pub(crate) error_backtrace: Option<Backtrace>,
+
+    // The files that are being evaluated, innermost last.
+    loading: Vec<PathBuf>,
+
+    // Argument lists that calls are done with, to be used again instead of
+    // allocating new ones.
+    arg_buffers: Vec<Vec<Value>>,
+    // The same for compiled lambdas that have returned.
+    pub(crate) activations: Vec<Box<Activation>>,
+
+    // How many calls to `eval` and `apply` are running inside of each other.
+    pub(crate) native_depth: usize,
+
+    // Whether the host is in the middle of evaluating something, such as a
+    // call to `eval_str` or a run of an `Execution`.
+    running: bool,
+
+    // How many more steps can be taken, if that is limited.
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    // Steps taken since the deadline was last checked.
+    unchecked_steps: u32,
 }
 
+/// How many steps are taken between checks of the deadline.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// The default for `Context::max_depth`.
+const DEFAULT_MAX_DEPTH: usize = 1000000;
+
+/// The default for `Context::max_native_depth`.
+const DEFAULT_MAX_NATIVE_DEPTH: usize = 64;
+
+/// How many argument lists are kept around to be used again, and how big
+/// they can be.
+const MAX_ARG_BUFFERS: usize = 16;
+const MAX_ARG_BUFFER_CAPACITY: usize = 64;
+
 pub trait State: Any {}
 
 impl <T: Any> State for T {}
@@ -33,6 +90,15 @@ impl <S: State + ?Sized> Context<S> {
         let mut ctx = Context {
             env: env,
             interner: SymbolIntern::new(),
+            spans: SpanTable::new(),
+            source_names: SourceNames::new(),
+            reader_macros: ReaderMacros::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_native_depth: DEFAULT_MAX_NATIVE_DEPTH,
+            max_nesting: DEFAULT_MAX_NESTING,
+            compile_lambdas: true,
+            memory: MemoryAccount::new(),
+            interrupt: InterruptHandle::new(),
             _state: PhantomData,
         };
         stdlib::load_all(&mut ctx);
@@ -43,6 +109,15 @@ impl <S: State + ?Sized> Context<S> {
         Context {
             env: Rc::new(RefCell::new(Environment::new())),
             interner: SymbolIntern::new(),
+            spans: SpanTable::new(),
+            source_names: SourceNames::new(),
+            reader_macros: ReaderMacros::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_native_depth: DEFAULT_MAX_NATIVE_DEPTH,
+            max_nesting: DEFAULT_MAX_NESTING,
+            compile_lambdas: true,
+            memory: MemoryAccount::new(),
+            interrupt: InterruptHandle::new(),
             _state: PhantomData,
         }
     }
@@ -52,16 +127,81 @@ impl <S: State + ?Sized> Context<S> {
         self
     }
 
+    /// Lets scripts call `memory-stats`.
+    pub fn with_memory_stats(mut self) -> Context<S> {
+        stdlib::load_memory(&mut self);
+        self
+    }
+
+    /// Lets scripts call `load`, which reads and evaluates any file that
+    /// the process can read.  Leave this off for scripts that aren't
+    /// trusted.
+    pub fn with_file_loading(mut self) -> Context<S> {
+        stdlib::load_files(&mut self);
+        self
+    }
+
     pub fn format_value(&self, value: &Value) -> String {
         ::stdlib::types::to_string_helper(value, self.interner())
     }
 
+    /// Formats a backtrace with one call per line, innermost first.
+    pub fn format_backtrace(&self, backtrace: &Backtrace) -> String {
+        let mut lines = Vec::with_capacity(backtrace.frames.len());
+        for frame in &backtrace.frames {
+            let mut line = format!("  in ({}", frame.name.as_ref().map_or("<lambda>", |n| &n[..]));
+            for arg in &frame.args {
+                line.push(' ');
+                line.push_str(&self.format_value(arg));
+            }
+            line.push(')');
+            if let Some(span) = frame.span {
+                line.push_str(&format!(" at {}", span));
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
     pub fn load<'a>(&'a mut self, state: &'a mut S) -> LoadedContext<'a, S> {
         LoadedContext {
             ctx: self,
-            state: state,
+            state: Some(state),
+            env_stack: vec![],
+            stack: vec![],
+            error_span: None,
+            error_backtrace: None,
+            loading: vec![],
+            arg_buffers: vec![],
+            activations: vec![],
+            native_depth: 0,
+            running: false,
+            fuel: None,
+            deadline: None,
+            unchecked_steps: 0,
+        }
+    }
+
+    /// Loads the context without any state.  Used for evaluating the parts of
+    /// the standard library that are written in Ares; calling `state()` on the
+    /// result will panic.
+    #[doc(hidden)]
+    pub fn load_stateless<'a>(&'a mut self) -> LoadedContext<'a, S> {
+        LoadedContext {
+            ctx: self,
+            state: None,
             env_stack: vec![],
             stack: vec![],
+            error_span: None,
+            error_backtrace: None,
+            loading: vec![],
+            arg_buffers: vec![],
+            activations: vec![],
+            native_depth: 0,
+            running: false,
+            fuel: None,
+            deadline: None,
+            unchecked_steps: 0,
         }
     }
 
@@ -82,7 +222,96 @@ impl <S: State + ?Sized> Context<S> {
                                                 name: N,
                                                 f: ForeignFunction<S>)
                                                 -> Option<Value> {
-        self.set(name, Value::ForeignFn(f.erase()))
+        self.set(name, Value::foreign_fn(f))
+    }
+
+    /// Registers a reader macro, so that `#tag form` is read as whatever
+    /// `f` returns when given `form`.
+    pub fn set_reader_macro<N, F>(&mut self, tag: N, f: F)
+        where N: Into<String>,
+              F: Fn(Value, &mut SymbolIntern) -> Result<Value, String> + 'static
+    {
+        self.reader_macros.insert(tag, f);
+    }
+
+    pub fn reader_macros(&self) -> &ReaderMacros {
+        &self.reader_macros
+    }
+
+    pub fn reader_macros_mut(&mut self) -> &mut ReaderMacros {
+        &mut self.reader_macros
+    }
+
+    /// How many steps of evaluation can be waiting on each other before
+    /// evaluation fails with `AresError::DepthLimitExceeded`.  Calls in tail
+    /// position don't count.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// How many times evaluation can re-enter itself from Rust, like when a
+    /// foreign function calls `eval` or `call`, before failing with
+    /// `AresError::DepthLimitExceeded`.  Each of these uses up the native
+    /// stack, so keep this low enough for the thread that evaluates.  The
+    /// calls that `apply` and `for-each` make, and so `map`, `filter` and
+    /// `fold-left`, don't count.
+    pub fn max_native_depth(&self) -> usize {
+        self.max_native_depth
+    }
+
+    pub fn set_max_native_depth(&mut self, depth: usize) {
+        self.max_native_depth = depth;
+    }
+
+    /// How deeply forms read by this context can be nested inside of each
+    /// other before reading fails with `ParseError::TooDeeplyNested`.
+    pub fn max_nesting(&self) -> usize {
+        self.max_nesting
+    }
+
+    pub fn set_max_nesting(&mut self, depth: usize) {
+        self.max_nesting = depth;
+    }
+
+    /// Whether lambdas are compiled to bytecode when they are made.  Lambdas
+    /// that aren't compiled are walked a step at a time, which is slower
+    /// but can be handy for comparison.  On by default.
+    pub fn compile_lambdas(&self) -> bool {
+        self.compile_lambdas
+    }
+
+    pub fn set_compile_lambdas(&mut self, compile: bool) {
+        self.compile_lambdas = compile;
+    }
+
+    /// The memory allocated by scripts in this context.  Foreign functions
+    /// that make big values can account for them here.
+    pub fn memory(&self) -> &MemoryAccount {
+        &self.memory
+    }
+
+    /// A handle that can stop evaluation in this context from any thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.memory.stats()
+    }
+
+    /// Limits how many bytes scripts can allocate before evaluation fails
+    /// with `AresError::MemoryLimitExceeded`.  Allocations are counted until
+    /// `reset_memory_stats` is called.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.set_limit(limit);
+    }
+
+    pub fn reset_memory_stats(&mut self) {
+        self.memory.reset();
     }
 
     pub fn env(&self) -> &Env {
@@ -100,6 +329,25 @@ impl <S: State + ?Sized> Context<S> {
     pub fn interner_mut(&mut self) -> &mut SymbolIntern {
         &mut self.interner
     }
+
+    /// The source locations of every list parsed by this context.
+    pub fn spans(&self) -> &SpanTable {
+        &self.spans
+    }
+
+    pub fn spans_mut(&mut self) -> &mut SpanTable {
+        &mut self.spans
+    }
+
+    /// The names of the files that this context has read, which the sources
+    /// of its positions can be looked up in.
+    pub fn source_names(&self) -> &SourceNames {
+        &self.source_names
+    }
+
+    pub fn source_names_mut(&mut self) -> &mut SourceNames {
+        &mut self.source_names
+    }
 }
 
 impl <'a, S: State + ?Sized> LoadedContext<'a, S> {
@@ -111,18 +359,57 @@ impl <'a, S: State + ?Sized> LoadedContext<'a, S> {
         (self.env_stack.pop().unwrap(), r)
     }
 
+    /// Finishes the running foreign function by evaluating `bodies` in place
+    /// of the call to it, in `env` if one is given.  The value of the call is
+    /// the value of the last body, which is evaluated in tail position.
+    ///
+    /// Return what this returns from the function straight away; the bodies
+    /// aren't evaluated until the function has returned, and aren't
+    /// evaluated at all if the function returns anything else.
+    pub fn eval_in_tail_position(&mut self,
+                                 bodies: Vec<Value>,
+                                 env: Option<Env>)
+                                 -> AresResult<Value> {
+        if bodies.is_empty() {
+            return Err(AresError::InvalidState("nothing to evaluate in tail position".into()));
+        }
+        Ok(Value::user_data(InTailPosition(RefCell::new(Some((bodies, env))))))
+    }
+
     pub fn with_other_state<F, R>(&mut self, state: &mut S, f: F) -> R
         where F: FnOnce(&mut LoadedContext<'a, S>) -> R
     {
         use std::mem::{swap, transmute};
         // This is safe because the state gets immeditately swapped back out.
-        let mut state: &'a mut S = unsafe { transmute(state) };
+        let mut state: Option<&'a mut S> = Some(unsafe { transmute(state) });
         swap(&mut self.state, &mut state);
         let r = f(self);
         swap(&mut self.state, &mut state);
         r
     }
 
+    /// An empty list to collect arguments in.
+    #[doc(hidden)]
+    pub fn arg_buffer(&mut self, capacity: usize) -> Vec<Value> {
+        match self.arg_buffers.pop() {
+            Some(mut buffer) => {
+                buffer.reserve(capacity);
+                buffer
+            }
+            None => Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Gives back arguments that aren't needed anymore, so that their list
+    /// can be used by `arg_buffer`.
+    #[doc(hidden)]
+    pub fn recycle_args(&mut self, mut args: Vec<Value>) {
+        if self.arg_buffers.len() < MAX_ARG_BUFFERS && args.capacity() <= MAX_ARG_BUFFER_CAPACITY {
+            args.clear();
+            self.arg_buffers.push(args);
+        }
+    }
+
     pub fn env(&self) -> &Env {
         let &LoadedContext { ref ctx, ref env_stack, ..} = self;
         env_stack.last().unwrap_or(&ctx.env)
@@ -133,24 +420,231 @@ impl <'a, S: State + ?Sized> LoadedContext<'a, S> {
         env_stack.last_mut().unwrap_or(&mut ctx.env)
     }
 
+    /// How many more steps of evaluation can be taken before evaluation
+    /// fails with `AresError::OutOfFuel`, or `None` if there is no limit,
+    /// which is the default.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Allows `fuel` more steps to be taken.  Does nothing if there is no
+    /// limit.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(ref mut remaining) = self.fuel {
+            *remaining = remaining.saturating_add(fuel);
+        }
+    }
+
+    /// When evaluation should fail with `AresError::Timeout`, if ever.
+    ///
+    /// The deadline is only checked every so often, so evaluation can run a
+    /// little past it.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// How many evaluations are running on the native stack, inside of each
+    /// other, at the moment.  This is what `max_native_depth` limits.
+    pub fn native_depth(&self) -> usize {
+        self.native_depth
+    }
+
+    /// Uses up the fuel for one step, failing if there is none left, the
+    /// deadline has passed or the context has been interrupted.
+    #[doc(hidden)]
+    pub fn use_fuel(&mut self) -> AresResult<()> {
+        if self.ctx.interrupt.is_interrupted() {
+            self.ctx.interrupt.clear();
+            return Err(AresError::Interrupted);
+        }
+        if let Some(ref mut fuel) = self.fuel {
+            if *fuel == 0 {
+                return Err(AresError::OutOfFuel);
+            }
+            *fuel -= 1;
+        }
+        if let Some(deadline) = self.deadline {
+            self.unchecked_steps += 1;
+            if self.unchecked_steps >= DEADLINE_CHECK_INTERVAL {
+                self.unchecked_steps = 0;
+                if Instant::now() >= deadline {
+                    return Err(AresError::Timeout);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where in the source the last error given back by this context
+    /// happened, if that is known.  The next evaluation forgets it, so read
+    /// it before evaluating anything else.
+    pub fn error_span(&self) -> Option<Span> {
+        self.error_span
+    }
+
+    /// The calls that were running when the last error raised by this
+    /// context happened.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.error_backtrace.as_ref()
+    }
+
     pub fn state(&mut self) -> &mut S {
-        self.state
+        match self.state {
+            Some(ref mut state) => &mut **state,
+            None => panic!("state(): this context was loaded without a state"),
+        }
+    }
+
+    /// Runs `f`, which evaluates something for the host.  Interrupts that
+    /// came in before the outermost of these started are forgotten; ones
+    /// that come in while it runs, even between top-level forms, stop it.
+    #[doc(hidden)]
+    pub fn run_for_host<F, T>(&mut self, clear_interrupt: bool, f: F) -> AresResult<T>
+        where F: FnOnce(&mut LoadedContext<'a, S>) -> AresResult<T>
+    {
+        if self.running {
+            return f(self);
+        }
+        if clear_interrupt {
+            self.ctx.interrupt.clear();
+        }
+        self.running = true;
+        self.error_span = None;
+        let result = f(self);
+        self.running = false;
+        if result.is_ok() {
+            self.error_span = None;
+        }
+        result
     }
 
     pub fn eval(&mut self, value: &Value) -> AresResult<Value> {
-        eval(value, self)
+        self.run_for_host(true, |ctx| eval(value, ctx))
     }
 
     pub fn macroexpand(&mut self, value: Value) -> AresResult<Value> {
         macroexpand(&[value], self)
     }
 
+    /// Expands the macros in `value` and returns an execution that
+    /// evaluates it a few steps at a time.  No steps are taken yet.
+    pub fn start(&mut self, value: Value) -> AresResult<Execution> {
+        let expanded = try!(self.run_for_host(true, |ctx| ctx.macroexpand(value)));
+        Ok(Execution::new(expanded))
+    }
+
     pub fn eval_str(&mut self, program: &str) -> AresResult<Value> {
-        let trees = try!(parse(program, &mut self.interner));
+        self.run_for_host(true, |ctx| ctx.eval_str_running(program))
+    }
+
+    fn eval_str_running(&mut self, program: &str) -> AresResult<Value> {
+        self.error_backtrace = None;
+        let trees = {
+            let ctx = &mut *self.ctx;
+            try!(parse_with_macros(program,
+                                   &mut ctx.interner,
+                                   Some(&mut ctx.spans),
+                                   &ctx.reader_macros,
+                                   ctx.max_nesting))
+        };
+        self.eval_forms(trees)
+    }
+
+    /// Reads and evaluates the file at `path`, returning the value of the
+    /// last form in it.  Positions in errors say what file they are in.
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> AresResult<Value> {
+        let path = path.as_ref();
+        let file = try!(File::open(path).map_err(|e| io_error(path, e)));
+        self.eval_reader(file, path)
+    }
+
+    /// Reads all of `reader` and evaluates it as if it were the file at
+    /// `path`.
+    pub fn eval_reader<R: Read, P: AsRef<Path>>(&mut self,
+                                                reader: R,
+                                                path: P)
+                                                -> AresResult<Value> {
+        let path = path.as_ref();
+        self.run_for_host(true, |ctx| ctx.eval_reader_running(reader, path))
+    }
+
+    fn eval_reader_running<R: Read>(&mut self, mut reader: R, path: &Path) -> AresResult<Value> {
+        let mut program = String::new();
+        try!(reader.read_to_string(&mut program).map_err(|e| io_error(path, e)));
+
+        self.error_backtrace = None;
+        let trees = {
+            let ctx = &mut *self.ctx;
+            let source = ctx.source_names.named(path.to_string_lossy());
+            try!(parse_source(&program,
+                              source,
+                              &mut ctx.interner,
+                              Some(&mut ctx.spans),
+                              &ctx.reader_macros,
+                              ctx.max_nesting))
+        };
+        self.loading.push(path.to_path_buf());
+        let result = self.eval_forms(trees);
+        self.loading.pop();
+        result
+    }
+
+    /// A message for `error`, which this context just gave back, that says
+    /// which file and where in it the error happened, if that is known.
+    pub fn describe_error(&self, error: &AresError) -> String {
+        match (error, self.error_span) {
+            (&AresError::ParseError(ref e), _) => e.describe(&self.source_names),
+            (_, Some(span)) => format!("{:?} at {}", error, self.source_names.describe(span.start)),
+            (_, None) => format!("{:?}", error),
+        }
+    }
+
+    /// The file that is being evaluated by `eval_file` or `eval_reader`, if
+    /// any.  Nested loads give the innermost file.
+    pub fn current_file(&self) -> Option<&Path> {
+        self.loading.last().map(|path| &**path)
+    }
+
+    /// Reads every complete form out of `reader`, interning symbols and
+    /// recording spans in this context.  Pass the forms to `eval_forms`.
+    pub fn read(&mut self, reader: &mut Reader) -> ReadResult {
+        let ctx = &mut *self.ctx;
+        reader.read_with_macros(&mut ctx.interner,
+                                Some(&mut ctx.spans),
+                                &ctx.reader_macros,
+                                ctx.max_nesting)
+    }
+
+    /// Macroexpands and evaluates each form in turn, returning the value of
+    /// the last one.
+    pub fn eval_forms(&mut self, forms: Vec<(Value, Span)>) -> AresResult<Value> {
+        self.run_for_host(true, |ctx| ctx.eval_forms_running(forms))
+    }
+
+    fn eval_forms_running(&mut self, forms: Vec<(Value, Span)>) -> AresResult<Value> {
+        self.error_backtrace = None;
         let mut last = None;
-        for tree in trees {
-            let tree = try!(self.macroexpand(tree));
-            last = Some(try!(self.eval(&tree)))
+        for (tree, span) in forms {
+            let result = self.macroexpand(tree).and_then(|tree| self.eval(&tree));
+            match result {
+                Ok(v) => last = Some(v),
+                Err(e) => {
+                    // Errors that didn't happen inside of any list (like an
+                    // undefined top-level symbol) still get the top-level span.
+                    if self.error_span.is_none() && !is_jump(&e) {
+                        self.error_span = Some(span);
+                    }
+                    return Err(e);
+                }
+            }
         }
         match last {
             Some(v) => Ok(v),
@@ -161,7 +655,7 @@ impl <'a, S: State + ?Sized> LoadedContext<'a, S> {
     pub fn call(&mut self, func: &Value, args: &[Value]) -> AresResult<Value> {
         // FIXME
         let args: Vec<_> = args.iter().cloned().collect();
-        apply(func, args, self)
+        self.run_for_host(true, |ctx| apply(func, args, ctx))
     }
 
     pub fn call_named<N: ?Sized + AsRef<str>>(&mut self,
@@ -191,3 +685,10 @@ impl <'a, S: State + ?Sized> DerefMut for LoadedContext<'a, S> {
         &mut self.ctx
     }
 }
+
+fn io_error(path: &Path, error: ::std::io::Error) -> AresError {
+    AresError::IoError {
+        path: path.to_string_lossy().into_owned(),
+        error: error,
+    }
+}
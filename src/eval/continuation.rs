@@ -0,0 +1,166 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {Value, AresError, AresResult};
+use stdlib::util::expect_arity;
+
+use super::{Env, StepState, user_fn, cleanup_stack};
+use super::context::{LoadedContext, State};
+
+/// An id that hasn't been given out before, for an evaluation or for the
+/// place that an escape continuation goes back to.
+pub fn fresh_id() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Where a continuation carries on from.
+#[derive(Clone)]
+enum Target {
+    /// A copy of the states and environments of the evaluation.
+    Snapshot(Rc<(Vec<StepState>, Vec<Env>)>),
+    /// The `StepState::Escape` with this id, which is still on the stack.
+    Escape(usize),
+}
+
+/// A continuation that was called, on its way to the evaluation that it
+/// continues.
+pub struct Jump {
+    evaluation: usize,
+    target: Target,
+    value: Value,
+}
+
+/// Makes a continuation of the evaluation that is going on.  `escape`
+/// makes one that only works until the value of the `call/ec` is complete,
+/// which doesn't need to copy the stack.
+///
+/// Continuations only go as far as the innermost evaluation, so the rest of
+/// a foreign function that this is being evaluated for isn't part of one.
+pub fn capture<S: State + ?Sized>(escape: bool, ctx: &mut LoadedContext<S>) -> Value {
+    let (bottom, evaluation) = ctx.stack
+        .iter()
+        .enumerate()
+        .rev()
+        .filter_map(|(at, state)| match *state {
+            StepState::Return(id) => Some((at, id)),
+            _ => None,
+        })
+        .next()
+        .expect("capture(..): not evaluating anything");
+
+    let target = if escape {
+        let id = fresh_id();
+        ctx.stack.push(StepState::Escape(id));
+        Target::Escape(id)
+    } else {
+        let stack = ctx.stack[bottom + 1..].to_vec();
+        let envs = stack.iter().map(StepState::envs).sum::<usize>();
+        let env_stack = ctx.env_stack[ctx.env_stack.len() - envs..].to_vec();
+        Target::Snapshot(Rc::new((stack, env_stack)))
+    };
+    let continuation = move |args: &[Value], ctx: &mut LoadedContext<S>| -> AresResult<Value> {
+        try!(expect_arity(args, |l| l == 1, "exactly 1"));
+        if find(evaluation, &target, &ctx.stack).is_none() {
+            return Err(AresError::DeadContinuation);
+        }
+        Err(AresError::ContinuationCalled(Jump {
+            evaluation: evaluation,
+            target: target.clone(),
+            value: args[0].clone(),
+        }))
+    };
+    Value::foreign_fn(user_fn::<S, _, _>("continuation", continuation))
+}
+
+/// Carries on from the continuation that `error` is a call to, if it
+/// continues the evaluation that starts at `bottom` on the stack.  Any other
+/// error is given back.
+pub fn resume<S: State + ?Sized>(error: AresError,
+                                 bottom: usize,
+                                 ctx: &mut LoadedContext<S>)
+                                 -> AresResult<()> {
+    let jump = match error {
+        AresError::ContinuationCalled(jump) => {
+            match ctx.stack.get(bottom) {
+                Some(&StepState::Return(id)) if id == jump.evaluation => jump,
+                _ => return Err(AresError::ContinuationCalled(jump)),
+            }
+        }
+        other => return Err(other),
+    };
+    let to = match find(jump.evaluation, &jump.target, &ctx.stack) {
+        Some(to) => to,
+        None => return Err(AresError::DeadContinuation),
+    };
+    cleanup_stack(to, ctx);
+    if let Target::Snapshot(ref snapshot) = jump.target {
+        ctx.stack.extend(snapshot.0.iter().cloned());
+        ctx.env_stack.extend(snapshot.1.iter().cloned());
+    }
+    ctx.stack.push(StepState::Complete(jump.value));
+    Ok(())
+}
+
+/// How big the stack is once a call to a continuation of the evaluation
+/// that starts at `bottom` on the stack has left everything after where it
+/// carries on from, if `error` is one.
+pub fn destination(error: &AresError, bottom: usize, stack: &[StepState]) -> Option<usize> {
+    match *error {
+        AresError::ContinuationCalled(ref jump) => {
+            match stack.get(bottom) {
+                Some(&StepState::Return(id)) if id == jump.evaluation => {
+                    find(jump.evaluation, &jump.target, stack)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// True if `error` is a continuation being called rather than something
+/// going wrong.
+pub fn is_jump(error: &AresError) -> bool {
+    match *error {
+        AresError::ContinuationCalled(_) => true,
+        _ => false,
+    }
+}
+
+/// How big the stack is once everything after where the continuation
+/// carries on from is gone, if its evaluation is still going.
+fn find(evaluation: usize, target: &Target, stack: &[StepState]) -> Option<usize> {
+    let bottom = match stack.iter().rposition(|state| match *state {
+        StepState::Return(id) => id == evaluation,
+        _ => false,
+    }) {
+        Some(bottom) => bottom,
+        None => return None,
+    };
+    match *target {
+        Target::Snapshot(_) => Some(bottom + 1),
+        Target::Escape(escape) => {
+            stack[bottom + 1..]
+                .iter()
+                .take_while(|state| match **state {
+                    StepState::Return(_) => false,
+                    _ => true,
+                })
+                .position(|state| match *state {
+                    StepState::Escape(id) => id == escape,
+                    _ => false,
+                })
+                .map(|at| bottom + 1 + at)
+        }
+    }
+}
+
+impl ::std::fmt::Debug for Jump {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        formatter.debug_struct("Jump")
+                 .field("evaluation", &self.evaluation)
+                 .field("value", &self.value)
+                 .finish()
+    }
+}
@@ -10,6 +10,11 @@ pub type Env = Rc<RefCell<Environment>>;
 pub struct Environment {
     parent: Option<Env>,
     bindings: HashMap<Symbol, Value>,
+    // Variables that compiled code knows the position of: parameters and
+    // the bindings of compiled `let`s.  Slots are `None` until the `let`
+    // binds them.  Anything `define`d at runtime goes in `bindings`.
+    names: Option<Rc<Vec<Symbol>>>,
+    slots: Vec<Option<Value>>,
 }
 
 impl Environment {
@@ -17,6 +22,8 @@ impl Environment {
         Environment {
             parent: None,
             bindings: HashMap::new(),
+            names: None,
+            slots: vec![],
         }
     }
 
@@ -24,15 +31,69 @@ impl Environment {
         Rc::new(RefCell::new(Environment {
             parent: Some(env),
             bindings: bindings,
+            names: None,
+            slots: vec![],
         }))
     }
 
+    /// An environment whose variables are in slots, one for each of `names`.
+    /// When a name is in there twice, the later slot is the one used.
+    pub fn new_with_slots(env: Env, names: Rc<Vec<Symbol>>, slots: Vec<Option<Value>>) -> Env {
+        debug_assert_eq!(names.len(), slots.len());
+        Rc::new(RefCell::new(Environment {
+            parent: Some(env),
+            bindings: HashMap::new(),
+            names: Some(names),
+            slots: slots,
+        }))
+    }
+
+    /// The slot that `name` is bound in here, if any.
+    fn slot(&self, name: Symbol) -> Option<usize> {
+        match self.names {
+            Some(ref names) => {
+                names.iter()
+                     .rposition(|&n| n == name)
+                     .and_then(|slot| self.slots[slot].as_ref().map(|_| slot))
+            }
+            None => None,
+        }
+    }
+
+    /// The value in `slot` of the environment `depth` levels up, as long as
+    /// nothing in between has had `name` defined in it at runtime.  `None`
+    /// means that `name` has to be looked up instead.
+    pub fn get_slot(&self, depth: usize, slot: usize, name: Symbol) -> Option<Value> {
+        if depth == 0 {
+            return self.slots[slot].clone();
+        }
+        if !self.bindings.is_empty() && self.bindings.contains_key(&name) {
+            return None;
+        }
+        match self.parent {
+            Some(ref p) => p.borrow().get_slot(depth - 1, slot, name),
+            None => None,
+        }
+    }
+
+    /// Binds `slot` in this environment.
+    pub fn set_slot(&mut self, slot: usize, value: Value) {
+        self.slots[slot] = Some(value);
+    }
+
     fn defined_helper(&self, values: &mut HashMap<Symbol, (u32, Value)>, depth: u32) {
         for (k, v) in &self.bindings {
             if !values.contains_key(k) {
                 values.insert(k.clone(), (depth, v.clone()));
             }
         }
+        if let Some(ref names) = self.names {
+            for (name, slot) in names.iter().zip(&self.slots).rev() {
+                if let Some(v) = slot.as_ref() {
+                    values.entry(*name).or_insert_with(|| (depth, v.clone()));
+                }
+            }
+        }
         if let &Some(ref parent) = &self.parent {
             parent.borrow().defined_helper(values, depth + 1);
         }
@@ -45,7 +106,7 @@ impl Environment {
     }
 
     pub fn is_defined_at_this_level(&self, name: Symbol) -> bool {
-        self.bindings.contains_key(&name)
+        self.slot(name).is_some() || self.bindings.contains_key(&name)
     }
 
     pub fn is_defined(&self, name: Symbol) -> bool {
@@ -53,7 +114,9 @@ impl Environment {
     }
 
     pub fn get(&self, name: Symbol) -> Option<Value> {
-        if self.bindings.contains_key(&name) {
+        if let Some(slot) = self.slot(name) {
+            self.slots[slot].clone()
+        } else if self.bindings.contains_key(&name) {
             Some(self.bindings[&name].clone())
         } else if let Some(ref p) = self.parent {
             let lock = p.borrow();
@@ -66,7 +129,9 @@ impl Environment {
     pub fn with_value<F, R>(&self, name: Symbol, function: F) -> Option<R>
         where F: FnOnce(&Value) -> R
     {
-        if self.bindings.contains_key(&name) {
+        if let Some(slot) = self.slot(name) {
+            self.slots[slot].as_ref().map(function)
+        } else if self.bindings.contains_key(&name) {
             Some(function(&self.bindings[&name]))
         } else if let Some(ref p) = self.parent {
             let lock = p.borrow();
@@ -79,7 +144,9 @@ impl Environment {
     pub fn with_value_mut<F, R>(&mut self, name: Symbol, function: F) -> Option<R>
         where F: FnOnce(&mut Value) -> R
     {
-        if self.bindings.contains_key(&name) {
+        if let Some(slot) = self.slot(name) {
+            self.slots[slot].as_mut().map(function)
+        } else if self.bindings.contains_key(&name) {
             Some(function(self.bindings.get_mut(&name).unwrap()))
         } else if let Some(ref p) = self.parent {
             let mut lock = p.borrow_mut();
@@ -90,6 +157,9 @@ impl Environment {
     }
 
     pub fn insert_here(&mut self, name: Symbol, value: Value) -> Option<Value> {
-        self.bindings.insert(name.into(), value)
+        match self.slot(name) {
+            Some(slot) => self.slots[slot].replace(value),
+            None => self.bindings.insert(name.into(), value),
+        }
     }
 }
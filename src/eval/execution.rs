@@ -0,0 +1,119 @@
+use std::mem;
+
+use {Value, AresError, AresResult};
+
+use super::{Env, StepState, step_or_clean_up};
+use super::continuation::fresh_id;
+use super::context::{LoadedContext, State};
+
+/// An evaluation that can be paused between steps and picked up again
+/// later, even with a different state loaded into the context.
+#[derive(Debug)]
+pub struct Execution {
+    stack: Vec<StepState>,
+    env_stack: Vec<Env>,
+    done: bool,
+}
+
+#[derive(Debug)]
+pub enum ExecutionStatus {
+    /// There are steps left to take.
+    Running,
+    Finished(Value),
+    Error(AresError),
+}
+
+impl Execution {
+    /// An execution that evaluates `value` as it is.  Use
+    /// `LoadedContext::start` to expand macros in it first.
+    pub fn new(value: Value) -> Execution {
+        Execution {
+            stack: vec![StepState::Return(fresh_id()), StepState::EvalThis(value, false)],
+            env_stack: vec![],
+            done: false,
+        }
+    }
+
+    /// True once the execution has finished, either with a value or with an
+    /// error.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Takes one step of evaluation.
+    pub fn step<S: State + ?Sized>(&mut self, ctx: &mut LoadedContext<S>) -> ExecutionStatus {
+        self.run_for(ctx, 1)
+    }
+
+    /// Takes up to `steps` steps of evaluation, stopping early if the
+    /// evaluation finishes.
+    ///
+    /// This can't be called while the context is evaluating something else,
+    /// like from inside of a foreign function.
+    pub fn run_for<S: State + ?Sized>(&mut self,
+                                      ctx: &mut LoadedContext<S>,
+                                      steps: usize)
+                                      -> ExecutionStatus {
+        if self.done {
+            let message = "this execution has already finished".into();
+            return ExecutionStatus::Error(AresError::InvalidState(message));
+        }
+        if !ctx.stack.is_empty() {
+            let message = "an execution can't be run while the context is evaluating".into();
+            return ExecutionStatus::Error(AresError::InvalidState(message));
+        }
+
+        ctx.error_backtrace = None;
+        // Interrupts that come while the execution is paused are for it, so
+        // they are left alone.
+        mem::swap(&mut self.stack, &mut ctx.stack);
+        mem::swap(&mut self.env_stack, &mut ctx.env_stack);
+        let result = ctx.run_for_host(false, |ctx| run_steps(ctx, steps));
+        mem::swap(&mut self.stack, &mut ctx.stack);
+        mem::swap(&mut self.env_stack, &mut ctx.env_stack);
+
+        match result {
+            Ok(None) => ExecutionStatus::Running,
+            Ok(Some(value)) => {
+                self.done = true;
+                ExecutionStatus::Finished(value)
+            }
+            Err(e) => {
+                self.done = true;
+                ExecutionStatus::Error(e)
+            }
+        }
+    }
+}
+
+/// Takes up to `steps` steps, giving the value of the evaluation if it
+/// finishes.
+fn run_steps<S: State + ?Sized>(ctx: &mut LoadedContext<S>,
+                                steps: usize)
+                                -> AresResult<Option<Value>> {
+    for _ in 0..steps {
+        // The bottom of the stack is the `StepState::Return` that `new` put
+        // there, so everything is done once the value above it is complete.
+        if ctx.stack.len() == 2 {
+            if let Some(&StepState::Complete(_)) = ctx.stack.last() {
+                break;
+            }
+        }
+        try!(step_or_clean_up(0, ctx));
+    }
+
+    let finished = match ctx.stack.last() {
+        Some(&StepState::Complete(_)) => ctx.stack.len() == 2,
+        _ => false,
+    };
+    if !finished {
+        return Ok(None);
+    }
+    match ctx.stack.pop() {
+        Some(StepState::Complete(value)) => {
+            ctx.stack.clear();
+            Ok(Some(value))
+        }
+        _ => unreachable!(),
+    }
+}
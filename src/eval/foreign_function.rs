@@ -1,9 +1,10 @@
 use std::rc::Rc;
 use std::any::TypeId;
 
-use {Value, AresResult, rc_to_usize, write_usize, State};
+use {Value, AresError, AresResult, rc_to_usize, write_usize, State};
 
 use super::context::LoadedContext;
+use super::special::SpecialForm;
 
 #[derive(Clone, Eq, PartialEq)]
 pub enum FfType {
@@ -18,6 +19,8 @@ pub struct ForeignFunction<S: State + ?Sized> {
     pub typ: FfType,
     #[doc(hidden)]
     pub function: Rc<Fn(&[Value], &mut LoadedContext<S>) -> AresResult<Value>>,
+    #[doc(hidden)]
+    pub special: Option<SpecialForm>,
     typeid: TypeId,
 }
 
@@ -37,17 +40,24 @@ impl ForeignFunction<()> {
             Err(self)
         }
     }
+
+    /// Like `correct`, for a function that is borrowed.
+    pub fn correct_ref<S: State + ?Sized>(&self) -> Option<&ForeignFunction<S>> {
+        use std::mem::transmute;
+        if TypeId::of::<S>() == self.typeid {
+            Some(unsafe { transmute(self) })
+        } else {
+            None
+        }
+    }
 }
 
 pub fn free_fn<S: State + ?Sized, N, F>(name: N, func: F) -> ForeignFunction<S>
     where N: Into<String>,
           F: Fn(&[Value]) -> AresResult<Value> + 'static
 {
-    let closure = move |values: &[Value], ctx: &mut LoadedContext<S>| {
-        let evaluated: Result<Vec<_>, _> = values.iter().map(|v| ctx.eval(v)).collect();
-        let evaluated = try!(evaluated);
-        func(&evaluated[..])
-    };
+    // The arguments are evaluated by the evaluator before this is called.
+    let closure = move |values: &[Value], _ctx: &mut LoadedContext<S>| func(values);
 
     let boxed = Rc::new(closure);
     ForeignFunction {
@@ -55,6 +65,7 @@ pub fn free_fn<S: State + ?Sized, N, F>(name: N, func: F) -> ForeignFunction<S>
         function: boxed,
         typeid: TypeId::of::<S>(),
         typ: FfType::Free,
+        special: None,
     }
 }
 
@@ -62,18 +73,13 @@ pub fn user_fn<S: State + ?Sized, N, F>(name: N, func: F) -> ForeignFunction<S>
     where N: Into<String>,
           F: Fn(&[Value], &mut LoadedContext<S>) -> AresResult<Value> + 'static
 {
-    let closure = move |values: &[Value], ctx: &mut LoadedContext<S>| {
-        let evaluated: Result<Vec<_>, _> = values.iter().map(|v| ctx.eval(v)).collect();
-        let evaluated = try!(evaluated);
-        func(&evaluated[..], ctx)
-    };
-
-    let boxed = Rc::new(closure);
+    let boxed = Rc::new(func);
     ForeignFunction {
         name: name.into(),
         function: boxed,
         typeid: TypeId::of::<S>(),
         typ: FfType::User,
+        special: None,
     }
 }
 
@@ -88,6 +94,29 @@ pub fn ast_fn<S: State + ?Sized, N, F>(name: N, func: F) -> ForeignFunction<S>
         function: boxed,
         typeid: TypeId::of::<S>(),
         typ: FfType::Ast,
+        special: None,
+    }
+}
+
+/// A form that the evaluator knows how to step through by itself.
+pub fn special_form<S: State + ?Sized, N>(name: N, form: SpecialForm) -> ForeignFunction<S>
+    where N: Into<String>
+{
+    let name = name.into();
+    let message = format!("{} is evaluated by the evaluator", name);
+    let function = move |_: &[Value], _: &mut LoadedContext<S>| -> AresResult<Value> {
+        Err(AresError::InvalidState(message.clone()))
+    };
+    ForeignFunction {
+        name: name,
+        function: Rc::new(function),
+        typeid: TypeId::of::<S>(),
+        typ: if form.evaluates_args() {
+            FfType::User
+        } else {
+            FfType::Ast
+        },
+        special: Some(form),
     }
 }
 
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Stops evaluation in a context from another thread.
+///
+/// Get one from `Context::interrupt_handle`.  Once `interrupt` is called,
+/// the next step that the context takes fails with `AresError::Interrupted`,
+/// and the interrupt is cleared again so that the context can carry on
+/// being used.
+///
+/// Only evaluation that is going on can be interrupted.  An interrupt that
+/// comes while the context is idle is forgotten once the host next calls
+/// something like `eval_str`, `call` or `LoadedContext::start`.  One that
+/// comes at any point after that, even while a program is being read or
+/// between its top-level forms, stops it.  One that comes while an
+/// `Execution` is paused stops it the next time it runs.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn new() -> InterruptHandle {
+        InterruptHandle::default()
+    }
+
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether an interrupt is waiting to stop evaluation.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Takes back an interrupt that hasn't stopped evaluation yet.
+    pub fn clear(&self) {
+        self.interrupted.store(false, Ordering::SeqCst);
+    }
+}
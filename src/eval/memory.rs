@@ -0,0 +1,112 @@
+use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use {Value, AresError, AresResult};
+
+/// Roughly how many bytes of lists, strings and maps have been allocated,
+/// by kind.
+///
+/// This counts what was allocated, not what is still alive, since values
+/// don't say when they are freed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub lists: usize,
+    pub strings: usize,
+    pub maps: usize,
+    /// How many bytes can be allocated in total, if that is limited.
+    pub limit: Option<usize>,
+}
+
+impl MemoryStats {
+    pub fn allocated(&self) -> usize {
+        self.lists + self.strings + self.maps
+    }
+}
+
+/// Keeps track of the memory allocated by a context.  Clones share the
+/// same counts, so foreign functions can hold on to one.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAccount {
+    stats: Rc<Cell<MemoryStats>>,
+}
+
+// The reference counts that come with every `Rc`.
+const RC_OVERHEAD: usize = 2 * 8;
+
+impl MemoryAccount {
+    pub fn new() -> MemoryAccount {
+        MemoryAccount::default()
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        self.stats.get()
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        let mut stats = self.stats.get();
+        stats.limit = limit;
+        self.stats.set(stats);
+    }
+
+    /// Forgets everything allocated so far, keeping the limit.
+    pub fn reset(&self) {
+        self.stats.set(MemoryStats { limit: self.stats.get().limit, ..MemoryStats::default() });
+    }
+
+    /// Accounts for a new list of `len` elements.
+    pub fn charge_list(&self, len: usize) -> AresResult<()> {
+        self.charge_list_growth(len).and_then(|_| {
+            self.charge(|stats| &mut stats.lists, RC_OVERHEAD + size_of::<Vec<Value>>())
+        })
+    }
+
+    /// Accounts for `added` more elements in a list that was already
+    /// accounted for.
+    pub fn charge_list_growth(&self, added: usize) -> AresResult<()> {
+        self.charge(|stats| &mut stats.lists, added.saturating_mul(size_of::<Value>()))
+    }
+
+    /// Accounts for a new string of `len` bytes.
+    pub fn charge_string(&self, len: usize) -> AresResult<()> {
+        self.charge(|stats| &mut stats.strings,
+                    len.saturating_add(RC_OVERHEAD + size_of::<String>()))
+    }
+
+    /// Accounts for a new map with `len` entries.
+    pub fn charge_map(&self, len: usize) -> AresResult<()> {
+        // Each entry also has a byte or so of bookkeeping in the table.
+        let entry = 2 * size_of::<Value>() + 1;
+        let table = RC_OVERHEAD + size_of::<HashMap<Value, Value>>();
+        self.charge(|stats| &mut stats.maps, len.saturating_mul(entry).saturating_add(table))
+    }
+
+    /// Accounts for a value that was just made, if it is a list, string or
+    /// map.  Only the outermost value is counted.
+    pub fn charge_value(&self, value: &Value) -> AresResult<()> {
+        match *value {
+            Value::List(ref l) => self.charge_list(l.len()),
+            Value::String(ref s) => self.charge_string(s.len()),
+            Value::Map(ref m) => self.charge_map(m.len()),
+            _ => Ok(()),
+        }
+    }
+
+    fn charge<F>(&self, kind: F, bytes: usize) -> AresResult<()>
+        where F: FnOnce(&mut MemoryStats) -> &mut usize
+    {
+        let mut stats = self.stats.get();
+        if let Some(limit) = stats.limit {
+            if stats.allocated().saturating_add(bytes) > limit {
+                return Err(AresError::MemoryLimitExceeded(limit));
+            }
+        }
+        {
+            let count = kind(&mut stats);
+            *count = count.saturating_add(bytes);
+        }
+        self.stats.set(stats);
+        Ok(())
+    }
+}
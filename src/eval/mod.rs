@@ -1,42 +1,206 @@
 use super::{Value, AresError, AresResult};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::mem;
+use parse::{Span, SpanTable};
+use intern::Symbol;
+use stdlib::map::keyword_lookup;
+use util::RcSlice;
+
+use self::backtrace::{Call, record_backtrace, record_failed_call};
+use self::vm::Activation;
 
 pub use self::environment::{Env, Environment};
-pub use self::foreign_function::{ForeignFunction, free_fn, ast_fn, user_fn, FfType};
+pub use self::foreign_function::{ForeignFunction, free_fn, ast_fn, user_fn, special_form, FfType};
+pub use self::special::SpecialForm;
 pub use self::procedure::{Procedure, ParamBinding};
 pub use self::context::{Context, LoadedContext, State};
+pub use self::execution::{Execution, ExecutionStatus};
+pub use self::memory::{MemoryAccount, MemoryStats};
+pub use self::interrupt::InterruptHandle;
+pub use self::backtrace::{Backtrace, Frame};
+pub use self::compile::compile;
+pub use self::continuation::Jump;
 
 mod environment;
 mod foreign_function;
 mod procedure;
 mod context;
+mod execution;
+mod memory;
+mod interrupt;
+mod backtrace;
+mod compile;
+mod vm;
+mod special;
 mod transformations;
+mod continuation;
+mod catch;
 
 #[derive(Clone)]
 pub enum StepState {
     EvalThis(Value, bool),
-    PopEnv,
-    Return,
+    /// Pops the environment of a `let` or of a call to a lambda.
+    PopEnv(Option<Rc<Call>>),
+    /// The bottom of an evaluation, which has this id.
+    Return(usize),
     Complete(Value),
+    /// Where the escape continuation with this id goes back to.
+    Escape(usize),
+    /// Evaluating the bodies of a `try`, or the `catch` that caught an
+    /// error from them if `catching`.
+    Try {
+        handlers: Rc<catch::Handlers>,
+        catching: bool,
+    },
+    /// Evaluating the `finally` of a `try`, which gives the outcome after.
+    Finally(catch::Outcome),
+    /// Waiting on the function of a call.  The arguments are `form[1..]`.
     PreEvaluatedCallable {
-        unevaluated: Vec<Value>,
+        form: Rc<Vec<Value>>,
     },
-    ArgCollectingLambda {
-        procedure: Procedure,
+    /// Waiting on an argument of a call.  The arguments after it are
+    /// `form[next..]`.
+    ArgCollecting {
+        function: Value,
         evaluated: Vec<Value>,
-        unevaluated: Vec<Value>,
+        next: usize,
+        form: Rc<Vec<Value>>,
     },
+    /// Waiting on a body that isn't the last.  `bodies` are the ones after
+    /// it.
     EvaluatingLambda {
-        name: Option<String>,
-        bodies: Vec<Value>,
+        bodies: RcSlice,
+    },
+    /// Running the compiled bodies of a lambda.
+    Compiled(Box<Activation>),
+    /// Waiting on the condition of an `if`.
+    Branch {
+        then: Value,
+        otherwise: Value,
+    },
+    /// Waiting on the value of a `let` binding.  `bindings` are the names
+    /// and values after it.
+    Binding {
+        name: Symbol,
+        bindings: RcSlice,
+        bodies: RcSlice,
+    },
+    /// Waiting on an operand of `and`, `or` or `xor`.
+    Logical {
+        form: SpecialForm,
+        rest: RcSlice,
+        seen_true: bool,
+        seen_false: bool,
+    },
+    /// Waiting on the value of a `define`, `define-macro` or `set`.
+    Assign {
+        form: SpecialForm,
+        name: Symbol,
+    },
+    /// Waiting on a call that `for-each` made, having made `count` before
+    /// it.  `rest` are the elements left to call `function` with.
+    ForEach {
+        function: Value,
+        rest: RcSlice,
+        count: i64,
     },
 }
 
+impl StepState {
+    /// The list form that this state is evaluating, if there is one.
+    fn form(&self) -> Option<&Rc<Vec<Value>>> {
+        match self {
+            &StepState::EvalThis(Value::List(ref form), _) |
+            &StepState::PreEvaluatedCallable { ref form, .. } |
+            &StepState::ArgCollecting { ref form, .. } => Some(form),
+            &StepState::Compiled(ref activation) => activation.form(),
+            _ => None,
+        }
+    }
+
+    /// The source location of the form that this state is evaluating, if known.
+    fn span(&self, spans: &SpanTable) -> Option<Span> {
+        self.form().and_then(|form| spans.get(form))
+    }
+
+    /// How many environments on the env-stack this state pops.
+    fn envs(&self) -> usize {
+        match self {
+            &StepState::PopEnv(_) => 1,
+            &StepState::Compiled(ref activation) => activation.envs(),
+            _ => 0,
+        }
+    }
+}
+
+/// Bodies to evaluate in place of a call to a foreign function, and the
+/// environment to evaluate them in.
+type TailEval = (Vec<Value>, Option<Env>);
+
+/// What `LoadedContext::eval_in_tail_position` gives, so that the foreign
+/// function that asked can return it.  What it holds is taken out when it
+/// is returned, so it is only evaluated once however often it is returned.
+struct InTailPosition(RefCell<Option<TailEval>>);
+
+/// Remembers where `error` came from, unless that is already known for
+/// the error being raised, and the calls that led to it.  Continuations
+/// being called aren't errors, so nothing is remembered for them.
+///
+/// `form` is the form that failed.  If its location isn't known, the
+/// innermost form that is still being evaluated is used instead.
+fn locate_error<S: ?Sized + State>(error: AresError,
+                                   form: Option<Rc<Vec<Value>>>,
+                                   ctx: &mut LoadedContext<S>)
+                                   -> AresError {
+    if continuation::is_jump(&error) {
+        return error;
+    }
+    record_backtrace(ctx);
+    if ctx.error_span.is_none() {
+        let span = form.and_then(|form| ctx.spans().get(&form));
+        ctx.error_span = span.or_else(|| {
+            ctx.stack.iter().rev().filter_map(|state| state.span(ctx.spans())).next()
+        });
+    }
+    error
+}
+
 fn cleanup_stack<S: ?Sized + State>(target_size: usize, ctx: &mut LoadedContext<S>) {
     while ctx.stack.len() > target_size {
-        ctx.stack.pop();
+        // Environments are popped along with the states that would have
+        // popped them.
+        if let Some(state) = ctx.stack.pop() {
+            for _ in 0..state.envs() {
+                ctx.env_stack.pop();
+            }
+        }
     }
 }
 
+/// Makes one step.  If that fails, cleans up the stack down to
+/// `cleanup_len` before propogating the error upwards.
+fn step_or_clean_up<S: ?Sized + State>(cleanup_len: usize,
+                                       ctx: &mut LoadedContext<S>)
+                                       -> AresResult<()> {
+    if let Err(e) = step_eval(ctx) {
+        // Errors can be caught, and continuations of this evaluation carry
+        // on from here.
+        if let Err(e) = catch::unwind(e, cleanup_len, ctx) {
+            cleanup_stack(cleanup_len, ctx);
+            return Err(e);
+        }
+    }
+
+    if ctx.stack.len() > ctx.max_depth() {
+        let error = AresError::DepthLimitExceeded(ctx.max_depth());
+        let error = locate_error(error, None, ctx);
+        cleanup_stack(cleanup_len, ctx);
+        return Err(error);
+    }
+    Ok(())
+}
+
 /// Runs the eval-loop until the state stack of of a target size.
 /// The target size is usually the size of the stack before the call to eval/apply
 /// plus 2 (one spot for the StepState::Return, one spot for the StepState::Complete)
@@ -59,14 +223,7 @@ fn run_evaluation<S: ?Sized + State>(target_len: usize,
         }
 
         // Make one step on the interpreter
-        let result = step_eval(ctx);
-
-        // If an error occurred, clean up the stack from this point and propogate
-        // the error upwards.
-        if let Err(e) = result {
-            cleanup_stack(cleanup_len, ctx);
-            return Err(e);
-        }
+        try!(step_or_clean_up(cleanup_len, ctx));
     }
 
     // Once the eval-loop is done, we are interested in the top-two elements on the
@@ -85,21 +242,52 @@ fn run_evaluation<S: ?Sized + State>(target_len: usize,
     }
 }
 
+/// Runs `f`, which evaluates something, unless evaluation is already
+/// nested too deeply in native code.
+fn nested<S: ?Sized, F>(ctx: &mut LoadedContext<S>, f: F) -> AresResult<Value>
+    where S: State,
+          F: FnOnce(&mut LoadedContext<S>) -> AresResult<Value>
+{
+    if ctx.native_depth >= ctx.max_native_depth() {
+        let error = AresError::DepthLimitExceeded(ctx.max_native_depth());
+        return Err(locate_error(error, None, ctx));
+    }
+    ctx.native_depth += 1;
+    let result = f(ctx);
+    ctx.native_depth -= 1;
+    if result.is_ok() {
+        // Whoever called this dealt with any error that happened inside.
+        ctx.error_span = None;
+        ctx.error_backtrace = None;
+    }
+    result
+}
+
 pub fn eval<S: ?Sized>(value: &Value, ctx: &mut LoadedContext<S>) -> AresResult<Value>
     where S: State
+{
+    nested(ctx, |ctx| eval_nested(value, ctx))
+}
+
+fn eval_nested<S: ?Sized>(value: &Value, ctx: &mut LoadedContext<S>) -> AresResult<Value>
+    where S: State
 {
     // FIXME: this will require an interface change that I don't want to do right
     // now.
     let value = value.clone();
 
     let cleanup_len = ctx.stack.len();
+    if cleanup_len == 0 {
+        ctx.error_span = None;
+        ctx.error_backtrace = None;
+    }
 
     // Push the return signal and a request to evaluate the value onto the stack.
-    ctx.stack.push(StepState::Return);
+    ctx.stack.push(StepState::Return(continuation::fresh_id()));
     ctx.stack.push(StepState::EvalThis(value, false));
 
     match try!(run_evaluation(ctx.stack.len(), cleanup_len, ctx)) {
-        (StepState::Return, StepState::Complete(value)) => Ok(value),
+        (StepState::Return(_), StepState::Complete(value)) => Ok(value),
         (next_top, top) => panic!("eval(..): invalid stack state [{:?}, {:?}, {:?}]",
                                   ctx.stack,
                                   next_top,
@@ -112,17 +300,33 @@ pub fn apply<'a, S: ?Sized>(func: &Value,
                             ctx: &mut LoadedContext<S>)
                             -> AresResult<Value>
     where S: State
+{
+    nested(ctx, |ctx| apply_nested(func, args, ctx))
+}
+
+fn apply_nested<S: ?Sized>(func: &Value,
+                           args: Vec<Value>,
+                           ctx: &mut LoadedContext<S>)
+                           -> AresResult<Value>
+    where S: State
 {
     // Keep track of the current stack size.
     let prior_len = ctx.stack.len();
+    if prior_len == 0 {
+        ctx.error_span = None;
+        ctx.error_backtrace = None;
+    }
     // Push the return signal onto the stack.
-    ctx.stack.push(StepState::Return);
+    ctx.stack.push(StepState::Return(continuation::fresh_id()));
     // `do_apply` will push either 1, 2, or 3 items on the stack by itself.
-    try!(do_apply(func.clone(), args, ctx));
+    if let Err(e) = do_apply(func.clone(), args, None, ctx) {
+        cleanup_stack(prior_len, ctx);
+        return Err(e);
+    }
     // Run the evaluation with a target end point of the prior length + 2
     // (one for the return, one for the Completed value.
     match try!(run_evaluation(prior_len + 2, prior_len, ctx)) {
-        (StepState::Return, StepState::Complete(value)) => Ok(value),
+        (StepState::Return(_), StepState::Complete(value)) => Ok(value),
         (next_top, top) => panic!("apply(..): invalid stack state [{:?}, {:?}, {:?}]",
                                   ctx.stack,
                                   next_top,
@@ -132,42 +336,77 @@ pub fn apply<'a, S: ?Sized>(func: &Value,
 
 /// Moves the interpreter one "step" forward in the execution.
 fn step_eval<S: State + ?Sized>(ctx: &mut LoadedContext<S>) -> AresResult<()> {
+    if let Err(e) = ctx.use_fuel() {
+        return Err(locate_error(e, None, ctx));
+    }
     // Pop the top off of the top value in the stack and switch on the value
     // contained within.
     let top = ctx.stack.pop().unwrap();
+    // Remember which form is being worked on in case this step fails.
+    let form = match top {
+        StepState::Complete(_) => ctx.stack.last().and_then(StepState::form).cloned(),
+        ref other => other.form().cloned(),
+    };
+    step_state(top, ctx).map_err(|e| locate_error(e, form, ctx))
+}
+
+fn step_state<S: State + ?Sized>(top: StepState, ctx: &mut LoadedContext<S>) -> AresResult<()> {
     // If a value was just computed, we need to apply that computed value to the
     // state machine that
     // is just below.
     if let StepState::Complete(value) = top {
         match ctx.stack.pop().unwrap() {
-            StepState::PreEvaluatedCallable { unevaluated } => {
+            StepState::PreEvaluatedCallable { form } => {
                 // A PreEvaluatedCallable just got the "function" evaluated.
                 // `value` is the function that will eventually be called
-                try!(transformations::from_pre_evaluated(unevaluated, value, ctx));
+                try!(transformations::from_pre_evaluated(value, form, ctx));
             }
-            StepState::ArgCollectingLambda { procedure, unevaluated, evaluated} => {
-                // An ArgCollectingLambda just got one of its arguments evaluated.
+            StepState::ArgCollecting { function, evaluated, next, form } => {
+                // An ArgCollecting just got one of its arguments evaluated.
                 // `value` is the post-evalauted argument.
-                try!(transformations::from_arg_collecting_lambda(procedure,
-                                                                 unevaluated,
-                                                                 evaluated,
-                                                                 value,
-                                                                 ctx));
+                try!(transformations::from_arg_collecting(function,
+                                                          evaluated,
+                                                          next,
+                                                          value,
+                                                          form,
+                                                          ctx));
             }
-            StepState::EvaluatingLambda { bodies, name } => {
+            StepState::EvaluatingLambda { bodies } => {
                 // An EvaluatingLambda just got the result from the execution of one of its
-                // bodies. `value` is the retult of that body being executed.
-                try!(transformations::from_evaluating_lambda(bodies, name, value, ctx));
+                // bodies.  That value is thrown away, and the next body is evaluated.
+                transformations::from_evaluating_lambda(bodies, ctx);
+            }
+            StepState::Compiled(activation) => {
+                // A compiled lambda was waiting on `value`.
+                try!(vm::resume(activation, value, ctx));
             }
-            StepState::PopEnv => {
+            StepState::PopEnv(call) => {
                 // Ok, this one isn't a state machine.  If you see a PopEnv, just
                 // pop the env-stack and push the completed value back on the stack.
                 ctx.env_stack.pop();
+                end_call(call, ctx);
+                ctx.stack.push(StepState::Complete(value));
+            }
+            state@StepState::Branch { .. } |
+            state@StepState::Binding { .. } |
+            state@StepState::Logical { .. } |
+            state@StepState::Assign { .. } |
+            state@StepState::ForEach { .. } => {
+                // A special form just got one of its expressions evaluated.
+                try!(special::resume(state, value, ctx));
+            }
+            StepState::Escape(_) => {
+                // The escape continuation wasn't used, so this is the value
+                // of the `call/ec`.
                 ctx.stack.push(StepState::Complete(value));
             }
+            state@StepState::Try { .. } |
+            state@StepState::Finally(_) => {
+                try!(catch::resume(state, value, ctx));
+            }
             // All of these should be impossible to reach, so let's panic.
             a@StepState::EvalThis(_, _) |
-            a@StepState::Return |
+            a@StepState::Return(_) |
             a@StepState::Complete(_) =>
                 panic!("step_eval(..): invalid stack state: [{:?}, {:?}, {:?}]",
                        ctx.stack,
@@ -183,14 +422,25 @@ fn step_eval<S: State + ?Sized>(ctx: &mut LoadedContext<S>) -> AresResult<()> {
                 // function.
                 try!(eval_this(value, ctx, proc_head));
             }
-            StepState::PopEnv => {
+            StepState::PopEnv(_) => {
                 ctx.env_stack.pop();
             }
+            StepState::Compiled(activation) => {
+                try!(vm::run(activation, ctx));
+            }
             // These should all be impossible to reach.
-            a@StepState::Return |
-            a@StepState::ArgCollectingLambda {..} |
+            a@StepState::Return(_) |
+            a@StepState::Escape(_) |
+            a@StepState::Try { .. } |
+            a@StepState::Finally(_) |
+            a@StepState::ArgCollecting { .. } |
             a@StepState::PreEvaluatedCallable { .. } |
-            a@StepState::EvaluatingLambda { .. } =>
+            a@StepState::EvaluatingLambda { .. } |
+            a@StepState::Branch { .. } |
+            a@StepState::Binding { .. } |
+            a@StepState::Logical { .. } |
+            a@StepState::Assign { .. } |
+            a@StepState::ForEach { .. } =>
                 panic!("step_eval(..): invalid stack state: [..., {:?}]", a),
         }
     }
@@ -211,7 +461,7 @@ fn eval_this<S: State + ?Sized>(value: Value,
             let lookup = ctx.env().borrow().get(symbol);
             match lookup {
                 // Ban Ast functions that are getting passed as arguments.
-                Some(Value::ForeignFn(ForeignFunction{typ: FfType::Ast, ..})) if !proc_head => {
+                Some(Value::ForeignFn(ref f)) if f.typ == FfType::Ast && !proc_head => {
                     Err(AresError::AstFunctionPass)
                 }
                 Some(v) => {
@@ -222,9 +472,8 @@ fn eval_this<S: State + ?Sized>(value: Value,
             }
         }
 
-        Value::List(items) => {
-            let mut items: Vec<_> = (*items).clone();
-            if items.len() == 0 {
+        Value::List(form) => {
+            if form.is_empty() {
                 return Err(AresError::ExecuteEmptyList);
             }
             // Grab the first element in the list.  This is the function that is
@@ -232,9 +481,10 @@ fn eval_this<S: State + ?Sized>(value: Value,
             //
             // (a b c d)
             //  ^
-            let first = items.remove(0);
-            // Start a pre-evaluated callable with the rest of the items.
-            ctx.stack.push(StepState::PreEvaluatedCallable { unevaluated: items });
+            let first = form[0].clone();
+            // Start a pre-evaluated callable, which gets the rest of the items
+            // from the form.
+            ctx.stack.push(StepState::PreEvaluatedCallable { form: form });
             // Try to evaluate the head.
             ctx.stack.push(StepState::EvalThis(first, true));
             Ok(())
@@ -251,98 +501,237 @@ fn eval_this<S: State + ?Sized>(value: Value,
     }
 }
 
-fn apply_lambda<S: ?Sized>(procedure: Procedure,
+fn apply_lambda<S: ?Sized>(procedure: Rc<Procedure>,
                            args: Vec<Value>,
+                           form: Option<Rc<Vec<Value>>>,
                            ctx: &mut LoadedContext<S>)
                            -> AresResult<()>
     where S: State
 {
-    // Make sure that there weren't any raw AST functions being passed in to the
-    // lambda.
-    for arg in &args {
-        if let &Value::ForeignFn(ForeignFunction { typ: FfType::Ast, .. }) = arg {
-            return Err(AresError::AstFunctionPass);
-        }
-    }
-
-
-    let mut bodies = (*procedure.bodies).clone();
-    // Reverse the body order because we'll be using pop to get them off and we
-    // want them in the correct order.
-    bodies.reverse();
-
-    // Unwrap is ok because lambdas must have at least one body.
-    let first_body = bodies.pop().unwrap();
-
+    let height = ctx.stack.len();
     // TODO: in the future, we might be able to skip all of this
     // if there aren't any args and there aren't any `define`s in the lambda body.
     //
     // Generate the new environment for the duration of the lambda body execution.
     // This environment will be pushed on the env-stack and then popped off once all
     // the bodies are done executing.
-    let new_env = try!(procedure.gen_env(args));
+    let new_env = match lambda_env(&procedure, &args, ctx) {
+        Ok(env) => env,
+        Err(e) => {
+            record_failed_call(procedure.name.clone(), &args, form.as_ref(), height, ctx);
+            return Err(e);
+        }
+    };
 
-    // Push the new environment on the env-stack, and immediately push the PopEnv on
-    // the step-state stack.  When the lambda is done being executed, the PopEnv
-    // will be on the top of the stack, so this env what we just pushed on to the
-    // env-stack will be popped off.
-    ctx.env_stack.push(new_env.clone());
-    ctx.stack.push(StepState::PopEnv);
+    let code = procedure.code.clone();
+    let bodies = procedure.bodies.clone();
+    let call = Call {
+        procedure: procedure,
+        args: args,
+        form: form,
+    };
+    push_env(new_env, Some(Rc::new(call)), ctx);
+    match code {
+        Some(code) => {
+            let activation = vm::activation(code, ctx);
+            ctx.stack.push(StepState::Compiled(activation));
+        }
+        None => push_bodies(RcSlice::from_rc(bodies), ctx),
+    }
+    Ok(())
+}
 
-    // A call to evaluate the first body under the new environment.
+fn lambda_env<S: ?Sized>(procedure: &Procedure,
+                         args: &[Value],
+                         ctx: &mut LoadedContext<S>)
+                         -> AresResult<Env>
+    where S: State
+{
+    // Make sure that there weren't any raw AST functions being passed in to the
+    // lambda.
+    for arg in args {
+        if let Value::ForeignFn(ref f) = *arg {
+            if f.typ == FfType::Ast {
+                return Err(AresError::AstFunctionPass);
+            }
+        }
+    }
+    procedure.gen_env(args, ctx.memory())
+}
+
+/// Makes `env` the current environment until the next value is completed.
+/// `call` is the call to a lambda that the environment is for, if any.
+///
+/// In tail position the current environment is about to be popped anyway,
+/// so it is replaced instead, along with the call if there is a new one.
+/// This keeps tail calls from growing the stacks.
+fn push_env<S: ?Sized>(env: Env, call: Option<Rc<Call>>, ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    let mut replaced = None;
+    if let Some(&mut StepState::PopEnv(ref mut current)) = ctx.stack.last_mut() {
+        if let Some(top) = ctx.env_stack.last_mut() {
+            *top = env;
+            if call.is_some() {
+                replaced = mem::replace(current, call);
+            }
+            return end_call(replaced, ctx);
+        }
+    }
+    // Push the new environment on the env-stack, and push the PopEnv on the
+    // step-state stack.  When the bodies are done being executed, the
+    // PopEnv will be on the top of the stack, so the environment will be
+    // popped off.
+    ctx.env_stack.push(env);
+    ctx.stack.push(StepState::PopEnv(call));
+}
+
+/// Lets the arguments of a call that is done be used again.
+fn end_call<S: ?Sized>(call: Option<Rc<Call>>, ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    if let Some(Ok(call)) = call.map(Rc::try_unwrap) {
+        ctx.recycle_args(call.args);
+    }
+}
+
+/// Evaluates `bodies` one after the other.  `bodies` can't be empty.
+fn push_bodies<S: ?Sized>(mut bodies: RcSlice, ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    let first_body = bodies.pop_front().unwrap();
     let body_eval = StepState::EvalThis(first_body, false);
-    if bodies.len() == 0 {
+    if bodies.is_empty() {
         // Optimizing the common case (lambdas with only one body).
         // This case doesn't need an EvaluatingLambda on the stack because
         // there are no further bodies to evaluate.
         ctx.stack.push(body_eval);
     } else {
-        // Make a watching state that holds the rest of the bodies of the lambda.
-        let watching_state = StepState::EvaluatingLambda {
-            name: procedure.name,
-            bodies: bodies,
-        };
-        ctx.stack.push(watching_state);
+        // Make a watching state that holds the rest of the bodies.
+        ctx.stack.push(StepState::EvaluatingLambda { bodies: bodies });
         ctx.stack.push(body_eval);
     }
-    Ok(())
 }
 
-fn apply_function<S: ?Sized>(function: ForeignFunction<()>,
+/// Calls a foreign function, pushing its result.  `form` is the call, if
+/// there is one.
+fn apply_function<S: ?Sized>(function: Rc<ForeignFunction<()>>,
                              args: Vec<Value>,
+                             form: Option<Rc<Vec<Value>>>,
                              ctx: &mut LoadedContext<S>)
-                             -> AresResult<Value>
+                             -> AresResult<()>
+    where S: State
+{
+    if let Some(special) = function.special {
+        return special::start(special, RcSlice::new(args), ctx);
+    }
+    let result = call_function(&function, &args, form, ctx);
+    ctx.recycle_args(args);
+    finish_call(try!(result), ctx)
+}
+
+/// Calls a function that takes the arguments of `form` unevaluated.
+fn apply_ast_function<S: ?Sized>(function: Rc<ForeignFunction<()>>,
+                                 form: Rc<Vec<Value>>,
+                                 ctx: &mut LoadedContext<S>)
+                                 -> AresResult<()>
+    where S: State
+{
+    if let Some(special) = function.special {
+        let args = RcSlice::from_rc(form);
+        let len = args.len();
+        return special::start(special, args.slice(1, len), ctx);
+    }
+    let result = try!(call_function(&function, &form[1..], Some(form.clone()), ctx));
+    finish_call(result, ctx)
+}
+
+/// Pushes the result of a foreign function, unless it returned what
+/// `eval_in_tail_position` gave it, in which case the bodies that it asked
+/// for are evaluated in its place.
+fn finish_call<S: ?Sized>(result: Value, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    let tail_eval = match result.as_user_data::<InTailPosition>() {
+        Some(&InTailPosition(ref tail_eval)) => tail_eval.borrow_mut().take(),
+        None => {
+            ctx.stack.push(StepState::Complete(result));
+            return Ok(());
+        }
+    };
+    match tail_eval {
+        Some((bodies, env)) => {
+            if let Some(env) = env {
+                push_env(env, None, ctx);
+            }
+            push_bodies(RcSlice::new(bodies), ctx);
+            Ok(())
+        }
+        None => {
+            let message = "returned a tail evaluation that was already done".into();
+            Err(AresError::InvalidState(message))
+        }
+    }
+}
+
+fn call_function<S: ?Sized>(function: &ForeignFunction<()>,
+                            args: &[Value],
+                            form: Option<Rc<Vec<Value>>>,
+                            ctx: &mut LoadedContext<S>)
+                            -> AresResult<Value>
     where S: State
 {
     // Make sure that there weren't any raw AST functions being passed in to the
     // function.
-    for arg in &args {
-        if let &Value::ForeignFn(ForeignFunction { typ: FfType::Ast, .. }) = arg {
-            return Err(AresError::AstFunctionPass);
+    for arg in args {
+        if let Value::ForeignFn(ref f) = *arg {
+            if f.typ == FfType::Ast {
+                return Err(AresError::AstFunctionPass);
+            }
         }
     }
 
     // Translate the function back into the correct generic form.
-    let corrected = try!(function.correct::<S>().or(Err(AresError::InvalidForeignFunctionState)));
-    // Call the function
-    // FIXME pass the whole vec in
-    (corrected.function)(&args[..], ctx)
+    let corrected = try!(function.correct_ref::<S>().ok_or(AresError::InvalidForeignFunctionState));
+    // Call the function.
+    let height = ctx.stack.len();
+    match (corrected.function)(args, ctx) {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            if !continuation::is_jump(&e) {
+                record_failed_call(Some(corrected.name.clone()), args, form.as_ref(), height, ctx);
+            }
+            Err(e)
+        }
+    }
 }
 
 fn do_apply<'a, S: ?Sized>(func: Value,
                            args: Vec<Value>,
+                           form: Option<Rc<Vec<Value>>>,
                            ctx: &mut LoadedContext<S>)
                            -> AresResult<()>
     where S: State
 {
     match func {
         Value::Lambda(procedure, _) => {
-            apply_lambda(procedure, args, ctx)
+            apply_lambda(procedure, args, form, ctx)
         }
-        Value::ForeignFn(ff) => {
-            let res = try!(apply_function(ff, args, ctx));
-            ctx.stack.push(StepState::Complete(res));
-            Ok(())
+        Value::ForeignFn(ff) => apply_function(ff, args, form, ctx),
+        keyword@Value::Keyword(_) => {
+            match keyword_lookup(&keyword, &args) {
+                Ok(res) => {
+                    ctx.recycle_args(args);
+                    ctx.stack.push(StepState::Complete(res));
+                    Ok(())
+                }
+                Err(e) => {
+                    let name = ctx.format_value(&keyword);
+                    let height = ctx.stack.len();
+                    record_failed_call(Some(name), &args, form.as_ref(), height, ctx);
+                    Err(e)
+                }
+            }
         }
         other => Err(AresError::UnexecutableValue(other)),
     }
@@ -354,29 +743,71 @@ impl ::std::fmt::Debug for StepState {
             &StepState::EvalThis(ref v, _) => formatter.debug_tuple("EvalThis")
                                                        .field(v)
                                                        .finish(),
-            &StepState::PopEnv => formatter.debug_tuple("PopEnv")
-                                           .finish(),
-            &StepState::Return => formatter.debug_tuple("Return")
-                                           .finish(),
+            &StepState::PopEnv(ref call) => formatter.debug_tuple("PopEnv")
+                                                     .field(call)
+                                                     .finish(),
+            &StepState::Return(id) => formatter.debug_tuple("Return")
+                                               .field(&id)
+                                               .finish(),
+            &StepState::Escape(id) => formatter.debug_tuple("Escape")
+                                               .field(&id)
+                                               .finish(),
+            &StepState::Try { catching, .. } =>
+                formatter.debug_struct("Try")
+                         .field("handlers", &"[..]")
+                         .field("catching", &catching)
+                         .finish(),
+            &StepState::Finally(ref outcome) => formatter.debug_tuple("Finally")
+                                                         .field(outcome)
+                                                         .finish(),
             &StepState::Complete(ref v) => formatter.debug_tuple("Complete")
                                                     .field(v)
                                                     .finish(),
-            &StepState::ArgCollectingLambda { ref procedure, ref evaluated, .. } =>
-                formatter.debug_struct("ArgCollectingLambda")
-                         .field("procedure", procedure)
+            &StepState::ArgCollecting { ref function, ref evaluated, .. } =>
+                formatter.debug_struct("ArgCollecting")
+                         .field("function", function)
                          .field("evaluated", evaluated)
                          .field("yet_to_be_evaluated", &"[..]")
                          .finish(),
-            &StepState::PreEvaluatedCallable { ref unevaluated } =>
+            &StepState::PreEvaluatedCallable { ref form } =>
                 formatter.debug_struct("PreEvaluatedCallable")
-                         .field("unevaluated", unevaluated)
+                         .field("unevaluated", &&form[1..])
                          .finish(),
-            &StepState::EvaluatingLambda { ref bodies, ref name, .. } =>
+            &StepState::EvaluatingLambda { ref bodies } =>
                 formatter.debug_struct("EvaluatingLambda")
-                         .field("name", name)
                          .field("bodies", bodies)
                          .field("env", &"{..}")
                          .finish(),
+            &StepState::Branch { ref then, ref otherwise } =>
+                formatter.debug_struct("Branch")
+                         .field("then", then)
+                         .field("otherwise", otherwise)
+                         .finish(),
+            &StepState::Binding { ref name, ref bindings, .. } =>
+                formatter.debug_struct("Binding")
+                         .field("name", name)
+                         .field("bindings", bindings)
+                         .field("bodies", &"[..]")
+                         .finish(),
+            &StepState::Logical { ref form, ref rest, .. } =>
+                formatter.debug_struct("Logical")
+                         .field("form", form)
+                         .field("rest", rest)
+                         .finish(),
+            &StepState::Assign { ref form, ref name } =>
+                formatter.debug_struct("Assign")
+                         .field("form", form)
+                         .field("name", name)
+                         .finish(),
+            &StepState::ForEach { ref function, ref rest, count } =>
+                formatter.debug_struct("ForEach")
+                         .field("function", function)
+                         .field("rest", rest)
+                         .field("count", &count)
+                         .finish(),
+            &StepState::Compiled(ref activation) => formatter.debug_tuple("Compiled")
+                                                             .field(activation)
+                                                             .finish(),
         }
     }
 }
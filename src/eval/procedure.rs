@@ -1,23 +1,36 @@
 use std::rc::Rc;
-use std::collections::HashMap;
 
 use {Value, AresError, AresResult, rc_to_usize, write_usize};
 
 pub use super::environment::{Env, Environment};
+use super::MemoryAccount;
+use super::compile::Chunk;
 use intern::Symbol;
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ParamBinding {
     pub params: Vec<Symbol>,
     pub rest: Option<Symbol>,
 }
 
+impl ParamBinding {
+    /// The names of the slots that the parameters are bound in: the
+    /// parameters, then the rest parameter.
+    pub fn slot_names(&self) -> Vec<Symbol> {
+        self.params.iter().cloned().chain(self.rest).collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct Procedure {
     pub name: Option<String>,
     pub bodies: Rc<Vec<Value>>,
     param_names: ParamBinding,
+    slot_names: Rc<Vec<Symbol>>,
     environment: Env,
+    /// The compiled bodies, if the lambda was compiled.
+    #[doc(hidden)]
+    pub code: Option<Rc<Chunk>>,
 }
 
 impl Procedure {
@@ -26,18 +39,34 @@ impl Procedure {
                param_names: ParamBinding,
                env: Env)
                -> Procedure {
+        let slot_names = Rc::new(param_names.slot_names());
+        Procedure::with_slot_names(name, bodies, param_names, slot_names, env)
+    }
+
+    /// Like `new`, but with the names from `ParamBinding::slot_names`, so that
+    /// lambdas made over and over can share them.
+    #[doc(hidden)]
+    pub fn with_slot_names(name: Option<String>,
+                           bodies: Rc<Vec<Value>>,
+                           param_names: ParamBinding,
+                           slot_names: Rc<Vec<Symbol>>,
+                           env: Env)
+                           -> Procedure {
         Procedure {
             name: name,
             bodies: bodies,
             param_names: param_names,
+            slot_names: slot_names,
             environment: env,
+            code: None,
         }
     }
 
-    pub fn gen_env(&self, params: Vec<Value>) -> AresResult<Env> {
+    /// The environment that the bodies run in when called with `params`.
+    /// The list of rest parameters is accounted for in `memory`.
+    pub fn gen_env(&self, params: &[Value], memory: &MemoryAccount) -> AresResult<Env> {
         let params_expected = self.param_names.params.len();
         let has_rest = self.param_names.rest.is_some();
-        // FIXME: remove this collect
         if params.len() < params_expected {
             return Err(AresError::UnexpectedArity {
                 found: params.len() as u16,
@@ -50,22 +79,14 @@ impl Procedure {
                                   params.len()),
             });
         }
-        let named: Vec<_> = params[..params_expected].into();
-        let mut bindings: HashMap<Symbol, Value> = self.param_names
-                                                       .params
-                                                       .iter()
-                                                       .cloned()
-                                                       .zip(named)
-                                                       .collect();
-        match self.param_names.rest {
-            Some(rest_sym) => {
-                let vec: Vec<_> = params[params_expected..].into();
-                let list: Value = vec.into();
-                bindings.insert(rest_sym.clone(), list);
-            }
-            None => (),
+        let mut slots = Vec::with_capacity(self.slot_names.len());
+        slots.extend(params[..params_expected].iter().cloned().map(Some));
+        if has_rest {
+            try!(memory.charge_list(params.len() - params_expected));
+            let vec: Vec<_> = params[params_expected..].into();
+            slots.push(Some(vec.into()));
         }
-        Ok(Environment::new_with_data(self.environment.clone(), bindings))
+        Ok(Environment::new_with_slots(self.environment.clone(), self.slot_names.clone(), slots))
     }
 }
 
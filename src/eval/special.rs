@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use {Value, AresError, AresResult};
+use stdlib::core;
+use stdlib::util::expect_arity;
+use util::RcSlice;
+
+use super::{StepState, Environment, push_env, push_bodies, do_apply};
+use super::{continuation, catch};
+use super::context::{LoadedContext, State};
+
+/// The forms that are evaluated by the evaluator itself rather than by a
+/// foreign function, so that the expressions inside of them are evaluated
+/// on the step stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialForm {
+    If,
+    Let,
+    And,
+    Or,
+    Xor,
+    Define,
+    DefineMacro,
+    Set,
+    Quote,
+    Lambda,
+    CallCc,
+    CallEc,
+    Try,
+    Apply,
+    ForEach,
+}
+
+impl SpecialForm {
+    /// Whether the arguments are evaluated before the form starts, like
+    /// they are for a function.
+    pub fn evaluates_args(self) -> bool {
+        match self {
+            SpecialForm::CallCc |
+            SpecialForm::CallEc |
+            SpecialForm::Apply |
+            SpecialForm::ForEach => true,
+            _ => false,
+        }
+    }
+}
+
+/// Starts evaluating a special form, given its arguments.
+pub fn start<S: ?Sized>(form: SpecialForm,
+                        args: RcSlice,
+                        ctx: &mut LoadedContext<S>)
+                        -> AresResult<()>
+    where S: State
+{
+    match form {
+        // These don't evaluate anything, but compiled lambdas have their own
+        // versions of them.
+        SpecialForm::Quote => {
+            let value = try!(core::quote(&args, ctx));
+            ctx.stack.push(StepState::Complete(value));
+        }
+        SpecialForm::Lambda => {
+            let value = try!(core::lambda(&args, ctx));
+            ctx.stack.push(StepState::Complete(value));
+        }
+        SpecialForm::CallCc | SpecialForm::CallEc => {
+            try!(expect_arity(&args, |l| l == 1, "exactly 1"));
+            let continuation = continuation::capture(form == SpecialForm::CallEc, ctx);
+            let mut call_args = ctx.arg_buffer(1);
+            call_args.push(continuation);
+            try!(do_apply(args[0].clone(), call_args, None, ctx));
+        }
+        SpecialForm::Try => try!(catch::start(args, ctx)),
+        SpecialForm::Apply => {
+            try!(expect_arity(&args, |l| l == 2, "exactly 2"));
+            let call_args = try!(list_arg(&args[1])).to_vec();
+            try!(do_apply(args[0].clone(), call_args, None, ctx));
+        }
+        SpecialForm::ForEach => {
+            try!(expect_arity(&args, |l| l == 2, "exactly 2"));
+            let elements = try!(list_arg(&args[0]));
+            try!(next_element(args[1].clone(), elements, 0, ctx));
+        }
+        SpecialForm::If => {
+            try!(expect_arity(&args, |l| l == 3, "exactly 3"));
+            ctx.stack.push(StepState::Branch {
+                then: args[1].clone(),
+                otherwise: args[2].clone(),
+            });
+            ctx.stack.push(StepState::EvalThis(args[0].clone(), false));
+        }
+        SpecialForm::Let => {
+            try!(expect_arity(&args, |l| l >= 2, "at least 2"));
+            let bodies = args.slice(1, args.len());
+            let bindings = match args[0] {
+                Value::List(ref inner) => RcSlice::from_rc(inner.clone()),
+                ref other => return Err(AresError::UnexpectedType {
+                    value: other.clone(),
+                    expected: "List".into(),
+                }),
+            };
+            try!(expect_arity(&bindings, |l| l % 2 == 0, "an even number"));
+            for name in bindings.iter().step_by(2) {
+                if let Value::Symbol(_) = *name {
+                    continue;
+                }
+                return Err(AresError::UnexpectedType {
+                    value: name.clone(),
+                    expected: "Symbol".into(),
+                });
+            }
+
+            let new_env = Environment::new_with_data(ctx.env().clone(), HashMap::new());
+            push_env(new_env, None, ctx);
+            next_binding(bindings, bodies, ctx);
+        }
+        SpecialForm::And | SpecialForm::Or | SpecialForm::Xor => {
+            next_operand(form, args, false, false, ctx);
+        }
+        SpecialForm::Define | SpecialForm::DefineMacro | SpecialForm::Set => {
+            try!(expect_arity(&args, |l| l == 2, "exactly 2"));
+            let name = match args[0] {
+                Value::Symbol(s) => s,
+                ref other => return Err(AresError::UnexpectedType {
+                    value: other.clone(),
+                    expected: "Symbol".into(),
+                }),
+            };
+            if form == SpecialForm::Set {
+                if !ctx.env().borrow().is_defined(name) {
+                    return Err(AresError::UndefinedName(ctx.interner().lookup_or_anon(name)));
+                }
+            } else if ctx.env().borrow().is_defined_at_this_level(name) {
+                return Err(AresError::AlreadyDefined(ctx.interner().lookup_or_anon(name)));
+            }
+            ctx.stack.push(StepState::Assign {
+                form: form,
+                name: name,
+            });
+            ctx.stack.push(StepState::EvalThis(args[1].clone(), false));
+        }
+    }
+    Ok(())
+}
+
+/// Carries on with a special form now that `value` has been computed.
+pub fn resume<S: ?Sized>(state: StepState, value: Value, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    match state {
+        StepState::Branch { then, otherwise } => {
+            // Both branches are in tail position.
+            let branch = match value {
+                Value::Bool(true) => then,
+                Value::Bool(false) => otherwise,
+                other => return Err(AresError::UnexpectedType {
+                    value: other,
+                    expected: "Bool".into(),
+                }),
+            };
+            ctx.stack.push(StepState::EvalThis(branch, false));
+        }
+        StepState::Binding { name, bindings, bodies } => {
+            ctx.env().borrow_mut().insert_here(name, value);
+            next_binding(bindings, bodies, ctx);
+        }
+        StepState::Logical { form, rest, seen_true, seen_false } => {
+            let b = match value {
+                Value::Bool(b) => b,
+                other => return Err(AresError::UnexpectedType {
+                    value: other,
+                    expected: "Bool".into(),
+                }),
+            };
+            let (seen_true, seen_false) = (seen_true || b, seen_false || !b);
+            let done = match form {
+                SpecialForm::And if !b => Some(false),
+                SpecialForm::Or if b => Some(true),
+                SpecialForm::Xor if seen_true && seen_false => Some(true),
+                _ => None,
+            };
+            match done {
+                Some(result) => ctx.stack.push(StepState::Complete(Value::Bool(result))),
+                None => next_operand(form, rest, seen_true, seen_false, ctx),
+            }
+        }
+        StepState::ForEach { function, rest, count } => {
+            try!(next_element(function, rest, count + 1, ctx));
+        }
+        StepState::Assign { form, name } => {
+            let value = match (form, value) {
+                (SpecialForm::Set, value) => value,
+                // Defined lambdas are named after what they are defined as,
+                // which shows up in backtraces.
+                (_, Value::Lambda(mut procedure, is_macro)) => {
+                    if procedure.name.is_none() {
+                        Rc::make_mut(&mut procedure).name = Some(ctx.interner().lookup_or_anon(name));
+                    }
+                    Value::Lambda(procedure, is_macro || form == SpecialForm::DefineMacro)
+                }
+                (SpecialForm::DefineMacro, other) => return Err(AresError::UnexpectedType {
+                    value: other,
+                    expected: "Lambda".into(),
+                }),
+                (_, value) => value,
+            };
+            if form == SpecialForm::Set {
+                ctx.env().borrow_mut().with_value_mut(name, |v| *v = value.clone());
+            } else {
+                ctx.env().borrow_mut().insert_here(name, value.clone());
+            }
+            ctx.stack.push(StepState::Complete(value));
+        }
+        other => panic!("special::resume(..): {:?} is not a special form", other),
+    }
+    Ok(())
+}
+
+/// Evaluates the next `let` binding, or the bodies once there are none
+/// left.  `bindings` are names followed by values, and the names have
+/// already been checked.
+fn next_binding<S: ?Sized>(bindings: RcSlice, bodies: RcSlice, ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    if bindings.is_empty() {
+        // The last body is in tail position.
+        return push_bodies(bodies, ctx);
+    }
+    let name = match bindings[0] {
+        Value::Symbol(name) => name,
+        _ => unreachable!(),
+    };
+    let value = bindings[1].clone();
+    ctx.stack.push(StepState::Binding {
+        name: name,
+        bindings: bindings.slice(2, bindings.len()),
+        bodies: bodies,
+    });
+    ctx.stack.push(StepState::EvalThis(value, false));
+}
+
+/// Evaluates the next operand of `and`, `or` or `xor`, or finishes if there
+/// are none left.
+fn next_operand<S: ?Sized>(form: SpecialForm,
+                           mut rest: RcSlice,
+                           seen_true: bool,
+                           seen_false: bool,
+                           ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    match rest.pop_front() {
+        Some(operand) => {
+            ctx.stack.push(StepState::Logical {
+                form: form,
+                rest: rest,
+                seen_true: seen_true,
+                seen_false: seen_false,
+            });
+            ctx.stack.push(StepState::EvalThis(operand, false));
+        }
+        None => {
+            // Only `and` is true when every operand has been looked at.
+            let result = form == SpecialForm::And;
+            ctx.stack.push(StepState::Complete(Value::Bool(result)));
+        }
+    }
+}
+
+/// The elements of `value`, which has to be a list.
+fn list_arg(value: &Value) -> AresResult<RcSlice> {
+    match *value {
+        Value::List(ref list) => Ok(RcSlice::from_rc(list.clone())),
+        ref other => Err(AresError::UnexpectedType {
+            value: other.clone(),
+            expected: "List".into(),
+        }),
+    }
+}
+
+/// Calls `function` with the next of the elements of a `for-each`, or
+/// finishes with how many it was called with if there are none left.
+fn next_element<S: ?Sized>(function: Value,
+                           mut rest: RcSlice,
+                           count: i64,
+                           ctx: &mut LoadedContext<S>)
+                           -> AresResult<()>
+    where S: State
+{
+    match rest.pop_front() {
+        Some(element) => {
+            let mut call_args = ctx.arg_buffer(1);
+            call_args.push(element);
+            ctx.stack.push(StepState::ForEach {
+                function: function.clone(),
+                rest: rest,
+                count: count,
+            });
+            do_apply(function, call_args, None, ctx)
+        }
+        None => {
+            ctx.stack.push(StepState::Complete(Value::Int(count)));
+            Ok(())
+        }
+    }
+}
@@ -1,106 +1,99 @@
 use super::StepState;
 
 use {Value, AresError, AresResult};
+use std::rc::Rc;
 
-use super::procedure::Procedure;
 use super::context::{LoadedContext, State};
 
-use super::{apply_lambda, apply_function};
+use util::RcSlice;
 
-/// Transforms a pre-evaluated callable into a
-/// collecting-args lambda.
-pub fn from_pre_evaluated<S: ?Sized>(mut unevaluated: Vec<Value>,
-                                     function: Value,
+use super::{FfType, apply_ast_function, do_apply};
+
+/// Transforms a pre-evaluated callable into one that collects its
+/// arguments, which are `form[1..]`.
+pub fn from_pre_evaluated<S: ?Sized>(function: Value,
+                                     form: Rc<Vec<Value>>,
                                      ctx: &mut LoadedContext<S>)
                                      -> AresResult<()>
     where S: State
 {
-    // Check to make sure that we actually got something that is callable
-    let procedure = match function {
-        Value::Lambda(procedure, _) => procedure,
+    // Check to make sure that we actually got something that is callable.
+    // Ast functions get their arguments as they are.
+    let function = match function {
         Value::ForeignFn(func) => {
-            let apply_result = try!(apply_function(func, unevaluated, ctx));
-            ctx.stack.push(StepState::Complete(apply_result));
-            return Ok(());
+            if func.typ == FfType::Ast {
+                return apply_ast_function(func, form, ctx);
+            }
+            Value::ForeignFn(func)
         }
-        other => return Err(AresError::UnexecutableValue(other.clone())),
+        callable@Value::Lambda(..) |
+        callable@Value::Keyword(_) => callable,
+        other => return Err(AresError::UnexecutableValue(other)),
     };
 
-    // we will be wanting the "next" element very often,
-    // so reverse this right now and call `pop` to get the next one
-    // in the correct order.
-    unevaluated.reverse();
-
-    if let Some(first) = unevaluated.pop() {
+    if form.len() > 1 {
         // If we have at least one argument to pass in, start evaluating
-        // that one, and build up an ArgCollectingLambda in order to
+        // that one, and build up an ArgCollecting in order to
         // collect all the evaluated arguments.
-        ctx.stack.push(StepState::ArgCollectingLambda {
-            procedure: procedure,
-            evaluated: vec![],
-            unevaluated: unevaluated,
+        let first = form[1].clone();
+        let evaluated = ctx.arg_buffer(form.len() - 1);
+        ctx.stack.push(StepState::ArgCollecting {
+            function: function,
+            evaluated: evaluated,
+            next: 2,
+            form: form,
         });
         ctx.stack.push(StepState::EvalThis(first, false));
     } else {
         //  If there's no arguments, we can just apply the
-        //  lambda right now.
-        try!(apply_lambda(procedure, vec![], ctx));
+        //  function right now.
+        try!(do_apply(function, vec![], Some(form), ctx));
     }
     Ok(())
 }
 
-/// This is called when an arg_collecting_lambda gets one of its
-/// arguments evaluated.
-pub fn from_arg_collecting_lambda<S: ?Sized>(procedure: Procedure,
-                                             mut unevaluated: Vec<Value>,
-                                             mut evaluated: Vec<Value>,
-                                             completed: Value,
-                                             ctx: &mut LoadedContext<S>)
-                                             -> AresResult<()>
+/// This is called when an arg-collecting function gets one of its
+/// arguments evaluated.  The arguments that are left are `form[next..]`.
+pub fn from_arg_collecting<S: ?Sized>(function: Value,
+                                      mut evaluated: Vec<Value>,
+                                      next: usize,
+                                      completed: Value,
+                                      form: Rc<Vec<Value>>,
+                                      ctx: &mut LoadedContext<S>)
+                                      -> AresResult<()>
     where S: State
 {
     // Push the completed arg-value back on the list of
     // evaluated args.
     evaluated.push(completed);
 
-    if let Some(next) = unevaluated.pop() {
+    if next < form.len() {
         // If there's another argument to evaluate, do that
-        ctx.stack.push(StepState::ArgCollectingLambda {
-            procedure: procedure,
+        let arg = form[next].clone();
+        ctx.stack.push(StepState::ArgCollecting {
+            function: function,
             evaluated: evaluated,
-            unevaluated: unevaluated,
+            next: next + 1,
+            form: form,
         });
-        ctx.stack.push(StepState::EvalThis(next, false));
+        ctx.stack.push(StepState::EvalThis(arg, false));
     } else {
-        // Otherwise call the lambda right now!
-        try!(apply_lambda(procedure, evaluated, ctx));
+        // Otherwise call the function right now!
+        try!(do_apply(function, evaluated, Some(form), ctx));
     }
     Ok(())
 }
 
 /// This is called when an evaluating-lambda has finished evaluating
-/// one of the bodies of a lambda and has produced a result.
-pub fn from_evaluating_lambda<S: ?Sized>(mut bodies: Vec<Value>,
-                                         name: Option<String>,
-                                         past_body_result: Value,
-                                         ctx: &mut LoadedContext<S>)
-                                         -> AresResult<()>
+/// one of the bodies of a lambda, which aren't the last.
+pub fn from_evaluating_lambda<S: ?Sized>(mut bodies: RcSlice, ctx: &mut LoadedContext<S>)
     where S: State
 {
-    if let Some(next_body) = bodies.pop() {
-        // If this isn't the last body in the lambda, start evaluating the
-        // next one.
-        let body_eval = StepState::EvalThis(next_body, false);
-        let watching_state = StepState::EvaluatingLambda {
-            name: name,
-            bodies: bodies,
-        };
-        ctx.stack.push(watching_state);
-        ctx.stack.push(body_eval);
-    } else {
-        // If this was the last body in the lambda, then
-        // this is the result of that lambda being executed.
-        ctx.stack.push(StepState::Complete(past_body_result));
+    // Start evaluating the next body.  Nothing needs to watch the last body,
+    // which leaves it in tail position.
+    let next_body = bodies.pop_front().unwrap();
+    if !bodies.is_empty() {
+        ctx.stack.push(StepState::EvaluatingLambda { bodies: bodies });
     }
-    Ok(())
+    ctx.stack.push(StepState::EvalThis(next_body, false));
 }
@@ -0,0 +1,346 @@
+use std::rc::Rc;
+
+use {Value, AresError, AresResult};
+use intern::Symbol;
+
+use super::{StepState, Environment, Procedure, FfType, do_apply,
+            locate_error};
+use super::compile::{Chunk, Op, NO_FORM};
+use super::context::{LoadedContext, State};
+use super::transformations::from_pre_evaluated;
+
+/// A call to a compiled lambda that is running.
+#[derive(Debug, Clone)]
+pub struct Activation {
+    code: Rc<Chunk>,
+    pc: usize,
+    values: Vec<Value>,
+    // The calls whose arguments are being pushed: where the function is in
+    // `values`, and which form the call is.
+    calls: Vec<(usize, u32)>,
+    // How many environments `let`s have pushed that are still there.
+    envs: usize,
+}
+
+impl Activation {
+    pub fn new(code: Rc<Chunk>) -> Activation {
+        Activation {
+            pc: 0,
+            values: Vec::with_capacity(code.max_values),
+            calls: Vec::with_capacity(code.max_calls),
+            envs: 0,
+            code: code,
+        }
+    }
+
+    /// The call that the last op run was a part of.
+    pub fn form(&self) -> Option<&Rc<Vec<Value>>> {
+        if self.pc == 0 {
+            return None;
+        }
+        match self.code.op_forms[self.pc - 1] {
+            NO_FORM => None,
+            form => Some(&self.code.forms[form as usize]),
+        }
+    }
+
+    /// The calls whose arguments are being evaluated, innermost first.  Each
+    /// has the function, the arguments evaluated so far and the form.
+    pub fn pending_calls(&self) -> Vec<(&Value, &[Value], &Rc<Vec<Value>>)> {
+        let mut calls = Vec::with_capacity(self.calls.len());
+        let mut end = self.values.len();
+        for &(at, form) in self.calls.iter().rev() {
+            calls.push((&self.values[at], &self.values[at + 1..end], &self.code.forms[form as usize]));
+            end = at;
+        }
+        calls
+    }
+
+    /// How many environments this has pushed on the environment stack.
+    pub fn envs(&self) -> usize {
+        self.envs
+    }
+}
+
+/// How many activations that have returned are kept around to be used again.
+const MAX_SPARE_ACTIVATIONS: usize = 16;
+
+/// Starts a call to `code`, using an activation that has returned if there
+/// is one.
+pub fn activation<S: ?Sized>(code: Rc<Chunk>, ctx: &mut LoadedContext<S>) -> Box<Activation>
+    where S: State
+{
+    match ctx.activations.pop() {
+        Some(mut activation) => {
+            activation.values.reserve(code.max_values);
+            activation.calls.reserve(code.max_calls);
+            activation.pc = 0;
+            activation.code = code;
+            activation
+        }
+        None => Box::new(Activation::new(code)),
+    }
+}
+
+/// Keeps an activation that is done around for `activation` to use.
+fn retire<S: ?Sized>(act: Box<Activation>, ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    if ctx.activations.len() < MAX_SPARE_ACTIVATIONS {
+        debug_assert!(act.values.is_empty() && act.calls.is_empty() && act.envs == 0);
+        ctx.activations.push(act);
+    }
+}
+
+/// Carries on with `activation` now that `value` has been computed.
+pub fn resume<S: ?Sized>(mut activation: Box<Activation>,
+                         value: Value,
+                         ctx: &mut LoadedContext<S>)
+                         -> AresResult<()>
+    where S: State
+{
+    activation.values.push(value);
+    run(activation, ctx)
+}
+
+/// Runs `activation` until it finishes or makes a call.  Like in the step
+/// evaluator, every call takes a step of its own, so that fuel and
+/// executions count the work that compiled lambdas do.
+pub fn run<S: ?Sized>(mut act: Box<Activation>, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    let code = act.code.clone();
+    loop {
+        let pc = act.pc;
+        act.pc += 1;
+        match code.ops[pc] {
+            Op::Const(ref value) => act.values.push(value.clone()),
+            Op::Load(symbol) => {
+                match lookup(symbol, false, ctx) {
+                    Ok(value) => act.values.push(value),
+                    Err(e) => return fail(act, e, ctx),
+                }
+            }
+            Op::LoadHead(symbol) => {
+                match lookup(symbol, true, ctx) {
+                    Ok(value) => act.values.push(value),
+                    Err(e) => return fail(act, e, ctx),
+                }
+            }
+            Op::LoadSlot { depth, slot, name, head } => {
+                let value = ctx.env().borrow().get_slot(depth as usize, slot as usize, name);
+                let value = match value {
+                    Some(Value::ForeignFn(ref f)) if f.typ == FfType::Ast && !head => {
+                        Err(AresError::AstFunctionPass)
+                    }
+                    Some(value) => Ok(value),
+                    None => lookup(name, head, ctx),
+                };
+                match value {
+                    Ok(value) => act.values.push(value),
+                    Err(e) => return fail(act, e, ctx),
+                }
+            }
+            Op::Eval(ref value) => {
+                let value = value.clone();
+                let form = act.form().cloned();
+                return hand_off(act, false, form, ctx, move |ctx| {
+                    ctx.stack.push(StepState::EvalThis(value, false));
+                    Ok(())
+                });
+            }
+            Op::Callee { form, end, tail } => {
+                let unevaluated = match act.values.last() {
+                    Some(&Value::ForeignFn(ref f)) => f.typ == FfType::Ast,
+                    Some(&Value::Lambda(..)) |
+                    Some(&Value::Keyword(_)) => false,
+                    _ => {
+                        let other = act.values.pop().unwrap();
+                        return fail(act, AresError::UnexecutableValue(other), ctx);
+                    }
+                };
+                if unevaluated {
+                    let callee = act.values.pop().unwrap();
+                    return call_form(act, callee, form, end, tail, ctx);
+                } else {
+                    let at = act.values.len() - 1;
+                    act.calls.push((at, form));
+                }
+            }
+            Op::Call { argc, tail } => {
+                let (at, form) = act.calls.pop().unwrap();
+                debug_assert_eq!(act.values.len() - at - 1, argc as usize);
+                let mut args = ctx.arg_buffer(argc as usize);
+                args.extend(act.values.drain(at + 1..));
+                let callee = act.values.pop().unwrap();
+                let form = code.forms[form as usize].clone();
+                let tail = tail &&
+                           match callee {
+                    // A lambda doesn't need the environments of the `let`s
+                    // that it is called from, so tail calls leave them.
+                    Value::Lambda(..) => {
+                        leave_lets(&mut act, ctx);
+                        true
+                    }
+                    _ => act.envs == 0,
+                };
+                let error_form = Some(form.clone());
+                return hand_off(act, tail, error_form, ctx, move |ctx| {
+                    do_apply(callee, args, Some(form), ctx)
+                });
+            }
+            Op::Special { special, form, end, tail } => {
+                let callee = act.values.pop().unwrap();
+                let is_special = match callee {
+                    Value::ForeignFn(ref function) => function.special == Some(special),
+                    _ => false,
+                };
+                if !is_special {
+                    // Whatever it is now, call it the slow way.
+                    return call_form(act, callee, form, end, tail, ctx);
+                }
+            }
+            Op::JumpIfFalse(to) => {
+                match act.values.pop().unwrap() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => act.pc = to as usize,
+                    other => return fail(act, not_a_bool(other), ctx),
+                }
+            }
+            Op::Jump(to) => act.pc = to as usize,
+            Op::ShortCircuit { on, to } => {
+                match act.values.pop().unwrap() {
+                    Value::Bool(b) if b == on => {
+                        act.values.push(Value::Bool(on));
+                        act.pc = to as usize;
+                    }
+                    Value::Bool(_) => {}
+                    other => return fail(act, not_a_bool(other), ctx),
+                }
+            }
+            Op::Pop => {
+                act.values.pop();
+            }
+            Op::EnterLet(ref names) => {
+                let slots = vec![None; names.len()];
+                let env = Environment::new_with_slots(ctx.env().clone(), names.clone(), slots);
+                ctx.env_stack.push(env);
+                act.envs += 1;
+            }
+            Op::Bind(slot) => {
+                let value = act.values.pop().unwrap();
+                ctx.env().borrow_mut().set_slot(slot as usize, value);
+            }
+            Op::ExitLet => {
+                ctx.env_stack.pop();
+                act.envs -= 1;
+            }
+            Op::Closure(index) => {
+                let prototype = &code.lambdas[index as usize];
+                let mut procedure = Procedure::with_slot_names(None,
+                                                               prototype.bodies.clone(),
+                                                               prototype.params.clone(),
+                                                               prototype.slot_names.clone(),
+                                                               ctx.env().clone());
+                procedure.code = Some(prototype.code.clone());
+                act.values.push(Value::lambda(procedure, false));
+            }
+            Op::Return => {
+                debug_assert_eq!(act.envs, 0);
+                let value = act.values.pop().unwrap();
+                ctx.stack.push(StepState::Complete(value));
+                retire(act, ctx);
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn lookup<S: ?Sized>(symbol: Symbol, head: bool, ctx: &LoadedContext<S>) -> AresResult<Value>
+    where S: State
+{
+    match ctx.env().borrow().get(symbol) {
+        // Ast functions can only be called.
+        Some(Value::ForeignFn(ref f)) if f.typ == FfType::Ast && !head => {
+            Err(AresError::AstFunctionPass)
+        }
+        Some(value) => Ok(value),
+        None => Err(AresError::UndefinedName(ctx.interner().lookup_or_anon(symbol))),
+    }
+}
+
+fn not_a_bool(value: Value) -> AresError {
+    AresError::UnexpectedType {
+        value: value,
+        expected: "Bool".into(),
+    }
+}
+
+/// Pops the environments of the `let`s that `act` is in.
+fn leave_lets<S: ?Sized>(act: &mut Activation, ctx: &mut LoadedContext<S>)
+    where S: State
+{
+    for _ in 0..act.envs {
+        ctx.env_stack.pop();
+    }
+    act.envs = 0;
+}
+
+/// Puts `act` back on the stack, where it will be cleaned up, and fails
+/// with `error`.
+fn fail<S: ?Sized>(act: Box<Activation>, error: AresError, ctx: &mut LoadedContext<S>) -> AresResult<()>
+    where S: State
+{
+    let form = act.form().cloned();
+    ctx.stack.push(StepState::Compiled(act));
+    Err(locate_error(error, form, ctx))
+}
+
+/// Evaluates the call `form` to `callee` with the step evaluator, carrying
+/// on at `end` once it is done.
+fn call_form<S: ?Sized>(mut act: Box<Activation>,
+                        callee: Value,
+                        form: u32,
+                        end: u32,
+                        tail: bool,
+                        ctx: &mut LoadedContext<S>)
+                        -> AresResult<()>
+    where S: State
+{
+    let form = act.code.forms[form as usize].clone();
+    act.pc = end as usize;
+    let tail = tail && act.envs == 0;
+    let error_form = Some(form.clone());
+    hand_off(act, tail, error_form, ctx, move |ctx| from_pre_evaluated(callee, form, ctx))
+}
+
+/// Lets `f` push states that compute the next value of `act`.
+///
+/// Unless this is in tail position, `act` waits for the value on the stack.
+/// In tail position, `act` is done, and the value is the value of the
+/// lambda.
+fn hand_off<S: ?Sized, F>(act: Box<Activation>,
+                          tail: bool,
+                          error_form: Option<Rc<Vec<Value>>>,
+                          ctx: &mut LoadedContext<S>,
+                          f: F)
+                          -> AresResult<()>
+    where S: State,
+          F: FnOnce(&mut LoadedContext<S>) -> AresResult<()>
+{
+    if tail {
+        debug_assert!(act.calls.is_empty() && act.envs == 0);
+        if let Err(e) = f(ctx) {
+            ctx.stack.push(StepState::Compiled(act));
+            return Err(locate_error(e, error_form, ctx));
+        }
+        retire(act, ctx);
+        return Ok(());
+    }
+
+    ctx.stack.push(StepState::Compiled(act));
+    if let Err(e) = f(ctx) {
+        return Err(locate_error(e, error_form, ctx));
+    }
+    Ok(())
+}
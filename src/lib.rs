@@ -9,9 +9,15 @@ mod error;
 pub mod util;
 pub mod intern;
 
-pub use parse::parse;
+pub use parse::{parse, parse_with_spans, parse_with_macros, parse_source, parse_recovering,
+                parse_recovering_with_macros, Recovered, ParseError, Position, SourceId,
+                SourceNames, Span, SpanTable, Reader, ReadResult, ReadStatus, Cst, Node, NodeKind,
+                Open, Close, FormLike, ReaderMacros, ReaderMacro, classify_tokens,
+                ClassifiedTokens, ClassifiedToken, TokenClass, DEFAULT_MAX_NESTING};
 pub use eval::{user_fn, free_fn, ast_fn, Procedure, ForeignFunction, Env, Environment,
-               ParamBinding, Context, LoadedContext, State};
+               ParamBinding, Context, LoadedContext, State, Execution, ExecutionStatus,
+               MemoryAccount, MemoryStats, InterruptHandle,
+               Backtrace, Frame};
 pub use error::{AresError, AresResult};
 
 macro_rules! gen_from {
@@ -27,6 +33,12 @@ macro_rules! gen_from {
     }
 }
 
+/// A value in Ares.
+///
+/// Numbers, booleans, characters and symbols are stored inline, and
+/// everything else is behind a single `Rc`, so values are two words big and
+/// cloning one never copies more than a reference count.  The constructors
+/// and `as_*` accessors below hide how the bigger variants are stored.
 #[derive(Debug, Clone)]
 pub enum Value {
     List(Rc<Vec<Value>>),
@@ -34,14 +46,16 @@ pub enum Value {
     Float(f64),
     Int(i64),
     Bool(bool),
-    Option(Option<Box<Value>>),
+    Char(char),
+    Option(Option<Rc<Value>>),
     Map(Rc<HashMap<Value, Value>>),
 
     Symbol(intern::Symbol),
-    ForeignFn(ForeignFunction<()>),
-    Lambda(Procedure, bool),
+    Keyword(intern::Symbol),
+    ForeignFn(Rc<ForeignFunction<()>>),
+    Lambda(Rc<Procedure>, bool),
 
-    UserData(Rc<Any>),
+    UserData(Rc<Box<Any>>),
 }
 
 impl Value {
@@ -54,7 +68,112 @@ impl Value {
     }
 
     pub fn user_data<T: Any>(t: T) -> Value {
-        Value::UserData(Rc::new(t) as Rc<Any>)
+        Value::UserData(Rc::new(Box::new(t) as Box<Any>))
+    }
+
+    pub fn option(o: Option<Value>) -> Value {
+        Value::Option(o.map(Rc::new))
+    }
+
+    pub fn foreign_fn<S: State + ?Sized>(f: ForeignFunction<S>) -> Value {
+        Value::ForeignFn(Rc::new(f.erase()))
+    }
+
+    pub fn lambda(procedure: Procedure, is_macro: bool) -> Value {
+        Value::Lambda(Rc::new(procedure), is_macro)
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Value::Int(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Value::Float(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_char(&self) -> Option<char> {
+        match *self {
+            Value::Char(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match *self {
+            Value::List(ref l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&HashMap<Value, Value>> {
+        match *self {
+            Value::Map(ref m) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn as_symbol(&self) -> Option<intern::Symbol> {
+        match *self {
+            Value::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_keyword(&self) -> Option<intern::Symbol> {
+        match *self {
+            Value::Keyword(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// `Some` with what is in the option if this is an option.
+    pub fn as_option(&self) -> Option<Option<&Value>> {
+        match *self {
+            Value::Option(ref o) => Some(o.as_ref().map(|v| &**v)),
+            _ => None,
+        }
+    }
+
+    pub fn as_foreign_fn(&self) -> Option<&ForeignFunction<()>> {
+        match *self {
+            Value::ForeignFn(ref f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// The procedure, and whether it is a macro, if this is a lambda.
+    pub fn as_lambda(&self) -> Option<(&Procedure, bool)> {
+        match *self {
+            Value::Lambda(ref p, is_macro) => Some((p, is_macro)),
+            _ => None,
+        }
+    }
+
+    pub fn as_user_data<T: Any>(&self) -> Option<&T> {
+        match *self {
+            Value::UserData(ref u) => u.downcast_ref(),
+            _ => None,
+        }
     }
 }
 
@@ -73,12 +192,14 @@ gen_from!(f64, Value::Float);
 
 gen_from!(bool, Value::Bool);
 
+gen_from!(char, Value::Char);
+
 gen_from!(String, Value::String, Rc::new);
 
 impl <T: Into<Value>> From<Option<T>> for Value {
     fn from(x: Option<T>) -> Value {
         match x {
-            Some(v) => Value::Option(Some(Box::new(v.into()))),
+            Some(v) => Value::Option(Some(Rc::new(v.into()))),
             None => Value::Option(None)
         }
     }
@@ -116,8 +237,10 @@ impl PartialEq for Value {
             (&Float(f1), &Float(f2)) => f1 == f2,
             (&Int(i1), &Int(i2)) => i1 == i2,
             (&Bool(b1), &Bool(b2)) => b1 == b2,
+            (&Char(c1), &Char(c2)) => c1 == c2,
             (&Value::Option(ref o1), &Value::Option(ref o2)) => o1 == o2,
             (&Symbol(ref id1), &Symbol(ref id2)) => id1 == id2,
+            (&Keyword(ref id1), &Keyword(ref id2)) => id1 == id2,
             (&ForeignFn(ref ff1), &ForeignFn(ref ff2)) => ff1 == ff2,
             (&Lambda(ref l1, b1), &Lambda(ref l2, b2)) => l1 == l2 && b1 == b2,
             (&Map(ref m1), &Map(ref m2)) => m1 == m2,
@@ -147,8 +270,14 @@ impl std::hash::Hash for Value {
                 };
                 state.write(&[byte])
             },
+            &Value::Char(c) => c.hash(state),
             &Value::Option(ref o) => o.hash(state),
             &Value::Symbol(ref rc) => rc.hash(state),
+            &Value::Keyword(ref rc) => {
+                // Don't collide with the symbol of the same name.
+                state.write(&[b':']);
+                rc.hash(state)
+            }
             &Value::ForeignFn(ref ff) => ff.hash(state),
             &Value::Lambda(ref p, ref b) => {
                 p.hash(state);
@@ -172,6 +301,6 @@ fn write_usize<H: ::std::hash::Hasher>(v: usize, hasher: &mut H) {
 }
 
 fn rc_to_usize<T: ?Sized>(rc: &Rc<T>) -> usize {
-    use std::mem::transmute;
-    unsafe { transmute(&*rc) }
+    // The address of the shared value, not of this particular handle to it.
+    &**rc as *const T as *const u8 as usize
 }
@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+use parse::{Position, Span, SourceId};
+use parse::tokens::{TokenIter, TokenType, Open, Close, FormLike};
+
+/// What kind of thing a `ClassifiedToken` is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenClass {
+    Open(Open),
+    Close(Close),
+    /// A reader shorthand like `'` or `~@`.
+    FormLike(FormLike),
+    String,
+    Char,
+    Number,
+    Symbol,
+    Bool,
+    Keyword,
+    /// A reader macro tag like `#color`.
+    ReaderMacro,
+    /// A line, block or datum comment.
+    Comment,
+    /// Input that could not be tokenized, with a description of what is
+    /// wrong with it.
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassifiedToken {
+    pub class: TokenClass,
+    /// The bytes of the input that the token covers.
+    pub range: Range<usize>,
+    /// From the first character of the token to just past the last one.
+    pub span: Span,
+}
+
+/// Splits `input` into classified tokens, for syntax highlighting and the
+/// like.
+///
+/// This uses the same lexer as the parser, so it agrees with the parser on
+/// what every token is.  It never stops early: input that can't be
+/// tokenized comes out as an `Error` token running up to the next
+/// delimiter or whitespace, and tokenizing carries on from there.
+/// Whitespace isn't included.
+pub fn classify_tokens(input: &str) -> ClassifiedTokens<'_> {
+    ClassifiedTokens {
+        input: input,
+        tokens: TokenIter::new(input),
+        cursor: 0,
+        cursor_pos: Position::start_of(SourceId::anonymous()),
+    }
+}
+
+pub struct ClassifiedTokens<'a> {
+    input: &'a str,
+    tokens: TokenIter<'a>,
+    // Everything before `cursor` is at or before `cursor_pos`.
+    cursor: usize,
+    cursor_pos: Position,
+}
+
+impl<'a> ClassifiedTokens<'a> {
+    /// The position of the character starting at byte `offset`.  Offsets
+    /// have to be asked for in increasing order.
+    fn position_of(&mut self, offset: usize) -> Position {
+        for c in self.input[self.cursor..offset].chars() {
+            self.cursor_pos.advance(c);
+        }
+        self.cursor = offset;
+        let mut pos = self.cursor_pos;
+        if let Some(c) = self.input[offset..].chars().next() {
+            pos.advance(c);
+        }
+        pos
+    }
+
+    fn classified(&mut self, class: TokenClass, range: Range<usize>) -> ClassifiedToken {
+        let start = self.position_of(range.start);
+        let last = self.input[..range.end].char_indices().next_back().map_or(0, |(i, _)| i);
+        let end = self.position_of(last).next();
+        ClassifiedToken {
+            class: class,
+            range: range,
+            span: Span::new(start, end),
+        }
+    }
+}
+
+impl<'a> Iterator for ClassifiedTokens<'a> {
+    type Item = ClassifiedToken;
+
+    fn next(&mut self) -> Option<ClassifiedToken> {
+        if let Some((range, skipped)) = self.tokens.next_comment() {
+            let class = match skipped {
+                Ok(()) => TokenClass::Comment,
+                Err(e) => TokenClass::Error(e.to_string()),
+            };
+            // Line comments take their newline with them.
+            let end = range.start + self.input[range.clone()].trim_end().len();
+            return Some(self.classified(class, range.start..end));
+        }
+
+        let start = self.tokens.offset();
+        match self.tokens.next() {
+            None => None,
+            Some(Ok(tok)) => {
+                let class = match tok.tt {
+                    TokenType::Open(open) => TokenClass::Open(open),
+                    TokenType::Close(close) => TokenClass::Close(close),
+                    TokenType::FormLike(fl) => TokenClass::FormLike(fl),
+                    TokenType::String(_) => TokenClass::String,
+                    TokenType::Char(_) => TokenClass::Char,
+                    TokenType::Number(_) => TokenClass::Number,
+                    TokenType::Symbol(ref s) if s == "true" || s == "false" => TokenClass::Bool,
+                    TokenType::Symbol(_) => TokenClass::Symbol,
+                    TokenType::Keyword(_) => TokenClass::Keyword,
+                    TokenType::Dispatch(_) => TokenClass::ReaderMacro,
+                };
+                Some(self.classified(class, tok.range))
+            }
+            Some(Err(e)) => {
+                self.tokens.skip_to_delimiter();
+                let end = self.tokens.offset();
+                Some(self.classified(TokenClass::Error(e.to_string()), start..end))
+            }
+        }
+    }
+}
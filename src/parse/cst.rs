@@ -0,0 +1,395 @@
+use std::fmt;
+
+use Value;
+use intern::SymbolIntern;
+use parse::{Builder, ParseError, Position, Span, DEFAULT_MAX_NESTING};
+use parse::tokens::{Token, TokenIter, TokenType, Open, FormLike};
+use parse::errors::ParseError::*;
+
+/// A concrete syntax tree: a parsed program that remembers exactly how it
+/// was written.
+///
+/// Whitespace and comments are kept with the node that follows them, and
+/// atoms keep their original text, so printing a `Cst` gives back the input
+/// byte-for-byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    pub forms: Vec<Node>,
+    /// Whitespace and comments after the last form.
+    pub trailing: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    /// Whitespace and comments before this node.
+    pub leading: String,
+    pub kind: NodeKind,
+    /// Where the node is in the source, not counting `leading`.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    /// A number, symbol or string, exactly as written.
+    Atom(String),
+    /// A reader shorthand like `'` or `~@` and the form that it applies to.
+    Prefixed(FormLike, Option<Box<Node>>),
+    /// A reader macro tag, without the `#`, and the form that it applies to.
+    Tagged(String, Option<Box<Node>>),
+    /// A delimited form.  The string holds the whitespace and comments
+    /// before the closing delimiter.
+    Delimited(Open, Vec<Node>, String),
+}
+
+impl Cst {
+    pub fn parse(input: &str) -> Result<Cst, ParseError> {
+        Cst::parse_with_max_nesting(input, DEFAULT_MAX_NESTING)
+    }
+
+    /// Like `parse`, but fails with `TooDeeplyNested` for forms nested more
+    /// than `max_nesting` deep.
+    pub fn parse_with_max_nesting(input: &str, max_nesting: usize) -> Result<Cst, ParseError> {
+        let mut parser = CstParser {
+            input: input,
+            tokens: TokenIter::new(input),
+            last: 0,
+            max_nesting: max_nesting,
+        };
+        let mut forms = vec![];
+        while let Some(next) = parser.next() {
+            let (leading, tok) = try!(next);
+            forms.push(try!(parser.node(leading, tok)));
+        }
+        Ok(Cst {
+            forms: forms,
+            trailing: input[parser.last..].into(),
+        })
+    }
+
+    /// The values that `parse` would have produced for the same input.
+    ///
+    /// No reader macros are registered here, so tagged forms are errors.
+    pub fn to_values(&self, interner: &mut SymbolIntern) -> Result<Vec<Value>, ParseError> {
+        let mut build = Builder {
+            interner: interner,
+            spans: None,
+            macros: None,
+        };
+        self.forms.iter().map(|node| node.value(&mut build).map(|(v, _)| v)).collect()
+    }
+}
+
+impl Node {
+    /// The value that `parse` would have produced for this node.
+    pub fn to_value(&self, interner: &mut SymbolIntern) -> Result<Value, ParseError> {
+        let mut build = Builder {
+            interner: interner,
+            spans: None,
+            macros: None,
+        };
+        self.value(&mut build).map(|(v, _)| v)
+    }
+
+    fn value(&self, build: &mut Builder) -> Result<(Value, Span), ParseError> {
+        // Nodes whose children are being converted, with the values and
+        // spans of the children converted so far.
+        let mut parents: Vec<(&Node, Vec<Value>, Vec<Span>)> = vec![];
+        let mut node = self;
+        loop {
+            let mut result = match node.kind {
+                NodeKind::Atom(ref text) => {
+                    let start = node.span.start;
+                    let before = Position(start.0, start.1 - 1, start.2);
+                    match TokenIter::with_position(text, before).next() {
+                        Some(tok) => Ok((try!(build.atom(try!(tok).tt, start)), node.span)),
+                        None => unreachable!(),
+                    }
+                }
+                NodeKind::Prefixed(_, Some(ref child)) |
+                NodeKind::Tagged(_, Some(ref child)) => {
+                    parents.push((node, vec![], vec![]));
+                    node = child;
+                    continue;
+                }
+                NodeKind::Prefixed(fl, None) => build.form_like(fl, node.span, None),
+                NodeKind::Tagged(ref tag, None) => build.tagged(tag.clone(), node.span.start, None),
+                NodeKind::Delimited(open, ref children, _) => {
+                    if let Some(first) = children.first() {
+                        parents.push((node, vec![], vec![]));
+                        node = first;
+                        continue;
+                    }
+                    build.delimited(open, vec![], vec![], node.span).map(|v| (v, node.span))
+                }
+            };
+
+            // Hand the converted node up until some parent has children left.
+            loop {
+                let (value, span) = try!(result);
+                let (parent, mut values, mut items) = match parents.pop() {
+                    Some(parent) => parent,
+                    None => return Ok((value, span)),
+                };
+                result = match parent.kind {
+                    NodeKind::Prefixed(fl, _) => {
+                        build.form_like(fl, parent.span, Some((value, span)))
+                    }
+                    NodeKind::Tagged(ref tag, _) => {
+                        build.tagged(tag.clone(), parent.span.start, Some((value, span)))
+                    }
+                    NodeKind::Delimited(open, ref children, _) => {
+                        values.push(value);
+                        items.push(span);
+                        if values.len() < children.len() {
+                            node = &children[values.len()];
+                            parents.push((parent, values, items));
+                            break;
+                        }
+                        build.delimited(open, values, items, parent.span).map(|v| (v, parent.span))
+                    }
+                    NodeKind::Atom(_) => unreachable!(),
+                };
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in &self.forms {
+            try!(write!(f, "{}", node));
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // What is left to print, in reverse order.
+        enum Piece<'a> {
+            Node(&'a Node),
+            Text(&'a str),
+            Close(Open),
+        }
+
+        let mut pieces = vec![Piece::Node(self)];
+        while let Some(piece) = pieces.pop() {
+            let node = match piece {
+                Piece::Node(node) => node,
+                Piece::Text(text) => {
+                    try!(write!(f, "{}", text));
+                    continue;
+                }
+                Piece::Close(open) => {
+                    try!(write!(f, "{}", open.closed_by().to_char()));
+                    continue;
+                }
+            };
+            try!(write!(f, "{}", node.leading));
+            match node.kind {
+                NodeKind::Atom(ref text) => try!(write!(f, "{}", text)),
+                NodeKind::Prefixed(fl, ref quoted) => {
+                    try!(write!(f, "{}", fl.to_str()));
+                    if let Some(ref node) = *quoted {
+                        pieces.push(Piece::Node(node));
+                    }
+                }
+                NodeKind::Tagged(ref tag, ref form) => {
+                    try!(write!(f, "#{}", tag));
+                    if let Some(ref node) = *form {
+                        pieces.push(Piece::Node(node));
+                    }
+                }
+                NodeKind::Delimited(open, ref children, ref trailing) => {
+                    try!(write!(f, "{}", open.to_char()));
+                    pieces.push(Piece::Close(open));
+                    pieces.push(Piece::Text(trailing));
+                    for child in children.iter().rev() {
+                        pieces.push(Piece::Node(child));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct CstParser<'a> {
+    input: &'a str,
+    tokens: TokenIter<'a>,
+    // The end of the last token read.
+    last: usize,
+    max_nesting: usize,
+}
+
+impl<'a> CstParser<'a> {
+    /// The next token, along with the trivia in front of it.
+    fn next(&mut self) -> Option<Result<(String, Token), ParseError>> {
+        self.tokens.next().map(|tok| {
+            tok.map(|tok| {
+                let leading = self.input[self.last..tok.range.start].into();
+                self.last = tok.range.end;
+                (leading, tok)
+            })
+        })
+    }
+
+    /// Reads the node that starts with `tok`.
+    ///
+    /// Nodes that are still being read are kept on a stack rather than on
+    /// the native stack, so deeply nested input can't overflow it.
+    fn node(&mut self, leading: String, tok: Token) -> Result<Node, ParseError> {
+        let mut partials = vec![];
+        let (mut leading, mut tok) = (leading, tok);
+        loop {
+            let kind = match tok.tt {
+                TokenType::Open(open) => Some(PartialKind::Delimited(open, vec![])),
+                TokenType::Close(close) => return Err(ExtraRightDelimiter(close, tok.start)),
+                TokenType::FormLike(fl) => Some(PartialKind::Prefixed(fl)),
+                TokenType::Dispatch(ref tag) => Some(PartialKind::Tagged(tag.clone())),
+                _ => None,
+            };
+            let mut read = match kind {
+                None => {
+                    Read::Done(Node {
+                        leading: leading,
+                        kind: NodeKind::Atom(self.input[tok.range.clone()].into()),
+                        span: Span::new(tok.start, tok.end),
+                    })
+                }
+                Some(kind) => {
+                    if partials.len() >= self.max_nesting {
+                        return Err(TooDeeplyNested(tok.start, self.max_nesting));
+                    }
+                    let partial = Partial {
+                        leading: leading,
+                        start: tok.start,
+                        kind: kind,
+                    };
+                    if let PartialKind::Delimited(..) = partial.kind {
+                        partials.push(partial);
+                        try!(self.element(&mut partials))
+                    } else {
+                        try!(self.prefixed(&mut partials, partial, tok.end))
+                    }
+                }
+            };
+
+            // Hand finished nodes up to the nodes that they are in, until
+            // there is another node to start.
+            loop {
+                let node = match read {
+                    Read::Child(trivia, next) => {
+                        leading = trivia;
+                        tok = next;
+                        break;
+                    }
+                    Read::Done(node) => node,
+                };
+                read = match partials.pop() {
+                    None => return Ok(node),
+                    Some(Partial { leading, start, kind: PartialKind::Delimited(open, mut children) }) => {
+                        children.push(node);
+                        partials.push(Partial {
+                            leading: leading,
+                            start: start,
+                            kind: PartialKind::Delimited(open, children),
+                        });
+                        try!(self.element(&mut partials))
+                    }
+                    Some(partial) => {
+                        let end = node.span.end;
+                        Read::Done(partial.finish(Some(node), end))
+                    }
+                };
+            }
+        }
+    }
+
+    /// Reads the next element of the innermost partial node, which is
+    /// delimited, or finishes it if it has been closed.
+    fn element(&mut self, partials: &mut Vec<Partial>) -> Result<Read, ParseError> {
+        let (open, start) = match partials.last() {
+            Some(&Partial { start, kind: PartialKind::Delimited(open, _), .. }) => (open, start),
+            _ => unreachable!(),
+        };
+        let (trivia, next) = match self.next() {
+            Some(next) => try!(next),
+            None => return Err(MissingRightDelimiter(open.closed_by(), start)),
+        };
+        match next.tt {
+            TokenType::Close(close) => {
+                if close != open.closed_by() {
+                    return Err(ExtraRightDelimiter(open.closed_by(), next.start));
+                }
+                let partial = partials.pop().unwrap();
+                let children = match partial.kind {
+                    PartialKind::Delimited(_, children) => children,
+                    _ => unreachable!(),
+                };
+                Ok(Read::Done(Node {
+                    leading: partial.leading,
+                    kind: NodeKind::Delimited(open, children, trivia),
+                    span: Span::new(start, next.end),
+                }))
+            }
+            _ => Ok(Read::Child(trivia, next)),
+        }
+    }
+
+    /// Starts reading the node after a quote or a tag that ends at `end`.
+    fn prefixed(&mut self,
+                partials: &mut Vec<Partial>,
+                partial: Partial,
+                end: Position)
+                -> Result<Read, ParseError> {
+        match self.next() {
+            None => Ok(Read::Done(partial.finish(None, end))),
+            Some(next) => {
+                let (trivia, next) = try!(next);
+                if let TokenType::Close(close) = next.tt {
+                    return Err(ExtraRightDelimiter(close, next.start));
+                }
+                partials.push(partial);
+                Ok(Read::Child(trivia, next))
+            }
+        }
+    }
+}
+
+/// A node that is still being read.
+struct Partial {
+    leading: String,
+    start: Position,
+    kind: PartialKind,
+}
+
+enum PartialKind {
+    Delimited(Open, Vec<Node>),
+    Prefixed(FormLike),
+    Tagged(String),
+}
+
+impl Partial {
+    /// Finishes a prefixed or tagged node that ends at `end`.
+    fn finish(self, child: Option<Node>, end: Position) -> Node {
+        let child = child.map(Box::new);
+        let kind = match self.kind {
+            PartialKind::Prefixed(fl) => NodeKind::Prefixed(fl, child),
+            PartialKind::Tagged(tag) => NodeKind::Tagged(tag, child),
+            PartialKind::Delimited(..) => unreachable!(),
+        };
+        Node {
+            leading: self.leading,
+            kind: kind,
+            span: Span::new(self.start, end),
+        }
+    }
+}
+
+/// What reading a token led to.
+enum Read {
+    /// Another node to read, with the trivia in front of it.
+    Child(String, Token),
+    Done(Node),
+}
@@ -0,0 +1,54 @@
+use std::rc::Rc;
+use std::fmt;
+use std::collections::HashMap;
+
+use Value;
+use intern::SymbolIntern;
+
+/// A reader macro: takes the form that follows its tag and returns the value
+/// to read in its place, or a message saying why the form is no good.
+pub type ReaderMacro = Fn(Value, &mut SymbolIntern) -> Result<Value, String>;
+
+/// The dispatch macros that the parser knows about.
+///
+/// A tagged form like `#color "ff00aa"` is read by parsing the form after
+/// the tag and handing it to the macro registered for `color`.
+#[derive(Clone, Default)]
+pub struct ReaderMacros {
+    macros: HashMap<String, Rc<ReaderMacro>>,
+}
+
+impl ReaderMacros {
+    pub fn new() -> ReaderMacros {
+        ReaderMacros { macros: HashMap::new() }
+    }
+
+    /// Registers `f` as the macro for `#tag`, returning the macro that it
+    /// replaces.
+    pub fn insert<N, F>(&mut self, tag: N, f: F) -> Option<Rc<ReaderMacro>>
+        where N: Into<String>,
+              F: Fn(Value, &mut SymbolIntern) -> Result<Value, String> + 'static
+    {
+        self.macros.insert(tag.into(), Rc::new(f))
+    }
+
+    pub fn remove(&mut self, tag: &str) -> Option<Rc<ReaderMacro>> {
+        self.macros.remove(tag)
+    }
+
+    pub fn get(&self, tag: &str) -> Option<&Rc<ReaderMacro>> {
+        self.macros.get(tag)
+    }
+
+    pub fn contains(&self, tag: &str) -> bool {
+        self.macros.contains_key(tag)
+    }
+}
+
+impl fmt::Debug for ReaderMacros {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tags: Vec<_> = self.macros.keys().collect();
+        tags.sort();
+        write!(f, "ReaderMacros({:?})", tags)
+    }
+}
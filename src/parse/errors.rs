@@ -1,35 +1,106 @@
 use std::error::Error;
 use std::fmt;
-use parse::tokens::{Position, Close};
+use parse::tokens::{Position, Close, FormLike};
+use parse::source::SourceNames;
 
 #[derive(Debug)]
 pub enum ParseError {
     UnexpectedChar(char, Position, String),
     UnterminatedString(Position),
-    ConversionError(String, Box<Error>),
+    UnterminatedComment(Position),
+    MissingDatum(Position),
+    ConversionError(String, Box<Error>, Position),
     BadEscape(Position, String),
-    MissingRightDelimiter(Close),
+    UnknownCharacter(Position, String),
+    MissingRightDelimiter(Close, Position),
     ExtraRightDelimiter(Close, Position),
     InvalidMapLiteral(Position),
+    UnknownReaderMacro(String, Position),
+    MissingTaggedForm(String, Position),
+    /// A quote, quasiquote or unquote with nothing after it to quote.
+    MissingQuotedForm(FormLike, Position),
+    ReaderMacroFailed(String, String, Position),
+    /// A form nested more deeply than the limit, which is given.
+    TooDeeplyNested(Position, usize),
 }
 
 use self::ParseError::*;
 
+impl ParseError {
+    /// True if the input ended in the middle of something, so that
+    /// more input could turn this error into a successful parse.
+    pub fn is_incomplete(&self) -> bool {
+        match *self {
+            UnterminatedString(_) |
+            UnterminatedComment(_) |
+            MissingRightDelimiter(..) |
+            MissingTaggedForm(..) |
+            MissingQuotedForm(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Where the error was found.  For unterminated strings, comments and
+    /// lists this is where the unterminated thing starts.
+    pub fn position(&self) -> Position {
+        match *self {
+            UnexpectedChar(_, pos, _) |
+            UnterminatedString(pos) |
+            UnterminatedComment(pos) |
+            MissingDatum(pos) |
+            ConversionError(_, _, pos) |
+            BadEscape(pos, _) |
+            UnknownCharacter(pos, _) |
+            MissingRightDelimiter(_, pos) |
+            ExtraRightDelimiter(_, pos) |
+            InvalidMapLiteral(pos) |
+            UnknownReaderMacro(_, pos) |
+            MissingTaggedForm(_, pos) |
+            MissingQuotedForm(_, pos) |
+            ReaderMacroFailed(_, _, pos) |
+            TooDeeplyNested(pos, _) => pos,
+        }
+    }
+
+    /// The message of this error, with the name of the file it is in
+    /// looked up in `names`.
+    pub fn describe(&self, names: &SourceNames) -> String {
+        let position = self.position();
+        let message = self.to_string();
+        message.replacen(&position.to_string(), &names.describe(position), 1)
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             UnexpectedChar(c, pos, ref while_doing) =>
                 write!(f, "Unexpected character {} at {}, {}", c, pos, while_doing),
             UnterminatedString(pos) => write!(f, "Unterminated string beginning at {}", pos),
-            ConversionError(ref s, ref e) => {
-                write!(f, "Could not convert {}: {}", s, e)
+            UnterminatedComment(pos) =>
+                write!(f, "Unterminated block comment beginning at {}", pos),
+            MissingDatum(pos) => write!(f, "Datum comment at {} has nothing to comment out", pos),
+            ConversionError(ref s, ref e, pos) => {
+                write!(f, "Could not convert {} at {}: {}", s, pos, e)
             }
             BadEscape(pos, ref s) =>
                 write!(f, "Invalid escape sequence starting at {}: {}", pos, s),
-            MissingRightDelimiter(c) => write!(f, "Missing right delimiter {}", c.to_char()),
+            UnknownCharacter(pos, ref s) =>
+                write!(f, "Unknown character literal {} at {}", s, pos),
+            MissingRightDelimiter(c, pos) =>
+                write!(f, "Missing right delimiter {} for the form at {}", c.to_char(), pos),
             ExtraRightDelimiter(c, pos) =>
                 write!(f, "Extra right delimiter {} at {}", c.to_char(), pos),
             InvalidMapLiteral(pos) => write!(f, "Map literal at {} is malformed", pos),
+            UnknownReaderMacro(ref tag, pos) => write!(f, "Unknown reader macro #{} at {}", tag, pos),
+            MissingTaggedForm(ref tag, pos) =>
+                write!(f, "Reader macro #{} at {} has nothing to read", tag, pos),
+            MissingQuotedForm(fl, pos) =>
+                write!(f, "{} at {} has nothing to quote", fl.to_str(), pos),
+            ReaderMacroFailed(ref tag, ref message, pos) =>
+                write!(f, "Reader macro #{} at {} failed: {}", tag, pos, message),
+            TooDeeplyNested(pos, max_nesting) =>
+                write!(f, "Form at {} is nested more than {} deep", pos, max_nesting),
         }
     }
 }
@@ -39,11 +110,19 @@ impl Error for ParseError {
         match *self {
             UnexpectedChar(_, _, _) => "Unexpected character",
             UnterminatedString(_) => "Unterminated string",
-            ConversionError(_, ref e) => e.description(),
+            UnterminatedComment(_) => "Unterminated block comment",
+            MissingDatum(_) => "Datum comment without a datum",
+            ConversionError(_, ref e, _) => e.description(),
             BadEscape(..) => "Bad escape sequence",
+            UnknownCharacter(..) => "Unknown character literal",
             MissingRightDelimiter(..) => "Missing right delimiter",
             ExtraRightDelimiter(..) => "Extra right delimiter",
             InvalidMapLiteral(..) => "Map literals require an even number of elements",
+            UnknownReaderMacro(..) => "Unknown reader macro",
+            MissingTaggedForm(..) => "Reader macro without a form",
+            MissingQuotedForm(..) => "Quote without a form",
+            ReaderMacroFailed(..) => "Reader macro failed",
+            TooDeeplyNested(..) => "Forms are nested too deeply",
         }
     }
 }
@@ -1,116 +1,588 @@
 // Based on Norvig's lisp interpreter
+use std::mem;
 use std::rc::Rc;
 use Value;
 use intern::SymbolIntern;
 
 mod errors;
 mod util;
+mod span;
+mod reader;
+mod cst;
+mod dispatch;
+mod classify;
+mod source;
 pub mod tokens;
 
-use parse::tokens::{TokenType, Token, Open, TokenIter};
+use parse::tokens::{TokenType, Token, TokenIter};
 pub use parse::errors::ParseError;
+pub use parse::span::{Span, SpanTable};
+pub use parse::tokens::Position;
+pub use parse::source::{SourceId, SourceNames};
+pub use parse::reader::{Reader, ReadResult, ReadStatus};
+pub use parse::cst::{Cst, Node, NodeKind};
+pub use parse::tokens::{Open, Close, FormLike};
+pub use parse::dispatch::{ReaderMacros, ReaderMacro};
+pub use parse::classify::{classify_tokens, ClassifiedTokens, ClassifiedToken, TokenClass};
 use parse::errors::ParseError::*;
 
-fn one_expr<'a, 'b>(tok: Token,
-                    tok_stream: &'a mut TokenIter<'b>,
-                    interner: &mut SymbolIntern)
-                    -> Result<Value, ParseError> {
-    use self::tokens::TokenType;
-    match tok.tt {
-        TokenType::Number(s) => Ok(try!(s.parse()
-                                         .map(Value::Int)
-                                         .or_else(|_| s.parse().map(Value::Float))
-                                         .map_err(|e| ConversionError(s, Box::new(e))))),
-        TokenType::Symbol(s) => Ok(s.parse()
-                                    .map(Value::Bool)
-                                    .unwrap_or(Value::Symbol(interner.intern(s)))),
-        TokenType::String(s) => Ok(Value::String(Rc::new(s))),
-        TokenType::FormLike(fl) => Ok({
-            let quoted = try!(parse_one_expr(tok_stream, interner));
-            let interned = Value::Symbol(interner.intern(fl.form_name()));
-            Value::list(match quoted {
-                None => vec![interned],
-                Some(v) => vec![interned, v],
-            })
-        }),
-        TokenType::Close(close) => Err(ExtraRightDelimiter(close, tok.start)),
-        TokenType::Open(open) => {
-            let mut values = try!(parse_delimited(tok_stream, open, interner));
-            match open {
-                Open::LParen => Ok(Value::list(values)),
-                Open::LBracket => if values.iter().all(|a| util::immediate_value(a, interner)) {
-                    let values = values.into_iter().map(util::unquote).collect();
-                    Ok(Value::list(vec![Value::Symbol(interner.intern("quote")),
-                                        Value::list(values)]))
+/// Builds values out of tokens and already-built elements.  Shared by the
+/// parser and by the concrete syntax tree.
+struct Builder<'a> {
+    interner: &'a mut SymbolIntern,
+    spans: Option<&'a mut SpanTable>,
+    macros: Option<&'a ReaderMacros>,
+}
+
+impl<'a> Builder<'a> {
+    fn symbol(&mut self, name: &str) -> Value {
+        Value::Symbol(self.interner.intern(name))
+    }
+
+    /// Builds a list value, recording where it came from.
+    fn list(&mut self, values: Vec<Value>, span: Span, items: Vec<Span>) -> Value {
+        let list = Rc::new(values);
+        if let Some(ref mut spans) = self.spans {
+            spans.insert(&list, span, items);
+        }
+        Value::List(list)
+    }
+
+    /// Converts a number, symbol or string token.
+    fn atom(&mut self, tt: TokenType, start: Position) -> Result<Value, ParseError> {
+        match tt {
+            TokenType::Number(s) => util::number(&s).map_err(|e| ConversionError(s, e, start)),
+            TokenType::Symbol(s) => Ok(s.parse()
+                                        .map(Value::Bool)
+                                        .unwrap_or(Value::Symbol(self.interner.intern(s)))),
+            TokenType::String(s) => Ok(Value::String(Rc::new(s))),
+            TokenType::Char(c) => Ok(Value::Char(c)),
+            TokenType::Keyword(s) => Ok(Value::Keyword(self.interner.intern(s))),
+            TokenType::Close(close) => Err(ExtraRightDelimiter(close, start)),
+            TokenType::Open(_) | TokenType::FormLike(_) | TokenType::Dispatch(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Runs the reader macro for `#tag` on the form that followed it.
+    fn tagged(&mut self,
+              tag: String,
+              start: Position,
+              form: Option<(Value, Span)>)
+              -> Result<(Value, Span), ParseError> {
+        let f = match self.macros.and_then(|macros| macros.get(&tag)) {
+            Some(f) => f.clone(),
+            None => return Err(UnknownReaderMacro(tag, start)),
+        };
+        let (value, span) = match form {
+            Some(form) => form,
+            None => return Err(MissingTaggedForm(tag, start)),
+        };
+        match f(value, self.interner) {
+            Ok(value) => Ok((value, Span::new(start, span.end))),
+            Err(message) => Err(ReaderMacroFailed(tag, message, start)),
+        }
+    }
+
+    /// Wraps `quoted` in the form that `fl` is shorthand for.
+    fn form_like(&mut self,
+                 fl: FormLike,
+                 span: Span,
+                 quoted: Option<(Value, Span)>)
+                 -> Result<(Value, Span), ParseError> {
+        let (v, v_span) = match quoted {
+            Some(quoted) => quoted,
+            None => return Err(MissingQuotedForm(fl, span.start)),
+        };
+        let interned = self.symbol(fl.form_name());
+        let span = Span::new(span.start, v_span.end);
+        Ok((self.list(vec![interned, v], span, vec![span, v_span]), span))
+    }
+
+    /// Builds the value for a delimited form out of its elements.
+    fn delimited(&mut self,
+                 open: Open,
+                 mut values: Vec<Value>,
+                 mut items: Vec<Span>,
+                 span: Span)
+                 -> Result<Value, ParseError> {
+        match open {
+            Open::LParen => Ok(self.list(values, span, items)),
+            Open::LBracket => if values.iter().all(|a| util::immediate_value(a, self.interner)) {
+                let values = values.into_iter().map(util::unquote).collect();
+                let quote = self.symbol("quote");
+                let quoted = self.list(values, span, items);
+                Ok(self.list(vec![quote, quoted], span, vec![span, span]))
+            } else {
+                values.insert(0, self.symbol("list"));
+                items.insert(0, span);
+                Ok(self.list(values, span, items))
+            },
+            Open::LBrace => {
+                if values.len() % 2 == 1 {
+                    return Err(InvalidMapLiteral(span.start));
+                }
+                if values.iter().all(|a| util::immediate_value(a, self.interner)) {
+                    let (keys, values): (Vec<_>, _) = values.into_iter()
+                                                            .enumerate()
+                                                            .partition(|&(i, _)| i % 2 == 0);
+                    if keys.iter().all(|&(_, ref k)| util::can_be_hash_key(k, self.interner)) {
+                        let m = keys.into_iter()
+                                    .map(|(_, k)| util::unquote(k))
+                                    .zip(values.into_iter().map(|(_, v)| util::unquote(v)))
+                                    .collect();
+                        Ok(Value::Map(Rc::new(m)))
+                    } else {
+                        Err(InvalidMapLiteral(span.start))
+                    }
                 } else {
-                    values.insert(0, Value::Symbol(interner.intern("list")));
-                    Ok(Value::list(values))
-                },
-                Open::LBrace => {
-                    if values.len() % 2 == 1 {
-                        return Err(InvalidMapLiteral(tok.start));
+                    values.insert(0, self.symbol("hash-map"));
+                    items.insert(0, span);
+                    Ok(self.list(values, span, items))
+                }
+            }
+        }
+    }
+}
+
+/// How deeply forms can be nested inside of each other before parsing
+/// fails with `ParseError::TooDeeplyNested`, unless a different limit is
+/// given.
+pub const DEFAULT_MAX_NESTING: usize = 1024;
+
+/// A form that the parser is in the middle of.
+enum Frame {
+    Delimited {
+        open: Open,
+        start: Position,
+        values: Vec<Value>,
+        items: Vec<Span>,
+    },
+    Prefixed(FormLike, Span),
+    Tagged(String, Position),
+}
+
+/// What the parser does next.
+enum Step {
+    /// Parse the expression starting with this token.
+    Start(Token),
+    /// Read the form after the prefix in the innermost frame.
+    Prefixed,
+    /// Read the next element of the innermost frame, a delimited form.
+    Element,
+    /// Give this expression to the innermost frame.  Forms after a prefix
+    /// can be missing.
+    Done(Result<Option<(Value, Span)>, ParseError>),
+}
+
+/// The error for the innermost delimited form in `frames`, if there is one.
+fn unclosed(frames: &[Frame]) -> Option<ParseError> {
+    frames.iter()
+          .rev()
+          .filter_map(|frame| match *frame {
+              Frame::Delimited { open, start, .. } => {
+                  Some(MissingRightDelimiter(open.closed_by(), start))
+              }
+              _ => None,
+          })
+          .next()
+}
+
+struct Parser<'a, 'b> {
+    tok_stream: TokenIter<'b>,
+    build: Builder<'a>,
+    // A token that was read but belongs to an enclosing form.
+    pending: Option<Token>,
+    // When recovering from errors, the errors found so far.
+    errors: Option<Vec<ParseError>>,
+    // Whether an opening delimiter in the first column ends any unclosed
+    // forms before it.
+    resync_at_column_one: bool,
+    max_nesting: usize,
+    // Whether the last token read was a closing delimiter.
+    after_close: bool,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn new(input: &'b str,
+           interner: &'a mut SymbolIntern,
+           spans: Option<&'a mut SpanTable>)
+           -> Parser<'a, 'b> {
+        Parser::with_position(input, Position::start_of(SourceId::anonymous()), interner, spans)
+    }
+
+    fn with_position(input: &'b str,
+                     pos: Position,
+                     interner: &'a mut SymbolIntern,
+                     spans: Option<&'a mut SpanTable>)
+                     -> Parser<'a, 'b> {
+        Parser {
+            tok_stream: TokenIter::with_position(input, pos),
+            build: Builder {
+                interner: interner,
+                spans: spans,
+                macros: None,
+            },
+            pending: None,
+            errors: None,
+            resync_at_column_one: false,
+            max_nesting: DEFAULT_MAX_NESTING,
+            after_close: false,
+        }
+    }
+
+    fn with_macros(mut self, macros: &'a ReaderMacros) -> Parser<'a, 'b> {
+        self.build.macros = Some(macros);
+        self
+    }
+
+    fn with_max_nesting(mut self, max_nesting: usize) -> Parser<'a, 'b> {
+        self.max_nesting = max_nesting;
+        self
+    }
+
+    fn recovering(input: &'b str,
+                  interner: &'a mut SymbolIntern,
+                  resync_at_column_one: bool)
+                  -> Parser<'a, 'b> {
+        let mut parser = Parser::new(input, interner, None);
+        parser.errors = Some(vec![]);
+        parser.resync_at_column_one = resync_at_column_one;
+        parser
+    }
+
+    /// Records `error` and carries on if we are recovering from errors,
+    /// otherwise returns it.
+    fn recover(&mut self, error: ParseError) -> Result<(), ParseError> {
+        match self.errors {
+            Some(ref mut errors) => {
+                // The same problem can be noticed twice, e.g. by a quote
+                // and then by the list the close delimiter belongs to.
+                if errors.last().map(|e| e.position()) != Some(error.position()) {
+                    errors.push(error);
+                }
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token, ParseError>> {
+        if let Some(tok) = self.pending.take() {
+            return Some(Ok(tok));
+        }
+        let next = self.tok_stream.next();
+        if let Some(Err(_)) = next {
+            if self.errors.is_some() {
+                self.tok_stream.skip_to_delimiter();
+            }
+        }
+        self.after_close = match next {
+            Some(Ok(Token { tt: TokenType::Close(_), .. })) => true,
+            _ => false,
+        };
+        next
+    }
+
+    /// True if the input read so far runs right up to the end, and more
+    /// input could change how it is read.  Only a closing delimiter can't
+    /// be changed: any other token could be extended, or has to be followed
+    /// by a delimiter that hasn't been seen yet.  Forms still waiting for
+    /// their delimiter or for the form after a prefix or tag fail with an
+    /// incomplete error instead.
+    fn could_continue(&mut self) -> bool {
+        !self.after_close && self.tok_stream.at_end()
+    }
+
+    /// Parses the expression starting with `tok`.
+    ///
+    /// Nested forms are kept on a stack of frames rather than on the native
+    /// stack, so deeply nested input can't overflow it.
+    fn one_expr(&mut self, tok: Token) -> Result<(Value, Span), ParseError> {
+        let mut frames = vec![];
+        let mut step = Step::Start(tok);
+        loop {
+            step = match step {
+                Step::Start(tok) => {
+                    let span = Span::new(tok.start, tok.end);
+                    let frame = match tok.tt {
+                        TokenType::FormLike(fl) => Frame::Prefixed(fl, span),
+                        TokenType::Dispatch(tag) => Frame::Tagged(tag, tok.start),
+                        TokenType::Open(open) => Frame::Delimited {
+                            open: open,
+                            start: tok.start,
+                            values: vec![],
+                            items: vec![],
+                        },
+                        tt => {
+                            let atom = self.build.atom(tt, tok.start);
+                            step = Step::Done(atom.map(|value| Some((value, span))));
+                            continue;
+                        }
+                    };
+                    if frames.len() >= self.max_nesting {
+                        return Err(TooDeeplyNested(tok.start, self.max_nesting));
                     }
-                    if values.iter().all(|a| util::immediate_value(a, interner)) {
-                        let (keys, values): (Vec<_>, _) = values.into_iter()
-                                                                .enumerate()
-                                                                .partition(|&(i, _)| i % 2 == 0);
-                        if keys.iter().all(|&(_, ref k)| util::can_be_hash_key(k, interner)) {
-                            let m = keys.into_iter()
-                                        .map(|(_, k)| util::unquote(k))
-                                        .zip(values.into_iter().map(|(_, v)| util::unquote(v)))
-                                        .collect();
-                            Ok(Value::Map(Rc::new(m)))
-                        } else {
-                            Err(InvalidMapLiteral(tok.start))
+                    let next = match frame {
+                        Frame::Delimited { .. } => Step::Element,
+                        _ => Step::Prefixed,
+                    };
+                    frames.push(frame);
+                    next
+                }
+                Step::Prefixed => self.prefixed_step(),
+                Step::Element => {
+                    let step = self.element_step(frames.last_mut().unwrap());
+                    if let Step::Done(_) = step {
+                        frames.pop();
+                    }
+                    step
+                }
+                Step::Done(result) => {
+                    let frame = match frames.pop() {
+                        Some(frame) => frame,
+                        // Only forms after a prefix can be missing.
+                        None => return result.map(|expr| expr.unwrap()),
+                    };
+                    match frame {
+                        Frame::Prefixed(fl, span) => {
+                            let quoted = match result {
+                                // The input ended.  If a delimited form is
+                                // still open, its delimiter is missing first.
+                                Ok(None) => match unclosed(&frames) {
+                                    Some(e) => Err(e),
+                                    None => self.build.form_like(fl, span, None),
+                                },
+                                result => {
+                                    result.and_then(|quoted| self.build.form_like(fl, span, quoted))
+                                }
+                            };
+                            Step::Done(quoted.map(Some))
+                        }
+                        Frame::Tagged(tag, start) => {
+                            let tagged = result.and_then(|form| self.build.tagged(tag, start, form));
+                            Step::Done(tagged.map(Some))
+                        }
+                        Frame::Delimited { open, start, mut values, mut items } => {
+                            match result {
+                                Ok(Some((value, span))) => {
+                                    values.push(value);
+                                    items.push(span);
+                                }
+                                Ok(None) => unreachable!(),
+                                Err(e) => try!(self.recover(e)),
+                            }
+                            frames.push(Frame::Delimited {
+                                open: open,
+                                start: start,
+                                values: values,
+                                items: items,
+                            });
+                            Step::Element
                         }
-                    } else {
-                        values.insert(0, Value::Symbol(interner.intern("hash-map")));
-                        Ok(Value::list(values))
                     }
                 }
+            };
+        }
+    }
+
+    /// Reads the form after a quote or a reader macro tag, if there is one.
+    fn prefixed_step(&mut self) -> Step {
+        match self.next_token() {
+            None => Step::Done(Ok(None)),
+            Some(Err(e)) => Step::Done(Err(e)),
+            Some(Ok(next)) => {
+                if let TokenType::Close(close) = next.tt {
+                    // Leave the delimiter for the list that it closes.
+                    let start = next.start;
+                    self.pending = Some(next);
+                    return Step::Done(Err(ExtraRightDelimiter(close, start)));
+                }
+                Step::Start(next)
             }
         }
     }
-}
 
+    /// Reads the next element of a delimited form, or finishes the form if
+    /// it has been closed.  When this is done, `frame` is finished with.
+    fn element_step(&mut self, frame: &mut Frame) -> Step {
+        let (open, start) = match *frame {
+            Frame::Delimited { open, start, .. } => (open, start),
+            _ => unreachable!(),
+        };
+        loop {
+            let tok = match self.next_token() {
+                Some(Ok(tok)) => tok,
+                Some(Err(e)) => {
+                    if let Err(e) = self.recover(e) {
+                        return Step::Done(Err(e));
+                    }
+                    continue;
+                }
+                None => return Step::Done(Err(MissingRightDelimiter(open.closed_by(), start))),
+            };
+            return match tok.tt {
+                TokenType::Close(close) => {
+                    if close != open.closed_by() {
+                        // A mismatched delimiter still closes this form.
+                        let extra = ExtraRightDelimiter(open.closed_by(), tok.start);
+                        if let Err(e) = self.recover(extra) {
+                            return Step::Done(Err(e));
+                        }
+                    }
+                    let (values, items) = match *frame {
+                        Frame::Delimited { ref mut values, ref mut items, .. } => {
+                            (mem::replace(values, vec![]), mem::replace(items, vec![]))
+                        }
+                        _ => unreachable!(),
+                    };
+                    let span = Span::new(start, tok.end);
+                    let value = self.build.delimited(open, values, items, span);
+                    Step::Done(value.map(|value| Some((value, span))))
+                }
+                TokenType::Open(_) if self.resync_at_column_one && tok.start.1 == 1 => {
+                    self.pending = Some(tok);
+                    Step::Done(Err(MissingRightDelimiter(open.closed_by(), start)))
+                }
+                _ => Step::Start(tok),
+            };
+        }
+    }
 
-fn parse_one_expr<'a, 'b>(tok_stream: &'a mut TokenIter<'b>,
-                          interner: &mut SymbolIntern)
-                          -> Result<Option<Value>, ParseError> {
-    if let Some(tok) = tok_stream.next() {
-        one_expr(try!(tok), tok_stream, interner).map(Some)
-    } else {
-        Ok(None)
+    fn parse_one_expr(&mut self) -> Result<Option<(Value, Span)>, ParseError> {
+        if let Some(tok) = self.next_token() {
+            self.one_expr(try!(tok)).map(Some)
+        } else {
+            Ok(None)
+        }
     }
-}
 
-fn parse_delimited<'a, 'b>(tok_stream: &'a mut TokenIter<'b>,
-                           opener: Open,
-                           interner: &mut SymbolIntern)
-                           -> Result<Vec<Value>, ParseError> {
-    let mut v = vec![];
-    loop {
-        if let Some(tok_or_err) = tok_stream.next() {
-            let tok = try!(tok_or_err);
-            match tok.tt {
-                TokenType::Close(close) => if close == opener.closed_by() {
+    fn parse_all(&mut self) -> Result<Vec<(Value, Span)>, ParseError> {
+        let mut v = vec![];
+        loop {
+            match self.parse_one_expr() {
+                Ok(Some(value)) => v.push(value),
+                Ok(None) => return Ok(v),
+                Err(e@TooDeeplyNested(..)) => {
+                    // There's no telling where the rest of the input starts.
+                    try!(self.recover(e));
                     return Ok(v);
-                } else {
-                    return Err(ExtraRightDelimiter(opener.closed_by(), tok.start));
-                },
-                _ => v.push(try!(one_expr(tok, tok_stream, interner))),
+                }
+                Err(e) => try!(self.recover(e)),
             }
-        } else {
-            return Err(MissingRightDelimiter(opener.closed_by()));
         }
     }
 }
 
+/// The result of `parse_recovering`.
+#[derive(Debug)]
+pub struct Recovered {
+    /// Every top-level form that was read, with its span.  A form that had
+    /// errors inside of it is still given, without the parts that couldn't
+    /// be read; check `errors` to know whether a form is whole.
+    pub forms: Vec<(Value, Span)>,
+    /// Every syntax error that was found, in the order that they appear.
+    pub errors: Vec<ParseError>,
+}
+
 pub fn parse(input: &str, interner: &mut SymbolIntern) -> Result<Vec<Value>, ParseError> {
-    let mut v = vec![];
-    let mut tok_iter = TokenIter::new(input);
-    while let Some(value) = try!(parse_one_expr(&mut tok_iter, interner)) {
-        v.push(value)
+    let forms = try!(Parser::new(input, interner, None).parse_all());
+    Ok(forms.into_iter().map(|(value, _)| value).collect())
+}
+
+/// Parses `input`, recording the source location of every list into `spans`.
+///
+/// Returns each top-level form along with its span.
+pub fn parse_with_spans(input: &str,
+                        interner: &mut SymbolIntern,
+                        spans: &mut SpanTable)
+                        -> Result<Vec<(Value, Span)>, ParseError> {
+    Parser::new(input, interner, Some(spans)).parse_all()
+}
+
+/// Like `parse_with_spans`, but reads tagged forms like `#color "ff00aa"`
+/// with the reader macros in `macros`, and fails with `TooDeeplyNested` for
+/// forms nested more than `max_nesting` deep.  Spans are only recorded if
+/// `spans` is given.
+pub fn parse_with_macros(input: &str,
+                         interner: &mut SymbolIntern,
+                         spans: Option<&mut SpanTable>,
+                         macros: &ReaderMacros,
+                         max_nesting: usize)
+                         -> Result<Vec<(Value, Span)>, ParseError> {
+    parse_source(input, SourceId::anonymous(), interner, spans, macros, max_nesting)
+}
+
+/// Like `parse_with_macros`, but for input that came from `source`, which
+/// every parsed position will refer to.
+pub fn parse_source(input: &str,
+                    source: SourceId,
+                    interner: &mut SymbolIntern,
+                    spans: Option<&mut SpanTable>,
+                    macros: &ReaderMacros,
+                    max_nesting: usize)
+                    -> Result<Vec<(Value, Span)>, ParseError> {
+    Parser::with_position(input, Position::start_of(source), interner, spans)
+        .with_macros(macros)
+        .with_max_nesting(max_nesting)
+        .parse_all()
+}
+
+/// Parses as much of `input` as possible, collecting every syntax error
+/// instead of stopping at the first one.
+///
+/// After an error inside of a form, parsing carries on with the next
+/// element of that form; a mismatched closing delimiter closes the form
+/// that it is in.  If some form is never closed, each opening delimiter in
+/// the first column of a line is taken to start a new top-level form, so
+/// that one missing delimiter doesn't swallow the rest of the input.
+///
+/// No reader macros are known, so every tagged form is an error; use
+/// `parse_recovering_with_macros` to read them.
+pub fn parse_recovering(input: &str, interner: &mut SymbolIntern) -> Recovered {
+    recover_all(input, interner, None)
+}
+
+/// Like `parse_recovering`, but reads tagged forms like `#color "ff00aa"`
+/// with the reader macros in `macros`.
+pub fn parse_recovering_with_macros(input: &str,
+                                    interner: &mut SymbolIntern,
+                                    macros: &ReaderMacros)
+                                    -> Recovered {
+    recover_all(input, interner, Some(macros))
+}
+
+fn recover_all(input: &str,
+               interner: &mut SymbolIntern,
+               macros: Option<&ReaderMacros>)
+               -> Recovered {
+    let mut recovered = recover_with(input, interner, macros, false);
+    let unclosed = recovered.errors.iter().any(|e| match *e {
+        MissingRightDelimiter(..) => true,
+        _ => false,
+    });
+    if unclosed {
+        recovered = recover_with(input, interner, macros, true);
+    }
+    recovered
+}
+
+fn recover_with(input: &str,
+                interner: &mut SymbolIntern,
+                macros: Option<&ReaderMacros>,
+                resync: bool)
+                -> Recovered {
+    let mut parser = Parser::recovering(input, interner, resync);
+    if let Some(macros) = macros {
+        parser = parser.with_macros(macros);
+    }
+    // Errors are all recorded, so this can't fail.
+    let forms = parser.parse_all().unwrap_or_default();
+    let mut errors = parser.errors.take().unwrap_or_default();
+    errors.sort_by_key(|e| e.position());
+    Recovered {
+        forms: forms,
+        errors: errors,
     }
-    Ok(v)
 }
@@ -0,0 +1,164 @@
+use Value;
+use intern::SymbolIntern;
+use parse::{Parser, ParseError, Position, Span, SpanTable, ReaderMacros, SourceId,
+            DEFAULT_MAX_NESTING};
+use parse::tokens::TokenIter;
+
+/// What was left in a `Reader` after reading as many forms as possible.
+#[derive(Debug)]
+pub enum ReadStatus {
+    /// Everything that was buffered has been read.
+    Complete,
+    /// The buffered input ends partway through a form.  Push more input and
+    /// read again.
+    NeedsMoreInput,
+    /// The buffered input has a syntax error in it.  The bad input is left
+    /// in the buffer; call `clear` to throw it away.
+    Error(ParseError),
+}
+
+#[derive(Debug)]
+pub struct ReadResult {
+    /// The complete top-level forms that were read, with their spans.
+    pub forms: Vec<(Value, Span)>,
+    /// How many bytes of buffered input those forms took up.  These bytes
+    /// have been removed from the reader.
+    pub consumed: usize,
+    pub status: ReadStatus,
+}
+
+/// An incremental parser.
+///
+/// Input is pushed in as it arrives (a line from a REPL, a packet from a
+/// socket, ...) and `read` returns every top-level form that is complete so
+/// far.  Input that might still be the start of a form stays buffered until
+/// more input arrives.  Positions keep counting across chunks.
+pub struct Reader {
+    buffer: String,
+    position: Position,
+}
+
+impl Reader {
+    pub fn new() -> Reader {
+        Reader::for_source(SourceId::anonymous())
+    }
+
+    /// A reader for input that comes from `source`.
+    pub fn for_source(source: SourceId) -> Reader {
+        Reader {
+            buffer: String::new(),
+            position: Position::start_of(source),
+        }
+    }
+
+    pub fn push_str(&mut self, input: &str) {
+        self.buffer.push_str(input);
+    }
+
+    /// The input that has been pushed but not yet read.
+    pub fn buffered(&self) -> &str {
+        &self.buffer
+    }
+
+    /// The position of the start of the buffered input.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// True if nothing but whitespace and comments is buffered.
+    pub fn is_empty(&self) -> bool {
+        TokenIter::with_position(&self.buffer, self.position).next().is_none()
+    }
+
+    /// Throws away all of the buffered input.
+    pub fn clear(&mut self) {
+        let len = self.buffer.len();
+        self.consume(len);
+    }
+
+    /// Reads every complete form out of the buffered input.
+    pub fn read(&mut self, interner: &mut SymbolIntern) -> ReadResult {
+        self.read_forms(interner, None, None, DEFAULT_MAX_NESTING, false)
+    }
+
+    /// Like `read`, but records the spans of the parsed lists in `spans`.
+    pub fn read_with_spans(&mut self,
+                           interner: &mut SymbolIntern,
+                           spans: &mut SpanTable)
+                           -> ReadResult {
+        self.read_forms(interner, Some(spans), None, DEFAULT_MAX_NESTING, false)
+    }
+
+    /// Like `read`, but reads tagged forms with the reader macros in
+    /// `macros`, and gives an error for forms nested more than
+    /// `max_nesting` deep.  Spans are only recorded if `spans` is given.
+    pub fn read_with_macros(&mut self,
+                            interner: &mut SymbolIntern,
+                            spans: Option<&mut SpanTable>,
+                            macros: &ReaderMacros,
+                            max_nesting: usize)
+                            -> ReadResult {
+        self.read_forms(interner, spans, Some(macros), max_nesting, false)
+    }
+
+    /// Reads the rest of the buffered input, treating the end of the buffer
+    /// as the end of the input.  Anything incomplete is an error.
+    pub fn finish(&mut self, interner: &mut SymbolIntern) -> ReadResult {
+        self.read_forms(interner, None, None, DEFAULT_MAX_NESTING, true)
+    }
+
+    fn read_forms(&mut self,
+                  interner: &mut SymbolIntern,
+                  spans: Option<&mut SpanTable>,
+                  macros: Option<&ReaderMacros>,
+                  max_nesting: usize,
+                  at_end: bool)
+                  -> ReadResult {
+        let mut forms = vec![];
+        let mut consumed = 0;
+        let status = {
+            let buffer = &self.buffer[..];
+            let mut parser = Parser::with_position(buffer, self.position, interner, spans)
+                .with_max_nesting(max_nesting);
+            if let Some(macros) = macros {
+                parser = parser.with_macros(macros);
+            }
+            loop {
+                match parser.parse_one_expr() {
+                    Ok(None) => break ReadStatus::Complete,
+                    // A form running right up to the end of the buffer
+                    // could still be continued by the next chunk (`fo` + `o`).
+                    Ok(Some(_)) if !at_end && parser.could_continue() => {
+                        break ReadStatus::NeedsMoreInput
+                    }
+                    Ok(Some(form)) => {
+                        consumed = parser.tok_stream.offset();
+                        forms.push(form);
+                    }
+                    Err(ref e) if !at_end && (e.is_incomplete() || parser.could_continue()) => {
+                        break ReadStatus::NeedsMoreInput
+                    }
+                    Err(e) => break ReadStatus::Error(e),
+                }
+            }
+        };
+        if at_end {
+            if let ReadStatus::Complete = status {
+                consumed = self.buffer.len();
+            }
+        }
+        self.consume(consumed);
+        ReadResult {
+            forms: forms,
+            consumed: consumed,
+            status: status,
+        }
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        for c in self.buffer[..bytes].chars() {
+            self.position.advance(c);
+        }
+        self.buffer.drain(..bytes);
+    }
+}
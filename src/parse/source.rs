@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use parse::Position;
+
+/// Identifies where some source code came from: a file, usually.
+///
+/// Ids are cheap to copy, so positions can say what file they are in without
+/// holding on to its name.  The names themselves are kept in a `SourceNames`
+/// table, which each `Context` has one of; an id only means something to the
+/// table that made it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SourceId(usize);
+
+impl SourceId {
+    /// The source of code that didn't come from anywhere in particular,
+    /// like a string passed to `eval_str`.
+    pub fn anonymous() -> SourceId {
+        SourceId(0)
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// The names of the sources that a context has read from.
+///
+/// Asking for the id of the same name twice gives the same id.
+#[derive(Debug, Clone, Default)]
+pub struct SourceNames {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl SourceNames {
+    pub fn new() -> SourceNames {
+        SourceNames::default()
+    }
+
+    /// The id of the source called `name`.
+    pub fn named<N: AsRef<str>>(&mut self, name: N) -> SourceId {
+        let name = name.as_ref();
+        if let Some(&id) = self.ids.get(name) {
+            return SourceId(id);
+        }
+        self.names.push(name.into());
+        self.ids.insert(name.into(), self.names.len());
+        SourceId(self.names.len())
+    }
+
+    /// The name that `id` was made from, or `None` if it is anonymous or
+    /// came from some other table.
+    pub fn name(&self, id: SourceId) -> Option<&str> {
+        if id.is_anonymous() {
+            None
+        } else {
+            self.names.get(id.0 - 1).map(|name| &**name)
+        }
+    }
+
+    /// Describes `position` the way that `Position` displays itself, followed
+    /// by the name of its source if it has one.
+    pub fn describe(&self, position: Position) -> String {
+        match self.name(position.source()) {
+            Some(name) => format!("{} of {}", position, name),
+            None => position.to_string(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
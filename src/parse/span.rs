@@ -0,0 +1,118 @@
+use std::fmt;
+use std::rc::{Rc, Weak};
+use std::collections::HashMap;
+
+use {Value, rc_to_usize};
+use parse::tokens::Position;
+
+/// The region of source text that a form was parsed from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span {
+            start: start,
+            end: end,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The end says what source the span is in.
+        write!(f, "line {}, column {} to {}", self.start.0, self.start.1, self.end)
+    }
+}
+
+struct ListSpans {
+    // Holding on to a weak reference keeps the allocation (and so the
+    // address that we are keyed on) from being reused by another list.
+    list: Weak<Vec<Value>>,
+    span: Span,
+    items: Vec<Span>,
+}
+
+/// Maps parsed lists back to the source that they came from.
+///
+/// Lists are tracked by identity, so the span of a list survives being
+/// cloned around by the evaluator, but a freshly built list (for example
+/// the output of a macro) has no span until one is given to it.
+pub struct SpanTable {
+    lists: HashMap<usize, ListSpans>,
+    live_after_prune: usize,
+}
+
+impl SpanTable {
+    pub fn new() -> SpanTable {
+        SpanTable {
+            lists: HashMap::new(),
+            live_after_prune: 0,
+        }
+    }
+
+    /// Records the span of `list` and the spans of each of its elements.
+    pub fn insert(&mut self, list: &Rc<Vec<Value>>, span: Span, items: Vec<Span>) {
+        if self.lists.len() > 2 * self.live_after_prune + 64 {
+            self.prune();
+        }
+        self.lists.insert(rc_to_usize(list),
+                          ListSpans {
+                              list: Rc::downgrade(list),
+                              span: span,
+                              items: items,
+                          });
+    }
+
+    /// Gives `to` the spans of `from`, unless `to` already has spans of its own.
+    pub fn inherit(&mut self, from: &Rc<Vec<Value>>, to: &Rc<Vec<Value>>) {
+        if self.get(to).is_some() {
+            return;
+        }
+        let copied = self.lists
+                         .get(&rc_to_usize(from))
+                         .map(|entry| (entry.span, entry.items.clone()));
+        if let Some((span, items)) = copied {
+            self.insert(to, span, items);
+        }
+    }
+
+    /// Gives `list` the span `span` unless it already has one.
+    pub fn inherit_span(&mut self, list: &Rc<Vec<Value>>, span: Span) {
+        if self.get(list).is_none() {
+            self.insert(list, span, vec![]);
+        }
+    }
+
+    /// The span of the whole list.
+    pub fn get(&self, list: &Rc<Vec<Value>>) -> Option<Span> {
+        self.lists.get(&rc_to_usize(list)).map(|entry| entry.span)
+    }
+
+    /// The span of the element at `index` in `list`.
+    pub fn item(&self, list: &Rc<Vec<Value>>, index: usize) -> Option<Span> {
+        self.lists
+            .get(&rc_to_usize(list))
+            .and_then(|entry| entry.items.get(index).cloned())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    /// Forgets about lists that have since been dropped.
+    pub fn prune(&mut self) {
+        let dead: Vec<usize> = self.lists
+                                   .iter()
+                                   .filter(|&(_, entry)| entry.list.upgrade().is_none())
+                                   .map(|(&key, _)| key)
+                                   .collect();
+        for key in dead {
+            self.lists.remove(&key);
+        }
+        self.live_after_prune = self.lists.len();
+    }
+}
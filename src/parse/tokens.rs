@@ -2,11 +2,16 @@ use std::fmt;
 use std::char;
 use std::str::CharIndices;
 use std::iter::Peekable;
+use std::ops::Range;
+use std::iter::repeat;
 use parse::errors::ParseError;
 use parse::errors::ParseError::*;
+use parse::source::SourceId;
 
-#[derive(Debug, Copy, Clone)]
-pub struct Position(pub usize, pub usize);
+/// A line and column in some source.  The first character of a source is at
+/// line 1, column 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position(pub usize, pub usize, pub SourceId);
 
 impl<'a> fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -15,6 +20,20 @@ impl<'a> fmt::Display for Position {
 }
 
 impl Position {
+    /// A position in an anonymous source.
+    pub fn new(line: usize, column: usize) -> Position {
+        Position(line, column, SourceId::anonymous())
+    }
+
+    /// The position just before the first character of `source`.
+    pub fn start_of(source: SourceId) -> Position {
+        Position(1, 0, source)
+    }
+
+    pub fn source(&self) -> SourceId {
+        self.2
+    }
+
     pub fn advance(&mut self, c: char) {
         if c == '\n' {
             self.0 += 1;
@@ -24,7 +43,7 @@ impl Position {
         }
     }
     pub fn next(&self) -> Position {
-        Position(self.0, self.1 + 1)
+        Position(self.0, self.1 + 1, self.2)
     }
 }
 
@@ -34,8 +53,12 @@ pub enum TokenType {
     Close(Close),
     FormLike(FormLike),
     String(String),
+    Char(char),
     Number(String),
     Symbol(String),
+    Keyword(String),
+    /// A reader macro tag like `#color`, without the `#`.
+    Dispatch(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
@@ -70,6 +93,18 @@ impl FormLike {
             &UnquoteSplicing => "unquote-splicing",
         }
     }
+
+    /// The shorthand as it is written in source.
+    #[inline]
+    pub fn to_str(&self) -> &'static str {
+        use self::FormLike::*;
+        match self {
+            &Quote => "'",
+            &QuasiQuote => "`",
+            &Unquote => "~",
+            &UnquoteSplicing => "~@",
+        }
+    }
 }
 
 use self::Open::*;
@@ -84,6 +119,15 @@ impl Open {
             &LBracket => RBracket,
         }
     }
+
+    #[inline]
+    pub fn to_char(&self) -> char {
+        match self {
+            &LParen => '(',
+            &LBrace => '{',
+            &LBracket => '[',
+        }
+    }
 }
 
 impl Close {
@@ -101,6 +145,8 @@ pub struct Token {
     pub tt: TokenType,
     pub start: Position,
     pub end: Position,
+    /// The bytes of the input that the token was read from.
+    pub range: Range<usize>,
 }
 
 impl Token {
@@ -109,6 +155,7 @@ impl Token {
             tt: t,
             start: start,
             end: end,
+            range: 0..0,
         }
     }
 
@@ -123,11 +170,7 @@ impl Token {
             '}' => Some(Close(RBrace)),
             _ => None,
         } {
-            Some(Token {
-                tt: tt,
-                start: start,
-                end: start.next(),
-            })
+            Some(Token::new(tt, start, start.next()))
         } else {
             None
         }
@@ -169,17 +212,19 @@ impl<'a> Iterator for TokenIter<'a>
     fn next<'b>(&'b mut self) -> Option<Self::Item> {
         use self::TokenType::*;
         use self::FormLike::*;
-        self.skip_ws();
+        if let Err(e) = self.skip_trivia() {
+            return Some(Err(e));
+        }
         if let Some((start, curchar, pos)) = self.iter.next() {
-            match curchar {
-                '\'' => Some(Ok(Token::new(FormLike(Quote), pos, pos.next()))),
-                '`' => Some(Ok(Token::new(FormLike(QuasiQuote), pos, pos.next()))),
+            let tok = match curchar {
+                '\'' => Ok(Token::new(FormLike(Quote), pos, pos.next())),
+                '`' => Ok(Token::new(FormLike(QuasiQuote), pos, pos.next())),
                 '~' => {
-                    let unquote = Some(Ok(Token::new(FormLike(Unquote), pos, pos.next())));
+                    let unquote = Ok(Token::new(FormLike(Unquote), pos, pos.next()));
                     if let Some(&(_, c, nextpos)) = self.iter.peek() {
                         if c == '@' {
                             self.iter.next();
-                            Some(Ok(Token::new(FormLike(UnquoteSplicing), pos, nextpos.next())))
+                            Ok(Token::new(FormLike(UnquoteSplicing), pos, nextpos.next()))
                         } else {
                             unquote
                         }
@@ -187,13 +232,23 @@ impl<'a> Iterator for TokenIter<'a>
                         unquote
                     }
                 }
-                c if is_symbol_start_c(c) => Some(self.read_symbol(c, start, pos)),
-                c if c.is_digit(10) => Some(self.read_number(start, pos)),
-                '(' | ')' | '[' | ']' | '{' | '}' =>
-                    Some(Ok(Token::new_delim(curchar, pos).unwrap())),
-                '"' => Some(self.read_string(start + 1, pos)),
-                _ => None,
-            }
+                '\\' => self.read_char(start, pos),
+                'r' if is_raw_string_start(&self.input[start + 1..]) => {
+                    self.read_raw_string(start, pos)
+                }
+                c if is_symbol_start_c(c) => self.read_symbol(c, start, pos).map(classify_symbol),
+                c if c.is_digit(10) => self.read_number(start, pos),
+                '(' | ')' | '[' | ']' | '{' | '}' => Ok(Token::new_delim(curchar, pos).unwrap()),
+                '"' if self.input[start..].starts_with("\"\"\"") => {
+                    self.read_multiline_string(pos)
+                }
+                '"' => self.read_string(start + 1, pos),
+                c => Err(UnexpectedChar(c, pos, "which cannot start a token".into())),
+            };
+            Some(tok.map(|mut tok| {
+                tok.range = start..self.offset();
+                tok
+            }))
         } else {
             None
         }
@@ -217,13 +272,28 @@ macro_rules! delimcheck {
 impl<'a> TokenIter<'a>
 {
     pub fn new(s: &'a str) -> TokenIter<'a> {
-        let iter = CharIndicesPos::new(Position(1, 0), s.char_indices());
+        TokenIter::with_position(s, Position::start_of(SourceId::anonymous()))
+    }
+
+    /// Tokenizes `s` as if it started at `pos` in some larger input.
+    pub fn with_position(s: &'a str, pos: Position) -> TokenIter<'a> {
+        let iter = CharIndicesPos::new(pos, s.char_indices());
         TokenIter {
             input: s,
             iter: iter.peekable(),
         }
     }
 
+    /// True if all of the input has been tokenized.
+    pub fn at_end(&mut self) -> bool {
+        self.iter.peek().is_none()
+    }
+
+    /// The byte offset of the next character that hasn't been tokenized yet.
+    pub fn offset(&mut self) -> usize {
+        self.iter.peek().map_or(self.input.len(), |&(j, _, _)| j)
+    }
+
     fn take_until<'b, F>(&'b mut self, f: F) -> (Vec<(usize, char, Position)>, Option<usize>)
         where F: Fn(char) -> bool
     {
@@ -246,32 +316,121 @@ impl<'a> TokenIter<'a>
         }
     }
 
+    /// Skips whitespace and all three kinds of comments:
+    ///
+    /// * `; ...` runs until the end of the line.
+    /// * `#| ... |#` block comments, which may be nested.
+    /// * `#_` which comments out the next complete expression.
+    fn skip_trivia<'b>(&'b mut self) -> Result<(), ParseError> {
+        while let Some((_, skipped)) = self.next_comment() {
+            try!(skipped);
+        }
+        Ok(())
+    }
+
+    /// Skips whitespace and then a single comment, returning the bytes that
+    /// the comment took up.  Returns `None` if no comment comes next.
+    pub fn next_comment(&mut self) -> Option<(Range<usize>, Result<(), ParseError>)> {
+        self.skip_ws();
+        let (j, c, pos) = match self.iter.peek() {
+            Some(&next) => next,
+            None => return None,
+        };
+        let skipped = if c == ';' {
+            self.skip_line_comment();
+            Ok(())
+        } else if self.input[j..].starts_with("#|") {
+            self.skip_block_comment(pos)
+        } else if self.input[j..].starts_with("#_") {
+            self.iter.next();
+            self.iter.next();
+            self.skip_datum(pos)
+        } else {
+            return None;
+        };
+        Some((j..self.offset(), skipped))
+    }
+
+    fn skip_line_comment<'b>(&'b mut self) {
+        for (_, c, _) in &mut self.iter {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn skip_block_comment<'b>(&'b mut self, startpos: Position) -> Result<(), ParseError> {
+        // Consume the opening `#|`.
+        self.iter.next();
+        self.iter.next();
+        let mut depth = 1;
+        while depth > 0 {
+            match self.iter.next() {
+                None => return Err(UnterminatedComment(startpos)),
+                Some((j, _, _)) => {
+                    if self.input[j..].starts_with("#|") {
+                        self.iter.next();
+                        depth += 1;
+                    } else if self.input[j..].starts_with("|#") {
+                        self.iter.next();
+                        depth -= 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and throws away the next expression for a `#_` comment.
+    fn skip_datum<'b>(&'b mut self, startpos: Position) -> Result<(), ParseError> {
+        use self::TokenType::{Open, Close, FormLike};
+        let mut opened: Vec<(self::Open, Position)> = vec![];
+        loop {
+            let tok = match self.next() {
+                Some(tok) => try!(tok),
+                None => match opened.pop() {
+                    Some((open, pos)) => return Err(MissingRightDelimiter(open.closed_by(), pos)),
+                    None => return Err(MissingDatum(startpos)),
+                },
+            };
+            match tok.tt {
+                // The quoted expression is part of the datum.
+                FormLike(_) => continue,
+                Open(open) => opened.push((open, tok.start)),
+                Close(close) => match opened.pop() {
+                    Some((open, _)) => if open.closed_by() != close {
+                        return Err(ExtraRightDelimiter(open.closed_by(), tok.start));
+                    },
+                    None => return Err(MissingDatum(startpos)),
+                },
+                _ => {}
+            }
+            if opened.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
     fn read_u_escape<'b>(&'b mut self,
                          start: usize,
                          escape_start: Position,
                          string_start: Position)
                          -> Result<char, ParseError> {
         let (chars, brace) = self.take_until(|c| c == '}');
-        let brace = brace.unwrap_or(self.input.len());
+        // The escape runs through the `}`, or to the end of the input when
+        // there isn't one.
+        let end = brace.map_or(self.input.len(), |brace| brace + 1);
+        let digits = || chars.iter().skip(1).map(|&(_, c, _)| c);
         match chars.len() {
             0 => Err(UnterminatedString(string_start)),
             l if l > 8 => Err(BadEscape(escape_start, self.input[start..chars[8].0].into())),
-            l => {
-                if chars[0].1 != '{' ||
-                   !(chars.iter()
-                          .skip(1)
-                          .take(l - 1)
-                          .map(|&(_, c, _)| c)
-                          .all(|c| c.is_digit(16))) {
-                    Err(BadEscape(escape_start, self.input[start..brace + 1].into()))
-                } else {
-                    let ival = chars.iter()
-                                    .skip(1)
-                                    .take(l - 1)
-                                    .fold(0, |acc, &(_, c, _)| acc * 16 + (c as u32 - '0' as u32));
-                    char::from_u32(ival)
-                        .ok_or(BadEscape(escape_start, self.input[start..brace + 1].into()))
-                }
+            _ if chars[0].1 != '{' || !digits().all(|c| c.is_digit(16)) => {
+                Err(BadEscape(escape_start, self.input[start..end].into()))
+            }
+            _ if brace.is_none() => Err(UnterminatedString(string_start)),
+            _ => {
+                let ival = digits().fold(0, |acc, c| acc * 16 + c.to_digit(16).unwrap());
+                char::from_u32(ival).ok_or_else(|| BadEscape(escape_start, self.input[start..end].into()))
             }
         }
     }
@@ -301,7 +460,10 @@ impl<'a> TokenIter<'a>
                         char::from_u32(ival)
                             .ok_or(BadEscape(escape_start, self.input[start..end_index].into()))
                     }
-                    _ => Err(BadEscape(escape_start, self.input[start..end_index + 1].into())),
+                    _ => {
+                        let end = end_index + c2.len_utf8();
+                        Err(BadEscape(escape_start, self.input[start..end].into()))
+                    }
                 }
             }
         }
@@ -321,7 +483,7 @@ impl<'a> TokenIter<'a>
                 '\'' => Ok('\''),
                 '"' => Ok('"'),
                 'n' => Ok('\n'),
-                _ => Err(BadEscape(escape_start, self.input[start..end + 1].into())),
+                _ => Err(BadEscape(escape_start, self.input[start..end + c.len_utf8()].into())),
             }
         } else {
             Err(UnterminatedString(string_start))
@@ -345,7 +507,16 @@ impl<'a> TokenIter<'a>
                     }
                     if c == '\\' {
                         string.push_str(&self.input[start.unwrap()..j]);
-                        string.push(try!(self.read_escape(j, pos, startpos)));
+                        match self.read_escape(j, pos, startpos) {
+                            Ok(c) => string.push(c),
+                            Err(e @ BadEscape(..)) => {
+                                // Don't leave the rest of the string lying
+                                // around to be tokenized as code.
+                                self.skip_string_rest();
+                                return Err(e);
+                            }
+                            Err(e) => return Err(e),
+                        }
                         start = None;
                     } else if c == '"' {
                         string.push_str(&self.input[start.unwrap()..j]);
@@ -361,20 +532,179 @@ impl<'a> TokenIter<'a>
         Ok(Token::new(TokenType::String(string), startpos, endpos))
     }
 
+    fn skip_string_rest<'b>(&'b mut self) {
+        while let Some((_, c, _)) = self.iter.next() {
+            if c == '\\' {
+                self.iter.next();
+            } else if c == '"' {
+                break;
+            }
+        }
+    }
+
+    /// Skips ahead to the next delimiter, so that tokenizing can carry on
+    /// after an error in the middle of a token.
+    pub fn skip_to_delimiter(&mut self) {
+        while self.iter.peek().map_or(false, |&(_, c, _)| !is_delimiter_c(c)) {
+            self.iter.next();
+        }
+    }
+
+    /// Reads a raw string like `r"C:\dir"` or `r#"say "hi""#`.  Nothing is
+    /// escaped; the string ends at a quote followed by as many `#`s as
+    /// followed the `r`.  The `r` has already been consumed.
+    fn read_raw_string<'b>(&'b mut self,
+                           start: usize,
+                           startpos: Position)
+                           -> Result<Token, ParseError> {
+        let hashes = self.input[start + 1..].chars().take_while(|&c| c == '#').count();
+        let content_start = start + hashes + 2;
+        let closing: String = Some('"').into_iter().chain(repeat('#').take(hashes)).collect();
+        // The `#`s and the opening quote.
+        for _ in 0..hashes + 1 {
+            self.iter.next();
+        }
+        let (content, endpos) = loop {
+            match self.iter.next() {
+                None => return Err(UnterminatedString(startpos)),
+                Some((j, '"', pos)) if self.input[j..].starts_with(&closing[..]) => {
+                    let mut endpos = pos.next();
+                    for _ in 0..hashes {
+                        endpos = self.iter.next().unwrap().2.next();
+                    }
+                    break (&self.input[content_start..j], endpos);
+                }
+                Some(_) => {}
+            }
+        };
+        if let Some(&(_, c, pos)) = self.iter.peek() {
+            delimcheck!(c, pos, startpos, "raw string");
+        }
+        Ok(Token::new(TokenType::String(content.into()), startpos, endpos))
+    }
+
+    /// Reads a multi-line string:
+    ///
+    /// ```text
+    /// (define usage """
+    ///     usage: thing [options]
+    ///       -v   be verbose
+    ///     """)
+    /// ```
+    ///
+    /// The opening quotes have to end their line, and that line break isn't
+    /// part of the string.  Nothing is escaped, and the indentation that all
+    /// of the lines share (including the line with the closing quotes, if
+    /// nothing else is on it) is removed, along with that last line.
+    /// The first quote has already been consumed.
+    fn read_multiline_string<'b>(&'b mut self, startpos: Position) -> Result<Token, ParseError> {
+        self.iter.next();
+        self.iter.next();
+        // Allow trailing spaces and a `\r\n` after the opening quotes.
+        while let Some(&(_, c, pos)) = self.iter.peek() {
+            if c == '\n' {
+                self.iter.next();
+                break;
+            } else if c == ' ' || c == '\t' || c == '\r' {
+                self.iter.next();
+            } else {
+                return Err(UnexpectedChar(c,
+                                          pos,
+                                          format!("while parsing a multi-line string starting \
+                                                   at {}",
+                                                  startpos)));
+            }
+        }
+        let content_start = self.offset();
+        let (content, endpos) = loop {
+            match self.iter.next() {
+                None => return Err(UnterminatedString(startpos)),
+                Some((j, '"', _)) if self.input[j..].starts_with("\"\"\"") => {
+                    self.iter.next();
+                    let (_, _, pos) = self.iter.next().unwrap();
+                    break (&self.input[content_start..j], pos.next());
+                }
+                Some(_) => {}
+            }
+        };
+        if let Some(&(_, c, pos)) = self.iter.peek() {
+            delimcheck!(c, pos, startpos, "multi-line string");
+        }
+        Ok(Token::new(TokenType::String(strip_indentation(content)), startpos, endpos))
+    }
+
+    /// Reads a character literal like `\a`, `\newline`, `\x41` or
+    /// `\u{1F600}`.  The backslash has already been consumed.
+    fn read_char<'b>(&'b mut self, start: usize, startpos: Position) -> Result<Token, ParseError> {
+        // The first character is always part of the literal, so that
+        // delimiters like `\(` and `\ ` can be written.
+        let (first, mut endpos) = match self.iter.next() {
+            Some((_, c, pos)) => (c, pos.next()),
+            None => return Err(UnknownCharacter(startpos, "\\".into())),
+        };
+        let braced = first == 'u' && self.iter.peek().map_or(false, |&(_, c, _)| c == '{');
+        if braced {
+            // `{` and `}` are delimiters, so `\u{...}` needs reading by hand.
+            while let Some(&(_, c, pos)) = self.iter.peek() {
+                if c != '{' && c != '}' && is_delimiter_c(c) {
+                    break;
+                }
+                endpos = pos.next();
+                self.iter.next();
+                if c == '}' {
+                    break;
+                }
+            }
+        } else {
+            while let Some(&(_, c, pos)) = self.iter.peek() {
+                if is_delimiter_c(c) {
+                    break;
+                }
+                endpos = pos.next();
+                self.iter.next();
+            }
+        }
+        let stop = self.offset();
+        if let Some(&(_, c, pos)) = self.iter.peek() {
+            delimcheck!(c, pos, startpos, "character");
+        }
+        let text = &self.input[start..stop];
+        match char_named(&text[1..]) {
+            Some(c) => Ok(Token::new(TokenType::Char(c), startpos, endpos)),
+            None => Err(UnknownCharacter(startpos, text.into())),
+        }
+    }
+
+    /// Reads a number whose first digit (and sign, if it has one) has
+    /// already been consumed.  `start` is the index of the first character.
     fn read_number<'b>(&'b mut self,
                        start: usize,
                        startpos: Position)
                        -> Result<Token, ParseError> {
+        let digits = self.input[start..].trim_start_matches(|c| c == '+' || c == '-');
+        let radix = radix_of(digits);
+        if radix != 10 {
+            // The `x`, `b` or `o` of the prefix.
+            self.iter.next();
+        }
         let stop;
         let mut endpos = startpos.next();
+        let mut prev = '0';
         loop {
             if let Some(&(j, c, pos)) = self.iter.peek() {
-                if !is_number_c(c) {
+                let in_number = match radix {
+                    10 => is_number_c(c) || ((c == '+' || c == '-') && (prev == 'e' || prev == 'E')),
+                    16 => c.is_digit(16) || c == '_',
+                    _ => c.is_digit(10) || c == '_',
+                };
+                if !in_number {
                     stop = j;
                     delimcheck!(c, pos, startpos, "number");
                     endpos = pos;
                     break;
                 }
+                prev = c;
+                endpos = pos.next();
                 self.iter.next();
             } else {
                 stop = self.input.len();
@@ -427,12 +757,114 @@ impl<'a> TokenIter<'a>
 
 #[inline]
 fn is_delimiter_c(c: char) -> bool {
-    c.is_whitespace() || c == '(' || c == ')' || c == '{' || c == '}' || c == '[' || c == ']'
+    c.is_whitespace() || c == '(' || c == ')' || c == '{' || c == '}' || c == '[' || c == ']' ||
+    c == ';'
 }
 
 #[inline]
 fn is_number_c(c: char) -> bool {
-    c.is_digit(10) || c == '.' || c == 'e' || c == 'E'
+    c.is_digit(10) || c == '.' || c == 'e' || c == 'E' || c == '_'
+}
+
+/// Symbols like `:name` are keywords, and ones like `#name` are reader
+/// macro tags.
+fn classify_symbol(tok: Token) -> Token {
+    match tok.tt {
+        TokenType::Symbol(ref s) if s.len() > 1 && s.starts_with(':') => {
+            Token::new(TokenType::Keyword(s[1..].into()), tok.start, tok.end)
+        }
+        TokenType::Symbol(ref s) if s.len() > 1 && s.starts_with('#') => {
+            Token::new(TokenType::Dispatch(s[1..].into()), tok.start, tok.end)
+        }
+        _ => tok,
+    }
+}
+
+/// True if `rest`, which follows an `r`, starts a raw string.
+fn is_raw_string_start(rest: &str) -> bool {
+    rest.trim_start_matches('#').starts_with('"')
+}
+
+fn strip_indentation(text: &str) -> String {
+    fn indentation(line: &str) -> usize {
+        line.len() - line.trim_start_matches(|c| c == ' ' || c == '\t').len()
+    }
+    fn is_blank(line: &str) -> bool {
+        line.trim_matches(|c| c == ' ' || c == '\t').is_empty()
+    }
+
+    // Lines can end in `\r\n` as well as `\n`.
+    let mut lines: Vec<&str> = text.split('\n')
+                                   .map(|line| if line.ends_with('\r') {
+                                       &line[..line.len() - 1]
+                                   } else {
+                                       line
+                                   })
+                                   .collect();
+    // The indentation of the closing quotes.
+    let closing = if lines.last().map_or(false, |line| is_blank(line)) {
+        lines.pop()
+    } else {
+        None
+    };
+    let indent = lines.iter()
+                      .filter(|line| !is_blank(line))
+                      .chain(closing.iter())
+                      .map(|line| indentation(line))
+                      .min()
+                      .unwrap_or(0);
+    let stripped: Vec<&str> = lines.iter()
+                                   .map(|line| if is_blank(line) {
+                                       ""
+                                   } else {
+                                       &line[indent..]
+                                   })
+                                   .collect();
+    stripped.join("\n")
+}
+
+/// The character named by the text of a character literal (without the
+/// backslash).
+fn char_named(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    let first = chars.next();
+    if first.is_some() && chars.next().is_none() {
+        return first;
+    }
+    match name {
+        "newline" => Some('\n'),
+        "space" => Some(' '),
+        "tab" => Some('\t'),
+        "return" => Some('\r'),
+        "nul" => Some('\0'),
+        _ if name.starts_with("u{") && name.ends_with('}') && name.len() > 3 => {
+            u32::from_str_radix(&name[2..name.len() - 1], 16).ok().and_then(char::from_u32)
+        }
+        _ if name.starts_with('x') && name.len() == 3 => {
+            u8::from_str_radix(&name[1..], 16).ok().and_then(|b| {
+                if b < 0x80 {
+                    Some(b as char)
+                } else {
+                    None
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The radix of an unsigned number literal: `0x` is hexadecimal, `0b`
+/// binary and `0o` octal.
+pub fn radix_of(digits: &str) -> u32 {
+    if digits.starts_with("0x") {
+        16
+    } else if digits.starts_with("0b") {
+        2
+    } else if digits.starts_with("0o") {
+        8
+    } else {
+        10
+    }
 }
 
 
@@ -1,6 +1,8 @@
 use super::super::Value;
 use super::super::Value::*;
 use intern::SymbolIntern;
+use parse::tokens::radix_of;
+use std::error::Error;
 
 pub fn immediate_value(v: &Value, interner: &mut SymbolIntern) -> bool {
     match v {
@@ -30,3 +32,46 @@ pub fn can_be_hash_key(v: &Value, interner: &mut SymbolIntern) -> bool {
         _ => true,
     }
 }
+
+/// Converts the text of a number token.
+///
+/// Radix-prefixed and plain integer literals have to fit in an `i64`;
+/// anything with a `.` or an exponent is a float, and has to be finite.
+/// Underscores between two digits are ignored.
+pub fn number(text: &str) -> Result<Value, Box<Error>> {
+    let (sign, unsigned) = if text.starts_with('-') {
+        ("-", &text[1..])
+    } else if text.starts_with('+') {
+        ("", &text[1..])
+    } else {
+        ("", text)
+    };
+    let radix = radix_of(unsigned);
+    let digits = if radix != 10 { &unsigned[2..] } else { unsigned };
+    if !underscores_separate_digits(digits, radix) {
+        return Err("_ is only allowed between two digits".into());
+    }
+    let cleaned: ::std::string::String = digits.chars().filter(|&c| c != '_').collect();
+    let signed = format!("{}{}", sign, cleaned);
+    if radix != 10 {
+        i64::from_str_radix(&signed, radix).map(Int).map_err(|e| Box::new(e) as Box<Error>)
+    } else if cleaned.contains(|c| c == '.' || c == 'e' || c == 'E') {
+        let float: f64 = try!(signed.parse().map_err(|e| Box::new(e) as Box<Error>));
+        if float.is_infinite() {
+            return Err("number too large to fit in a float".into());
+        }
+        Ok(Float(float))
+    } else {
+        signed.parse().map(Int).map_err(|e| Box::new(e) as Box<Error>)
+    }
+}
+
+/// Whether every `_` in `digits` has a digit of `radix` on both sides.
+fn underscores_separate_digits(digits: &str, radix: u32) -> bool {
+    let chars: Vec<char> = digits.chars().collect();
+    chars.iter().enumerate().all(|(i, &c)| {
+        c != '_' ||
+        (i > 0 && i + 1 < chars.len() && chars[i - 1].is_digit(radix) &&
+         chars[i + 1].is_digit(radix))
+    })
+}
@@ -1,8 +1,12 @@
+use std::mem;
 use std::rc::Rc;
 use std::collections::HashMap;
-use {Value, Procedure, AresResult, AresError, ParamBinding, LoadedContext, State, Environment};
+use std::path::PathBuf;
+use {Value, Procedure, AresResult, AresError, ParamBinding, LoadedContext, State,
+     rc_to_usize};
 use super::util::expect_arity;
 use intern::Symbol;
+use eval::{compile, special_form, user_fn, SpecialForm};
 
 pub fn equals(args: &[Value]) -> AresResult<Value> {
     try!(expect_arity(args, |l| l >= 2, "at least 2"));
@@ -17,204 +21,159 @@ pub fn equals(args: &[Value]) -> AresResult<Value> {
     Ok(Value::Bool(true))
 }
 
-pub fn lett<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
-    try!(expect_arity(args, |l| l >= 2, "at least 2"));
-    let bindings = &args[0];
-    let bodies = &args[1..];
-
-    let bindings = match bindings {
-        &Value::List(ref inner) => inner,
-        other => return Err(AresError::UnexpectedType {
-            value: other.clone(),
-            expected: "List".into(),
-        }),
-    };
-
-    try!(expect_arity(&**bindings, |l| l % 2 == 0, "an even number"));
-
-    let mut new_env = Environment::new_with_data(ctx.env().clone(), HashMap::new());
-    for pair in bindings.chunks(2) {
-        let (name, value) = (&pair[0], &pair[1]);
-
-        let name = match name {
-            &Value::Symbol(s) => s,
-            other => return Err(AresError::UnexpectedType {
-                value: other.clone(),
-                expected: "Symbol".into(),
-            }),
-        };
-
-        let (env, evaluated) = ctx.with_other_env(new_env, move |new_ctx| new_ctx.eval(value));
-        new_env = env;
-        let evaluated = try!(evaluated);
-
-        new_env.borrow_mut().insert_here(name, evaluated.clone());
-    }
-
-    let mut last = None;
-    let (_, bodies_execution) = ctx.with_other_env(new_env, |new_ctx| -> AresResult<()> {
-        for body in bodies {
-            last = Some(try!(new_ctx.eval(body)));
-        }
-        Ok(())
-    });
-    try!(bodies_execution);
-
-    Ok(last.unwrap())
-}
-
 pub fn eval<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
     try!(expect_arity(args, |l| l == 1, "exactly 1"));
     ctx.eval(&args[0])
 }
 
-pub fn apply<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
-    try!(expect_arity(args, |l| l == 2, "exactly 2"));
-    let func = &args[0];
-    let arguments = &args[1];
-    match arguments {
-        &Value::List(ref lst) => ctx.call(func, lst),
-        other => Err(AresError::UnexpectedType {
+/// Evaluates a file, returning the value of its last form.  A relative
+/// path is relative to the directory of the file doing the loading, if
+/// there is one.
+pub fn load<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
+    try!(expect_arity(args, |l| l == 1, "exactly 1"));
+    let path = match &args[0] {
+        &Value::String(ref s) => PathBuf::from(&s[..]),
+        other => return Err(AresError::UnexpectedType {
             value: other.clone(),
-            expected: "List".into(),
+            expected: "String".into(),
         }),
-    }
+    };
+    let path = match ctx.current_file().and_then(|file| file.parent()) {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    };
+    ctx.eval_file(path)
 }
 
 pub fn lambda<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
     try!(expect_arity(args, |l| l >= 2, "at least 2"));
     let dot = ctx.interner_mut().intern(".");
-    let param_names = match &args[0] {
+    let param_names = try!(param_binding(&args[0], dot));
+
+    let bodies: Vec<_> = args.iter().skip(1).cloned().collect();
+
+    let slot_names = Rc::new(param_names.slot_names());
+    let mut procedure = Procedure::with_slot_names(None,
+                                                   Rc::new(bodies),
+                                                   param_names,
+                                                   slot_names.clone(),
+                                                   ctx.env().clone());
+    if ctx.compile_lambdas() {
+        procedure.code = Some(compile(&procedure.bodies, &slot_names, ctx.env(), dot));
+    }
+    Ok(Value::lambda(procedure, false))
+}
+
+/// Reads the parameters of a lambda.  `dot` is the symbol that comes before
+/// rest parameters.
+pub fn param_binding(params: &Value, dot: Symbol) -> AresResult<ParamBinding> {
+    match params {
         &Value::List(ref v) => {
             let mut params = vec![];
             let mut rest = None;
             let mut seen_dot = false;
-            try!(v.iter()
-                  .map(|n| {
-                      match n {
-                          &Value::Symbol(s) if s == dot => {
-                              if seen_dot {
-                                  Err(AresError::UnexpectedArgsList(args[0].clone()))
-                              } else {
-                                  seen_dot = true;
-                                  Ok(())
-                              }
-                          }
-                          &Value::Symbol(s) => {
-                              if seen_dot {
-                                  match rest {
-                                      None => {
-                                          rest = Some(s);
-                                          Ok(())
-                                      }
-                                      Some(_) =>
-                                          Err(AresError::UnexpectedArgsList(args[0].clone())),
-                                  }
-                              } else {
-                                  params.push(s);
-                                  Ok(())
-                              }
-                          }
-                          &ref other => Err(AresError::UnexpectedType {
-                              value: other.clone(),
-                              expected: "Symbol".into(),
-                          }),
-                      }
-                  })
-                  .collect::<AresResult<Vec<()>>>());
-            ParamBinding {
+            for n in v.iter() {
+                match n {
+                    &Value::Symbol(s) if s == dot => {
+                        if seen_dot {
+                            return Err(AresError::UnexpectedArgsList(Value::List(v.clone())));
+                        }
+                        seen_dot = true;
+                    }
+                    &Value::Symbol(s) => {
+                        if !seen_dot {
+                            params.push(s);
+                        } else if rest.is_none() {
+                            rest = Some(s);
+                        } else {
+                            return Err(AresError::UnexpectedArgsList(Value::List(v.clone())));
+                        }
+                    }
+                    &ref other => return Err(AresError::UnexpectedType {
+                        value: other.clone(),
+                        expected: "Symbol".into(),
+                    }),
+                }
+            }
+            Ok(ParamBinding {
                 params: params,
                 rest: rest,
-            }
+            })
         }
         &Value::Symbol(s) => {
-            ParamBinding {
+            Ok(ParamBinding {
                 params: vec![],
                 rest: Some(s),
-            }
-        }
-        x => {
-            return Err(AresError::UnexpectedArgsList(x.clone()));
+            })
         }
-    };
-
-    let bodies: Vec<_> = args.iter().skip(1).cloned().collect();
-
-    Ok(Value::Lambda(Procedure::new(None, Rc::new(bodies), param_names, ctx.env().clone()),
-                     false))
-}
-
-fn define_helper<S: State + ?Sized>(args: &[Value],
-                                    ctx: &mut LoadedContext<S>)
-                                    -> AresResult<(Symbol, Value)> {
-    try!(expect_arity(args, |l| l == 2, "exactly 2"));
-    let name = match &args[0] {
-        &Value::Symbol(s) => s,
-        &ref other => return Err(AresError::UnexpectedType {
-            value: other.clone(),
-            expected: "Symbol".into(),
-        }),
-    };
-
-    if ctx.env().borrow().is_defined_at_this_level(name) {
-        return Err(AresError::AlreadyDefined(ctx.interner().lookup_or_anon(name)));
+        x => Err(AresError::UnexpectedArgsList(x.clone())),
     }
-
-    let value = &args[1];
-    let result = try!(ctx.eval(value));
-    Ok((name, result))
-}
-
-
-pub fn define<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
-    let (name, value) = try!(define_helper(args, ctx));
-    ctx.env().borrow_mut().insert_here(name, value.clone());
-    Ok(value)
-}
-
-pub fn define_macro<S: State + ?Sized>(args: &[Value],
-                                       ctx: &mut LoadedContext<S>)
-                                       -> AresResult<Value> {
-    let (name, value) = try!(define_helper(args, ctx));
-    let procedure = match value {
-        Value::Lambda(p, _) => p.clone(),
-        other => {
-            return Err(AresError::UnexpectedType {
-                value: other,
-                expected: "Lambda".into(),
-            });
-        }
-    };
-    let mac = Value::Lambda(procedure, true);
-    ctx.env().borrow_mut().insert_here(name, mac.clone());
-    Ok(mac)
 }
 
 pub fn walk<F>(value: &Value, f: &mut F) -> AresResult<Value>
     where F: FnMut(&Value) -> AresResult<(Value, bool)>
 {
-    let (v, recurse) = try!(f(value));
-    if recurse {
-        match v {
-            Value::List(v) => {
-                let result = try!(v.iter()
-                                   .map(|value| Ok(try!(walk(value, f))))
-                                   .collect::<AresResult<Vec<Value>>>());
-                Ok(Value::list(result))
+    /// A list or map whose elements are being walked.  Maps are walked as
+    /// their keys and values, one after the other.
+    struct Walking {
+        is_map: bool,
+        todo: ::std::vec::IntoIter<Value>,
+        done: Vec<Value>,
+    }
+
+    let mut walking: Vec<Walking> = vec![];
+    let mut next = value.clone();
+    loop {
+        let (v, recurse) = try!(f(&next));
+        let mut result = match v {
+            Value::List(ref v) if recurse => {
+                walking.push(Walking {
+                    is_map: false,
+                    todo: (**v).clone().into_iter(),
+                    done: Vec::with_capacity(v.len()),
+                });
+                None
             }
-            Value::Map(m) => {
-                let mut result = HashMap::with_capacity(m.len());
+            Value::Map(ref m) if recurse => {
+                let mut todo = Vec::with_capacity(m.len() * 2);
                 for (k, v) in m.iter() {
-                    let new_k = try!(walk(k, f));
-                    let new_v = try!(walk(v, f));
-                    result.insert(new_k, new_v);
+                    todo.push(k.clone());
+                    todo.push(v.clone());
+                }
+                walking.push(Walking {
+                    is_map: true,
+                    todo: todo.into_iter(),
+                    done: Vec::with_capacity(m.len() * 2),
+                });
+                None
+            }
+            v => Some(v),
+        };
+
+        // Hand the result up until something still has elements to walk.
+        loop {
+            if let Some(v) = result.take() {
+                match walking.last_mut() {
+                    Some(parent) => parent.done.push(v),
+                    None => return Ok(v),
                 }
-                Ok(Value::Map(Rc::new(result)))
             }
-            v => Ok(v),
+            if let Some(child) = walking.last_mut().unwrap().todo.next() {
+                next = child;
+                break;
+            }
+            let finished = walking.pop().unwrap();
+            result = Some(if finished.is_map {
+                let mut map = HashMap::with_capacity(finished.done.len() / 2);
+                let mut done = finished.done.into_iter();
+                while let (Some(k), Some(v)) = (done.next(), done.next()) {
+                    map.insert(k, v);
+                }
+                Value::Map(Rc::new(map))
+            } else {
+                Value::list(finished.done)
+            });
         }
-    } else {
-        Ok(v)
     }
 }
 
@@ -223,53 +182,105 @@ pub fn macroexpand<S: State + ?Sized>(args: &[Value],
                                       -> AresResult<Value> {
     try!(expect_arity(args, |l| l == 1, "exactly 1"));
     let quote = ctx.interner_mut().intern("quote");  // this should really be handled better...
-    let mut walk_f = |value: &Value| {
-        match value {
-            &Value::List(ref lst) => {
-                if lst.len() == 0 {
-                    return Ok((Value::List(lst.clone()), false));
+    expand_helper(&args[0], quote, ctx)
+}
+
+fn expand_helper<S: State + ?Sized>(value: &Value,
+                                    quote: Symbol,
+                                    ctx: &mut LoadedContext<S>)
+                                    -> AresResult<Value> {
+    /// What an expanded form will be handed to.
+    enum Expanding {
+        /// The elements of `list`, of which `done` have been expanded.
+        Items {
+            list: Rc<Vec<Value>>,
+            done: Vec<Value>,
+            changed: bool,
+        },
+        /// The output of the macro call `list`.
+        Macro(Rc<Vec<Value>>),
+    }
+
+    let mut expanding = vec![];
+    let mut next = value.clone();
+    loop {
+        // Go down into `next` until reaching something that expands to
+        // itself.
+        let mut result = loop {
+            let lst = match next {
+                Value::List(ref lst) if lst.len() != 0 => lst.clone(),
+                _ => break next,
+            };
+            if expanding.len() >= ctx.max_depth() {
+                return Err(AresError::DepthLimitExceeded(ctx.max_depth()));
+            }
+            if let Value::Symbol(s) = lst[0] {
+                if s == quote {
+                    break next;
                 }
-                match &lst[0] {
-                    &Value::Symbol(s) if s == quote => Ok((value.clone(), false)),
-                    &Value::Symbol(s) => {
-                        let v = ctx.env().borrow().get(s);
-                        match v {
-                            Some(v@Value::Lambda(_, true)) => {
-                                let macro_out = try!(ctx.call(&v, &lst[1..lst.len()]));
-                                let finished = try!(macroexpand(&[macro_out], ctx));
-                                Ok((finished, true))
-                            }
-                            _ => Ok((value.clone(), true)),
-                        }
+                let v = ctx.env().borrow().get(s);
+                if let Some(v@Value::Lambda(_, true)) = v {
+                    next = try!(ctx.call(&v, &lst[1..lst.len()]));
+                    expanding.push(Expanding::Macro(lst));
+                    continue;
+                }
+            }
+            next = lst[0].clone();
+            expanding.push(Expanding::Items {
+                done: Vec::with_capacity(lst.len()),
+                list: lst,
+                changed: false,
+            });
+        };
+
+        // Then hand the result back up until some list has elements left.
+        loop {
+            match expanding.pop() {
+                None => return Ok(result),
+                Some(Expanding::Macro(lst)) => {
+                    // Code generated by a macro is reported at the macro call site.
+                    if let (&Value::List(ref out), Some(span)) = (&result, ctx.spans().get(&lst)) {
+                        ctx.spans_mut().inherit_span(out, span);
+                    }
+                }
+                Some(Expanding::Items { list, mut done, changed }) => {
+                    let changed = changed || !same_value(&list[done.len()], &result);
+                    done.push(result);
+                    if done.len() < list.len() {
+                        next = list[done.len()].clone();
+                        expanding.push(Expanding::Items {
+                            list: list,
+                            done: done,
+                            changed: changed,
+                        });
+                        break;
                     }
-                    _ => Ok((value.clone(), true)),
+                    result = if changed {
+                        let expanded = Rc::new(done);
+                        ctx.spans_mut().inherit(&list, &expanded);
+                        Value::List(expanded)
+                    } else {
+                        Value::List(list)
+                    };
                 }
             }
-            other => Ok((other.clone(), false)),
         }
-    };
-    walk(&args[0], &mut walk_f)
+    }
 }
 
-pub fn set<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
-    try!(expect_arity(args, |l| l == 2, "exactly 2"));
-    let name = match &args[0] {
-        &Value::Symbol(s) => s,
-        &ref v => return Err(AresError::UnexpectedType {
-            value: v.clone(),
-            expected: "Symbol".into(),
-        }),
-    };
-
-    let value = &args[1];
-
-    if !ctx.env().borrow().is_defined(name) {
-        return Err(AresError::UndefinedName(ctx.interner().lookup_or_anon(name)));
+/// Like `==` on lists, but only checks identity so that it stays cheap.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::List(ref a), &Value::List(ref b)) => rc_to_usize(a) == rc_to_usize(b),
+        (&Value::List(_), _) | (_, &Value::List(_)) => false,
+        (a, b) => a == b,
     }
+}
 
-    let result = try!(ctx.eval(value));
-    ctx.env().borrow_mut().with_value_mut(name, |v| *v = result.clone());
-    Ok(result)
+/// `(throw value)` raises `value` as an error, which `try` can catch.
+pub fn throw(args: &[Value]) -> AresResult<Value> {
+    try!(expect_arity(args, |l| l == 1, "exactly 1"));
+    Err(AresError::Thrown(args[0].clone()))
 }
 
 pub fn quote<S: State + ?Sized>(args: &[Value], _ctx: &mut LoadedContext<S>) -> AresResult<Value> {
@@ -277,19 +288,6 @@ pub fn quote<S: State + ?Sized>(args: &[Value], _ctx: &mut LoadedContext<S>) ->
     Ok(args[0].clone())
 }
 
-pub fn cond<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
-    try!(expect_arity(args, |l| l == 3, "exactly 3"));
-    let (cond, true_branch, false_branch) = (&args[0], &args[1], &args[2]);
-    match try!(ctx.eval(cond)) {
-        Value::Bool(true) => ctx.eval(true_branch),
-        Value::Bool(false) => ctx.eval(false_branch),
-        other => Err(AresError::UnexpectedType {
-            value: other,
-            expected: "Bool".into(),
-        }),
-    }
-}
-
 pub fn gensym<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
     try!(expect_arity(args, |l| l == 0 || l == 1, "either 0 or 1"));
     let symbol = if args.len() == 0 {
@@ -306,49 +304,111 @@ pub fn gensym<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) ->
     Ok(Value::Symbol(symbol))
 }
 
+/// Evaluates to its template with the `unquote`d parts evaluated and the
+/// `unquote-splicing`d parts evaluated and spliced in.
+///
+/// The template is turned into an expression that builds it, which is
+/// evaluated in place of the `quasiquote`.
 pub fn quasiquote<S: State + ?Sized>(args: &[Value],
                                      ctx: &mut LoadedContext<S>)
                                      -> AresResult<Value> {
     try!(expect_arity(args, |l| l == 1, "exactly 1"));
     let unquote = Value::Symbol(ctx.interner_mut().intern("unquote"));
     let unquote_splicing = Value::Symbol(ctx.interner_mut().intern("unquote-splicing"));
-    let mut walk_f = |v: &Value| {
-        match v {
-            &Value::List(ref lst) => {
-                if lst.len() >= 1 && lst[0] == unquote_splicing {
-                    return Err(AresError::InvalidUnquotation);
-                } else if lst.len() == 2 && lst[0] == unquote {
-                    return Ok(try!(ctx.eval(&lst[1])));
+
+    let mut lst = match &args[0] {
+        &Value::List(ref lst) => lst.clone(),
+        other => return Ok(other.clone()),
+    };
+    if lst.len() >= 1 && lst[0] == unquote_splicing {
+        return Err(AresError::InvalidUnquotation);
+    } else if lst.len() == 2 && lst[0] == unquote {
+        return ctx.eval_in_tail_position(vec![lst[1].clone()], None);
+    }
+
+    let quote = Value::foreign_fn(special_form::<S, _>("quote", SpecialForm::Quote));
+    // The lists that `lst` is inside of, with how far along each one is.
+    let mut outer: Vec<(Rc<Vec<Value>>, usize, Vec<Value>, Vec<bool>)> = vec![];
+    let mut next = 0;
+    // The expressions for the elements of `lst` so far, and whether each
+    // one is spliced in.
+    let mut parts = vec![];
+    let mut spliced = vec![];
+    loop {
+        if next == lst.len() {
+            let build = Value::foreign_fn(user_fn::<S, _, _>("quasiquote", build_list(spliced)));
+            parts.insert(0, build);
+            let expression = Value::list(parts);
+            match outer.pop() {
+                Some((outer_lst, outer_next, mut outer_parts, mut outer_spliced)) => {
+                    outer_parts.push(expression);
+                    outer_spliced.push(false);
+                    lst = outer_lst;
+                    next = outer_next;
+                    parts = outer_parts;
+                    spliced = outer_spliced;
+                    continue;
                 }
-                let mut new_v = vec![];
-                for elem in lst.iter() {
-                    match elem {
-                        &Value::List(ref inner) => {
-                            if inner.len() == 2 && inner[0] == unquote {
-                                new_v.push(try!(ctx.eval(&inner[1])));
-                            } else if inner.len() == 2 && inner[0] == unquote_splicing {
-                                let evald = try!(ctx.eval(&inner[1]));
-                                match evald {
-                                    Value::List(ref evald) => new_v.extend(evald.iter().cloned()),
-                                    _ => return Err(AresError::UnexpectedType {
-                                        value: evald,
-                                        expected: "list".into(),
-                                    }),
-                                }
-                            } else {
-                                let r = try!(quasiquote(&[elem.clone()], ctx));
-                                new_v.push(r);
-                            }
-                        }
-                        elem => new_v.push(elem.clone()),
-                    }
+                None => return ctx.eval_in_tail_position(vec![expression], None),
+            }
+        }
+
+        let elem = lst[next].clone();
+        next += 1;
+        match elem {
+            Value::List(ref inner) if inner.len() == 2 && inner[0] == unquote => {
+                parts.push(inner[1].clone());
+                spliced.push(false);
+            }
+            Value::List(ref inner) if inner.len() == 2 && inner[0] == unquote_splicing => {
+                parts.push(inner[1].clone());
+                spliced.push(true);
+            }
+            Value::List(inner) => {
+                if inner.len() >= 1 && inner[0] == unquote_splicing {
+                    return Err(AresError::InvalidUnquotation);
                 }
-                Ok(Value::list(new_v))
+                outer.push((mem::replace(&mut lst, inner),
+                            next,
+                            mem::replace(&mut parts, vec![]),
+                            mem::replace(&mut spliced, vec![])));
+                next = 0;
+            }
+            elem@Value::Symbol(_) |
+            elem@Value::Lambda(..) => {
+                parts.push(Value::list(vec![quote.clone(), elem]));
+                spliced.push(false);
+            }
+            elem => {
+                parts.push(elem);
+                spliced.push(false);
             }
-            _ => Ok(v.clone()),
         }
-    };
-    walk_f(&args[0])
+    }
+}
+
+/// Builds one list of a quasiquoted template out of the values of its
+/// elements, splicing in the ones that are marked in `spliced`.
+fn build_list<S: State + ?Sized>(spliced: Vec<bool>)
+                                 -> Box<Fn(&[Value], &mut LoadedContext<S>) -> AresResult<Value>> {
+    Box::new(move |args: &[Value], ctx: &mut LoadedContext<S>| {
+        let mut new_v = Vec::with_capacity(args.len());
+        for (arg, &splice) in args.iter().zip(spliced.iter()) {
+            if !splice {
+                new_v.push(arg.clone());
+                continue;
+            }
+            match *arg {
+                Value::List(ref evald) => new_v.extend(evald.iter().cloned()),
+                _ => return Err(AresError::UnexpectedType {
+                    value: arg.clone(),
+                    expected: "list".into(),
+                }),
+            }
+        }
+        try!(ctx.memory().charge_list(new_v.len()));
+        Ok(Value::list(new_v))
+    })
 }
 
 pub fn unquote_error<S: State + ?Sized>(_args: &[Value],
@@ -356,3 +416,25 @@ pub fn unquote_error<S: State + ?Sized>(_args: &[Value],
                                         -> AresResult<Value> {
     Err(AresError::InvalidUnquotation)
 }
+
+/// `(memory-stats)` gives a map from `:allocated`, `:lists`, `:strings` and
+/// `:maps` to the number of bytes allocated so far, and `:limit` to an
+/// option of the context's memory limit.
+pub fn memory_stats<S: State + ?Sized>(args: &[Value],
+                                       ctx: &mut LoadedContext<S>)
+                                       -> AresResult<Value> {
+    try!(expect_arity(args, |l| l == 0, "exactly 0"));
+    let stats = ctx.memory_stats();
+    let limit = stats.limit.map(|limit| Value::Int(limit as i64));
+    let entries = vec![("allocated", Value::Int(stats.allocated() as i64)),
+                       ("lists", Value::Int(stats.lists as i64)),
+                       ("strings", Value::Int(stats.strings as i64)),
+                       ("maps", Value::Int(stats.maps as i64)),
+                       ("limit", Value::option(limit))];
+    let mut m = HashMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        m.insert(Value::Keyword(ctx.interner_mut().intern(key)), value);
+    }
+    try!(ctx.memory().charge_map(m.len()));
+    Ok(Value::Map(Rc::new(m)))
+}
@@ -42,11 +42,9 @@ pub fn debugger<S: State + ?Sized>(args: &[Value],
         Ok(false.into())
     };
 
-    let debugger_close: Value = Value::ForeignFn(free_fn::<S, _, _>("debugger-close",
-                                                                    debugger_close)
-                                                     .erase());
-    let debugger_env: Value = Value::ForeignFn(user_fn::<S, _, _>("debugger-env", debugger_env)
-                                                   .erase());
+    let debugger_close: Value = Value::foreign_fn(free_fn::<S, _, _>("debugger-close",
+                                                                     debugger_close));
+    let debugger_env: Value = Value::foreign_fn(user_fn::<S, _, _>("debugger-env", debugger_env));
     let mut mapping = HashMap::new();
     mapping.insert(ctx.interner_mut().intern("debugger-close"), debugger_close);
     mapping.insert(ctx.interner_mut().intern("debugger-env"), debugger_env);
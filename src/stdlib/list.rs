@@ -11,12 +11,15 @@ pub fn build_list<S: State + ?Sized>(args: &[Value],
     let vec = Rc::new(RefCell::new(Some(Vec::<Value>::new())));
     let writer1 = vec.clone();
     let writer2 = vec.clone();
+    let memory1 = ctx.memory().clone();
+    let memory2 = ctx.memory().clone();
 
     let push_individuals = move |values: &[Value]| -> AresResult<Value> {
         try!(expect_arity(values, |l| l >= 1, "at least 1"));
 
         match &mut *writer1.borrow_mut() {
             &mut Some(ref mut adder) => {
+                try!(memory1.charge_list_growth(values.len()));
                 let mut last = None;
                 for value in values {
                     adder.push(value.clone());
@@ -40,6 +43,7 @@ pub fn build_list<S: State + ?Sized>(args: &[Value],
                 let mut last = None;
                 for value in values {
                     if let &Value::List(ref list) = value {
+                        try!(memory2.charge_list_growth(list.len()));
                         for element in &***list {
                             adder.push(element.clone());
                         }
@@ -56,39 +60,26 @@ pub fn build_list<S: State + ?Sized>(args: &[Value],
         }
     };
 
-    let boxed_push_indiv: Value = Value::ForeignFn(free_fn::<S, _, _>("add", push_individuals)
-                                                       .erase());
-    let boxed_push_list: Value = Value::ForeignFn(free_fn::<S, _, _>("add-all", push_list_values)
-                                                      .erase());
+    let boxed_push_indiv: Value = Value::foreign_fn(free_fn::<S, _, _>("add", push_individuals));
+    let boxed_push_list: Value = Value::foreign_fn(free_fn::<S, _, _>("add-all", push_list_values));
 
-    let evaluator = args[0].clone();
-    // TODO: should this be apply?
-    try!(ctx.eval(&Value::list(vec![evaluator, boxed_push_indiv, boxed_push_list])));
-
-    let mut v = vec.borrow_mut();
-    Ok(Value::list(v.take().unwrap()))
-}
-
-pub fn foreach<S: State + ?Sized>(args: &[Value], ctx: &mut LoadedContext<S>) -> AresResult<Value> {
-    try!(expect_arity(args, |l| l == 2, "exactly 2"));
-    let list: Vec<_> = match args[0] {
-        Value::List(ref l) => (&**l).clone(),
-        ref other => return Err(AresError::UnexpectedType {
-            value: other.clone(),
-            expected: "List".into(),
-        }),
+    // Gives the list once the evaluator is done with it.
+    let finish = move |_: &[Value]| -> AresResult<Value> {
+        match vec.borrow_mut().take() {
+            Some(v) => Ok(Value::list(v)),
+            None => {
+                let err_msg = "build-list completed more than once.";
+                Err(AresError::InvalidState(err_msg.to_string()))
+            }
+        }
     };
+    let boxed_finish: Value = Value::foreign_fn(free_fn::<S, _, _>("build-list", finish));
 
-    let ref func = args[1];
-
-    let mut count = 0;
-    for element in list {
-        let singleton_slice: [Value; 1] = [element];
-        try!(ctx.call(&func, &singleton_slice[..]));
-        count += 1;
-    }
-
-    Ok(Value::Int(count))
+    try!(ctx.memory().charge_list(0));
+    let evaluator = args[0].clone();
+    // TODO: should this be apply?
+    let call = Value::list(vec![evaluator, boxed_push_indiv, boxed_push_list]);
+    ctx.eval_in_tail_position(vec![Value::list(vec![boxed_finish, call])], None)
 }
 
 pub static LIST: &'static str = "(lambda list list)";
@@ -16,22 +16,46 @@ pub fn hash_map<S: State + ?Sized>(args: &[Value],
     let mut key = None;
     for arg in args {
         match key {
-            None => match try!(ctx.eval(arg)) {
-                v@Value::List(_) | v@Value::Map(_) => {
+            None => match *arg {
+                Value::List(_) | Value::Map(_) => {
                     return Err(AresError::UnexpectedType {
-                        value: v,
+                        value: arg.clone(),
                         expected: "a hashable type".to_owned(),
                     });
                 }
-                v => key = Some(v),
+                ref v => key = Some(v.clone()),
             },
             Some(k) => {
-                let val = try!(ctx.eval(arg));
-                m.insert(k, val);
+                m.insert(k, arg.clone());
                 key = None
             }
         }
     }
+    try!(ctx.memory().charge_map(m.len()));
 
     Ok(Value::Map(Rc::new(m)))
 }
+
+/// Calling a keyword looks it up in a map.  `(:k m)` gives `Some` of the
+/// value or `None`; `(:k m default)` gives the value or `default`.
+pub fn keyword_lookup(keyword: &Value, args: &[Value]) -> AresResult<Value> {
+    if args.len() != 1 && args.len() != 2 {
+        return Err(AresError::UnexpectedArity {
+            found: args.len() as u16,
+            expected: "1 or 2".to_owned(),
+        });
+    }
+    let found = match args[0] {
+        Value::Map(ref m) => m.get(keyword).cloned(),
+        ref other => {
+            return Err(AresError::UnexpectedType {
+                value: other.clone(),
+                expected: "Map".to_owned(),
+            })
+        }
+    };
+    Ok(match args.get(1) {
+        Some(default) => found.unwrap_or_else(|| default.clone()),
+        None => found.into(),
+    })
+}
@@ -1,8 +1,11 @@
 use {user_fn, free_fn, ast_fn, Context, State};
+use eval::{special_form, SpecialForm};
 
 // Keep these here for when you want to build huge changes
 // pub fn load_all<T>(_: T) {}
 // pub fn load_debug<T>(_: T) {}
+// pub fn load_memory<T>(_: T) {}
+// pub fn load_files<T>(_: T) {}
 
 
 pub mod arithmetic;
@@ -10,7 +13,6 @@ pub mod math;
 pub mod core;
 pub mod types;
 pub mod list;
-pub mod logical;
 pub mod map;
 pub mod debugger;
 pub mod option;
@@ -37,9 +39,7 @@ pub mod util {
 }
 
 fn eval_into<S: State + ?Sized, P: AsRef<str>>(src: &P, ctx: &mut Context<S>) {
-    use std::mem::uninitialized;
-    let mut dummy: &mut S = unsafe { uninitialized() };
-    let mut ctx = ctx.load(dummy);
+    let mut ctx = ctx.load_stateless();
     ctx.eval_str(src.as_ref()).unwrap();
 }
 
@@ -58,14 +58,22 @@ pub fn load_debug<S: State + ?Sized>(ctx: &mut Context<S>) {
     ctx.set_fn("debugger", ast_fn("debugger", self::debugger::debugger));
 }
 
+pub fn load_memory<S: State + ?Sized>(ctx: &mut Context<S>) {
+    ctx.set_fn("memory-stats", user_fn("memory-stats", self::core::memory_stats));
+}
+
+pub fn load_files<S: State + ?Sized>(ctx: &mut Context<S>) {
+    ctx.set_fn("load", user_fn("load", self::core::load));
+}
+
 pub fn load_map<S: State + ?Sized>(ctx: &mut Context<S>) {
-    ctx.set_fn("hash-map", ast_fn("hash-map", self::map::hash_map));
+    ctx.set_fn("hash-map", user_fn("hash-map", self::map::hash_map));
 }
 
 pub fn load_logical<S: State + ?Sized>(ctx: &mut Context<S>) {
-    ctx.set_fn("and", ast_fn("and", self::logical::and));
-    ctx.set_fn("or", ast_fn("or", self::logical::or));
-    ctx.set_fn("xor", ast_fn("xor", self::logical::xor));
+    ctx.set_fn("and", special_form("and", SpecialForm::And));
+    ctx.set_fn("or", special_form("or", SpecialForm::Or));
+    ctx.set_fn("xor", special_form("xor", SpecialForm::Xor));
 }
 
 pub fn load_option<S: State + ?Sized>(ctx: &mut Context<S>) {
@@ -76,28 +84,32 @@ pub fn load_option<S: State + ?Sized>(ctx: &mut Context<S>) {
 
 pub fn load_core<S: State + ?Sized>(ctx: &mut Context<S>) {
     ctx.set_fn("eval", user_fn("eval", self::core::eval));
-    ctx.set_fn("apply", user_fn("apply", self::core::apply));
-    ctx.set_fn("quote", ast_fn("quote", self::core::quote));
+    ctx.set_fn("apply", special_form("apply", SpecialForm::Apply));
+    ctx.set_fn("quote", special_form("quote", SpecialForm::Quote));
     ctx.set_fn("quasiquote", ast_fn("quasiquote", self::core::quasiquote));
     ctx.set_fn("macroexpand",
                user_fn("macroexpand", self::core::macroexpand));
     ctx.set_fn("unquote", ast_fn("unquote", self::core::unquote_error));
     ctx.set_fn("unquote-splicing",
                ast_fn("unquote-splicing", self::core::unquote_error));
-    ctx.set_fn("if", ast_fn("if", self::core::cond));
-    ctx.set_fn("let", ast_fn("let", self::core::lett));
-    ctx.set_fn("set", ast_fn("set", self::core::set));
-    ctx.set_fn("define", ast_fn("define", self::core::define));
+    ctx.set_fn("if", special_form("if", SpecialForm::If));
+    ctx.set_fn("let", special_form("let", SpecialForm::Let));
+    ctx.set_fn("set", special_form("set", SpecialForm::Set));
+    ctx.set_fn("define", special_form("define", SpecialForm::Define));
     ctx.set_fn("define-macro",
-               ast_fn("define-macro", self::core::define_macro));
-    ctx.set_fn("lambda", ast_fn("lambda", self::core::lambda));
+               special_form("define-macro", SpecialForm::DefineMacro));
+    ctx.set_fn("lambda", special_form("lambda", SpecialForm::Lambda));
+    ctx.set_fn("call/cc", special_form("call/cc", SpecialForm::CallCc));
+    ctx.set_fn("call/ec", special_form("call/ec", SpecialForm::CallEc));
+    ctx.set_fn("try", special_form("try", SpecialForm::Try));
+    ctx.set_fn("throw", free_fn("throw", self::core::throw));
     ctx.set_fn("gensym", user_fn("gensym", self::core::gensym));
 }
 
 pub fn load_list<S: State + ?Sized>(ctx: &mut Context<S>) {
     {
         ctx.set_fn("build-list", ast_fn("build-list", self::list::build_list));
-        ctx.set_fn("for-each", user_fn("for-each", self::list::foreach));
+        ctx.set_fn("for-each", special_form("for-each", SpecialForm::ForEach));
     }
     eval_into(&format!("(define list {})", self::list::LIST), ctx);
     eval_into(&format!("(define map {})", self::list::MAP), ctx);
@@ -186,11 +198,15 @@ pub fn load_types<S: State + ?Sized>(ctx: &mut Context<S>) {
     ctx.set_fn("->float", free_fn("->float", self::types::to_float));
     ctx.set_fn("->string", user_fn("->string", self::types::to_string));
     ctx.set_fn("->bool", free_fn("->bool", self::types::to_bool));
+    ctx.set_fn("->char", free_fn("->char", self::types::to_char));
+    ctx.set_fn("->keyword", user_fn("->keyword", self::types::to_keyword));
 
     ctx.set_fn("int?", free_fn("int?", self::types::is_int));
     ctx.set_fn("float?", free_fn("float?", self::types::is_float));
     ctx.set_fn("bool?", free_fn("bool?", self::types::is_bool));
     ctx.set_fn("string?", free_fn("string?", self::types::is_string));
+    ctx.set_fn("char?", free_fn("char?", self::types::is_char));
+    ctx.set_fn("keyword?", free_fn("keyword?", self::types::is_keyword));
     ctx.set_fn("list?", free_fn("list?", self::types::is_list));
     ctx.set_fn("lambda?", free_fn("lambda?", self::types::is_lambda));
     ctx.set_fn("foreign-fn?",
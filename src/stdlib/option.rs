@@ -4,7 +4,7 @@ use super::util::expect_arity;
 pub fn some(args: &[Value]) -> AresResult<Value> {
     try!(expect_arity(args, |l| l == 1, "exactly 1"));
     let first = args[0].clone();
-    Ok(Value::Option(Some(Box::new(first))))
+    Ok(Value::option(Some(first)))
 }
 
 pub fn none(args: &[Value]) -> AresResult<Value> {
@@ -1,5 +1,4 @@
 use std::collections::HashSet;
-use std::collections::HashMap;
 use std::rc::Rc;
 
 use {Value, AresResult, AresError, rc_to_usize, State, LoadedContext};
@@ -24,9 +23,11 @@ gen_is_type!(is_int, Int);
 gen_is_type!(is_float, Float);
 gen_is_type!(is_bool, Bool);
 gen_is_type!(is_string, String);
+gen_is_type!(is_char, Char);
 gen_is_type!(is_list, List);
 gen_is_type!(is_option, Option);
 gen_is_type!(is_symbol, Symbol);
+gen_is_type!(is_keyword, Keyword);
 gen_is_type!(is_lambda, Lambda);
 gen_is_type!(is_foreign_fn, ForeignFn);
 
@@ -75,7 +76,11 @@ pub fn to_int(values: &[Value]) -> AresResult<Value> {
         } else {
             0
         })),
-        &Value::String(ref s) => Ok(Value::Int(s.parse().unwrap())), // TODO: no unwrap here
+        &Value::String(ref s) => s.parse().map(Value::Int).map_err(|_| AresError::IllegalConversion {
+            value: Value::String(s.clone()),
+            into: "Int".to_string(),
+        }),
+        &Value::Char(c) => Ok(Value::Int(c as i64)),
         other => Err(AresError::IllegalConversion {
             value: other.clone(),
             into: "Int".to_string(),
@@ -91,7 +96,10 @@ pub fn to_float(values: &[Value]) -> AresResult<Value> {
     let res = match values.first().unwrap() {
         &Value::Int(i) => Ok(Value::Float(i as f64)),
         &Value::Float(f) => Ok(Value::Float(f)),
-        &Value::String(ref s) => Ok(Value::Float(s.parse().unwrap())), // TODO: no unwrap here
+        &Value::String(ref s) => s.parse().map(Value::Float).map_err(|_| AresError::IllegalConversion {
+            value: Value::String(s.clone()),
+            into: "Float".to_string(),
+        }),
         other => Err(AresError::IllegalConversion {
             value: other.clone(),
             into: "Float".to_string(),
@@ -131,89 +139,128 @@ pub fn to_bool(values: &[Value]) -> AresResult<Value> {
     res
 }
 
+pub fn to_char(values: &[Value]) -> AresResult<Value> {
+    try!(expect_arity(values, |l| l == 1, "exactly 1"));
+
+    let first = values.first().unwrap();
+    let res = match first {
+        &Value::Char(c) => Some(c),
+        &Value::Int(i) if i >= 0 && i <= ::std::u32::MAX as i64 => {
+            ::std::char::from_u32(i as u32)
+        }
+        &Value::String(ref s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    res.map(Value::Char).ok_or_else(|| AresError::IllegalConversion {
+        value: first.clone(),
+        into: "Char".to_string(),
+    })
+}
+
+pub fn to_keyword<S: State + ?Sized>(values: &[Value],
+                                     ctx: &mut LoadedContext<S>)
+                                     -> AresResult<Value> {
+    try!(expect_arity(values, |l| l == 1, "exactly 1"));
+
+    match values.first().unwrap() {
+        &Value::Keyword(k) => Ok(Value::Keyword(k)),
+        &Value::Symbol(s) => Ok(Value::Keyword(s)),
+        &Value::String(ref s) if !s.is_empty() => {
+            Ok(Value::Keyword(ctx.interner_mut().intern(&s[..])))
+        }
+        other => Err(AresError::IllegalConversion {
+            value: other.clone(),
+            into: "Keyword".to_string(),
+        }),
+    }
+}
+
 pub fn to_string<S: State + ?Sized>(values: &[Value],
                                     ctx: &mut LoadedContext<S>)
                                     -> AresResult<Value> {
     try!(expect_arity(values, |l| l == 1, "exactly 1"));
     let first = values.first().unwrap();
     let s = to_string_helper(&first, ctx.interner());
+    try!(ctx.memory().charge_string(s.len()));
     Ok(Value::String(Rc::new(s)))
 }
 
 // TODO: move this out of stdlib?  Seems way too useful.
 pub fn to_string_helper(value: &Value, interner: &SymbolIntern) -> String {
-    match value {
-        &Value::Int(i) => format!("{}", i),
-        &Value::Float(f) => format!("{}", f),
-        &Value::String(ref s) => (&**s).clone(),
-        &Value::Bool(b) => format!("{}", b),
-        &Value::Option(ref o) => {
-            match o {
-                &Some(ref value) => format!("Some({})", to_string_helper(value, interner)),
-                &None => "None".to_owned()
+    /// What is left to print, in reverse order.
+    enum Piece<'a> {
+        Value(&'a Value),
+        Text(&'static str),
+    }
+
+    let mut buf = String::new();
+    // Lists and maps that have been printed already, so that cycles end.
+    let mut seen = HashSet::new();
+    let mut pieces = vec![Piece::Value(value)];
+    while let Some(piece) = pieces.pop() {
+        let value = match piece {
+            Piece::Text(text) => {
+                buf.push_str(text);
+                continue;
             }
-        }
-        &Value::ForeignFn(ref ff) => format!("<#{}>", ff.name),
-        &Value::Lambda(ref l, _) => format!("<@{}>",
-                                            l.name.as_ref().map(|s| &s[..]).unwrap_or("anonymous")),
-        &Value::UserData(ref u) => format!("UserData@{}", rc_to_usize(u)),
-        &Value::Symbol(s) => format!("'{}", interner.lookup_or_anon(s)),
-
-        &ref l@Value::List(_) | &ref l@Value::Map(_) => {
-            fn format_singles(vec: &Rc<Vec<Value>>,
-                              buf: &mut String,
-                              seen: &mut HashSet<usize>,
-                              interner: &SymbolIntern) {
-                let ptr = rc_to_usize(vec);
-                if seen.contains(&ptr) {
-                    buf.push_str("...")
-                } else {
-                    seen.insert(ptr);
-                    buf.push_str("[");
-                    for v in vec.iter() {
-                        build_buf(v, buf, seen, interner);
-                        buf.push_str(", ");
-                    }
-                    // remove trailing comma and space
-                    if vec.len() >= 1 {
-                        buf.pop();
-                        buf.pop();
-                    }
-                    buf.push_str("]");
-                }
+            Piece::Value(value) => value,
+        };
+        match value {
+            &Value::Int(i) => buf.push_str(&format!("{}", i)),
+            &Value::Float(f) => buf.push_str(&format!("{}", f)),
+            &Value::String(ref s) => buf.push_str(s),
+            &Value::Bool(b) => buf.push_str(&format!("{}", b)),
+            &Value::Char(c) => buf.push(c),
+            &Value::Option(Some(ref value)) => {
+                buf.push_str("Some(");
+                pieces.push(Piece::Text(")"));
+                pieces.push(Piece::Value(value));
             }
-            fn format_pairs(m: &Rc<HashMap<Value, Value>>,
-                            buf: &mut String,
-                            seen: &mut HashSet<usize>,
-                            interner: &SymbolIntern) {
-                let ptr = rc_to_usize(m);
-                if seen.contains(&ptr) {
-                    buf.push_str("...")
-                } else {
-                    seen.insert(ptr);
-                    buf.push_str("{");
-                    for (k, v) in m.iter() {
-                        build_buf(k, buf, seen, interner);
-                        buf.push_str(", ");
-                        build_buf(v, buf, seen, interner);
+            &Value::Option(None) => buf.push_str("None"),
+            &Value::ForeignFn(ref ff) => buf.push_str(&format!("<#{}>", ff.name)),
+            &Value::Lambda(ref l, _) => {
+                let name = l.name.as_ref().map(|s| &s[..]).unwrap_or("anonymous");
+                buf.push_str(&format!("<@{}>", name))
+            }
+            &Value::UserData(ref u) => buf.push_str(&format!("UserData@{}", rc_to_usize(u))),
+            &Value::Symbol(s) => buf.push_str(&format!("'{}", interner.lookup_or_anon(s))),
+            &Value::Keyword(s) => buf.push_str(&format!(":{}", interner.lookup_or_anon(s))),
+            &Value::List(ref vec) => {
+                if !seen.insert(rc_to_usize(vec)) {
+                    buf.push_str("...");
+                    continue;
+                }
+                buf.push_str("[");
+                pieces.push(Piece::Text("]"));
+                for (i, v) in vec.iter().enumerate().rev() {
+                    pieces.push(Piece::Value(v));
+                    if i > 0 {
+                        pieces.push(Piece::Text(", "));
                     }
-                    buf.push_str("}")
                 }
             }
-            fn build_buf(cur: &Value,
-                         buf: &mut String,
-                         seen: &mut HashSet<usize>,
-                         interner: &SymbolIntern) {
-                match cur {
-                    &Value::List(ref v) => format_singles(v, buf, seen, interner),
-                    &Value::Map(ref m) => format_pairs(m, buf, seen, interner),
-                    other => buf.push_str(&to_string_helper(&other, interner)),
+            &Value::Map(ref m) => {
+                if !seen.insert(rc_to_usize(m)) {
+                    buf.push_str("...");
+                    continue;
+                }
+                buf.push_str("{");
+                pieces.push(Piece::Text("}"));
+                let pairs: Vec<_> = m.iter().collect();
+                for &(k, v) in pairs.iter().rev() {
+                    pieces.push(Piece::Value(v));
+                    pieces.push(Piece::Text(", "));
+                    pieces.push(Piece::Value(k));
                 }
             }
-            let mut inner = String::new();
-            let mut seen = HashSet::new();
-            build_buf(&l, &mut inner, &mut seen, interner);
-            inner
         }
     }
+    buf
 }
@@ -17,7 +17,6 @@ pub fn prompt<P: ?Sized + AsRef<str>>(prompt: &P) -> Option<String> {
 
 
 pub mod rc_slice {
-    #![allow(unused)]
     use std::rc::Rc;
     use {Value, rc_to_usize};
 
@@ -34,14 +33,30 @@ pub mod rc_slice {
         }
 
         pub fn new(v: Vec<Value>) -> RcSlice {
-            let len = v.len();
+            RcSlice::from_rc(Rc::new(v))
+        }
+
+        /// All of `data`, without copying it.
+        pub fn from_rc(data: Rc<Vec<Value>>) -> RcSlice {
+            let len = data.len();
             RcSlice {
-                data: Rc::new(v),
+                data: data,
                 start: 0,
                 len: len,
             }
         }
 
+        /// Splits off the first value, if there is one.
+        pub fn pop_front(&mut self) -> Option<Value> {
+            if self.len == 0 {
+                return None;
+            }
+            let first = self.data[self.start].clone();
+            self.start += 1;
+            self.len -= 1;
+            Some(first)
+        }
+
         pub fn tail(&self) -> RcSlice {
             self.slice(1, self.len)
         }
@@ -59,12 +74,15 @@ pub mod rc_slice {
         }
 
         pub fn slice(&self, from: usize, to: usize) -> RcSlice {
-            // TODO: make this checked subtractions
-            let len = to - from;
+            assert!(from <= to && to <= self.len,
+                    "RcSlice::slice(..): {}..{} is out of bounds of {}",
+                    from,
+                    to,
+                    self.len);
             RcSlice {
                 data: self.data.clone(),
-                start: from,
-                len: len,
+                start: self.start + from,
+                len: to - from,
             }
         }
 
@@ -87,11 +105,25 @@ pub mod rc_slice {
         }
     }
 
+    impl ::std::ops::Deref for RcSlice {
+        type Target = [Value];
+
+        fn deref(&self) -> &[Value] {
+            self.get_slice()
+        }
+    }
+
+    impl ::std::fmt::Debug for RcSlice {
+        fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+            self.get_slice().fmt(fmt)
+        }
+    }
+
     impl PartialEq for RcSlice {
         fn eq(&self, other: &RcSlice) -> bool {
             // lengths *have* to be equal
             self.len == other.len &&
-            ((rc_to_usize(&self.data) == rc_to_usize(&self.data) && self.start == other.start) ||
+            ((rc_to_usize(&self.data) == rc_to_usize(&other.data) && self.start == other.start) ||
              self.get_slice() == other.get_slice())
         }
     }
@@ -42,3 +42,10 @@ fn wrapping_int_arith() {
     eval_ok!(&format!("(* {0} {0})", ::std::i64::MAX));
     eval_ok!(&format!("(/ {0} {0})", ::std::i64::MAX));
 }
+
+#[test]
+fn radix_literals() {
+    eval_ok!("(count_ones 0xFF)", 8);
+    eval_ok!("(+ 0b1010 0o10 0x1_0 1_000)", 1034);
+    eval_ok!("(- 0x10)", -16);
+}
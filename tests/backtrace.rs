@@ -0,0 +1,84 @@
+extern crate ares;
+
+use ares::{Context, AresError, Value};
+
+/// The names of the functions in the backtrace of the last error.
+fn names(ctx: &ares::LoadedContext<()>) -> Vec<String> {
+    ctx.backtrace()
+       .unwrap()
+       .frames
+       .iter()
+       .map(|frame| frame.name.clone().unwrap_or("<lambda>".into()))
+       .collect()
+}
+
+#[test]
+fn errors_come_with_the_calls_that_led_to_them() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define inner (lambda (x) (+ x true)))
+                  (define outer (lambda (x y)
+                    (inner x)
+                    y))")
+       .unwrap();
+    match ctx.eval_str("(outer 1 2)") {
+        Err(AresError::UnexpectedType { .. }) => {}
+        other => panic!("{:?} didn't fail", other),
+    }
+    assert_eq!(names(&ctx), vec!["+", "inner", "outer"]);
+
+    let frames = &ctx.backtrace().unwrap().frames;
+    assert_eq!(frames[0].args, vec![Value::Int(1), Value::Bool(true)]);
+    assert_eq!(frames[1].args, vec![Value::Int(1)]);
+    assert_eq!(frames[2].args, vec![Value::Int(1), Value::Int(2)]);
+    let outer = frames[2].span.unwrap();
+    assert_eq!((outer.start.0, outer.start.1), (1, 1));
+    assert!(frames.iter().all(|frame| frame.span.is_some()));
+
+    let printed = ctx.format_backtrace(ctx.backtrace().unwrap());
+    assert_eq!(printed.lines().count(), 3);
+    assert!(printed.lines().next().unwrap().starts_with("  in (+ 1 true) at line 1"));
+
+    // Successful evaluations clear the backtrace.
+    ctx.eval_str("(outer 1 (+ 1 1))").unwrap_err();
+    ctx.eval_str("(+ 1 2)").unwrap();
+    assert!(ctx.backtrace().is_none());
+}
+
+#[test]
+fn calls_still_evaluating_their_arguments_are_included() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define f (lambda (x) (+ 1 (g x))))
+                  (define g (lambda (x) (undefined x)))")
+       .unwrap();
+    ctx.eval_str("(f 5)").unwrap_err();
+    assert_eq!(names(&ctx), vec!["g", "+", "f"]);
+    assert_eq!(ctx.backtrace().unwrap().frames[1].args, vec![Value::Int(1)]);
+
+    // So are the foreign functions that evaluate scripts themselves.
+    ctx.eval_str("(eval (list (quote f) 5))").unwrap_err();
+    assert_eq!(names(&ctx), vec!["g", "+", "f", "eval"]);
+}
+
+#[test]
+fn tail_calls_replace_their_callers() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define down (lambda (n) (if (= n 0) (fail) (down (- n 1)))))
+                  (define fail (lambda () (let (x 1) (+ x :oops))))")
+       .unwrap();
+    ctx.eval_str("(down 100)").unwrap_err();
+    assert_eq!(names(&ctx), vec!["+", "fail"]);
+
+    // Calls that fail before they start are in the backtrace too.
+    match ctx.eval_str("(+ 1 (down))") {
+        Err(AresError::UnexpectedArity { .. }) => {}
+        other => panic!("{:?} didn't fail", other),
+    }
+    assert_eq!(names(&ctx), vec!["down", "+"]);
+    assert!(ctx.backtrace().unwrap().frames[0].args.is_empty());
+}
@@ -0,0 +1,160 @@
+extern crate ares;
+
+use ares::{Context, AresError, AresResult, Value, user_fn};
+
+fn eval(compile: bool, program: &str) -> AresResult<Value> {
+    let mut ctx = Context::new_empty();
+    ctx.set_compile_lambdas(compile);
+    ares::stdlib::load_all(&mut ctx);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str(program)
+}
+
+/// Evaluates `program` with and without compiling lambdas, which should
+/// give the same value.
+fn both(program: &str) -> Value {
+    let walked = eval(false, program).unwrap();
+    let compiled = eval(true, program).unwrap();
+    assert_eq!(walked, compiled);
+    compiled
+}
+
+#[test]
+fn errors_are_caught_by_kind() {
+    assert_eq!(both("(try (unwrap (none)) (catch :unwrap-none e 0))"), Value::Int(0));
+    assert_eq!(both("(try (->int \"abc\") (catch :conversion e (:into e 0)))"),
+               Value::string("Int"));
+    assert_eq!(both("(try undefined-thing (catch :undefined-name e (:name e 0)))"),
+               Value::string("undefined-thing"));
+    assert_eq!(both("(try ((lambda (x y) x) 1) (catch :arity e (:found e 0)))"),
+               Value::Int(1));
+    assert_eq!(both("(try (+ 1 true) (catch :type e (:value e 0)))"), Value::Bool(true));
+    // The first clause for the kind is used.
+    assert_eq!(both("(try (unwrap (none))
+                       (catch :type e 1)
+                       (catch :unwrap-none e 2)
+                       (catch e 3))"),
+               Value::Int(2));
+    assert_eq!(both("(= (try (unwrap (none)) (catch :type e 1) (catch e (:kind e 0))) :unwrap-none)"),
+               Value::Bool(true));
+    // Other kinds go on to the `try`s further out.
+    assert_eq!(both("(try (try (unwrap (none)) (catch :type e 1)) (catch :unwrap-none e 2))"),
+               Value::Int(2));
+    // Without an error, the value of the last body is the value of the `try`.
+    assert_eq!(both("(try 1 2 (catch e 3))"), Value::Int(2));
+}
+
+#[test]
+fn thrown_values_are_caught() {
+    assert_eq!(both("(try (throw (list 1 2)) (catch :thrown e (:value e 0)))"),
+               Value::list(vec![Value::Int(1), Value::Int(2)]));
+    assert_eq!(both("((lambda (x) (try (+ 1 (throw x)) (catch :thrown e (:value e 0)))) 5)"),
+               Value::Int(5));
+    match eval(true, "(throw 5)") {
+        Err(AresError::Thrown(Value::Int(5))) => {}
+        other => panic!("{:?} wasn't thrown", other),
+    }
+}
+
+#[test]
+fn errors_are_caught_from_inside_foreign_functions() {
+    assert_eq!(both("(try (for-each (list 1 2) (lambda (x) (throw x)))
+                       (catch :thrown e (:value e 0)))"),
+               Value::Int(1));
+    // A `try` inside of the callback only sees its own errors.
+    assert_eq!(both("(for-each (list 1 2) (lambda (x) (try (throw x) (catch e 0))))"),
+               Value::Int(2));
+}
+
+#[test]
+fn errors_from_rust_are_caught_as_data() {
+    let mut ctx = Context::new();
+    ctx.set_fn("fail", user_fn("fail", |_, _| Err(AresError::user_error(5u32))));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let data = ctx.eval_str("(try (fail) (catch :user e (:data e 0)))").unwrap();
+    assert_eq!(data.as_user_data::<u32>(), Some(&5));
+}
+
+#[test]
+fn finally_runs_on_every_exit() {
+    let program = "(define runs 0)
+                   (define count (lambda () (set runs (+ runs 1))))
+                   (try 1 (finally (count)))
+                   (try (throw 1) (catch e 2) (finally (count)))
+                   (call/ec (lambda (k) (try (k 1) (finally (count)))))
+                   (list (try 1 (finally 5)) runs)";
+    assert_eq!(both(program),
+               Value::list(vec![Value::Int(1), Value::Int(3)]));
+
+    for &compile in &[false, true] {
+        let mut ctx = Context::new_empty();
+        ctx.set_compile_lambdas(compile);
+        ares::stdlib::load_all(&mut ctx);
+        let mut state = ();
+        let mut ctx = ctx.load(&mut state);
+        ctx.eval_str("(define runs 0)").unwrap();
+        // Uncaught errors and errors in a `catch` are raised again after the
+        // `finally`, from where they happened.
+        let program = "(try (+ 1 true)\n  (catch :arity e 1)\n  (finally (set runs (+ runs 1))))";
+        match ctx.eval_str(program) {
+            Err(AresError::UnexpectedType { .. }) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        let start = ctx.error_span().unwrap().start;
+        assert_eq!((start.0, start.1), (1, 6));
+        let program = "(try (throw 1) (catch e (unwrap (none))) (finally (set runs (+ runs 1))))";
+        match ctx.eval_str(program) {
+            Err(AresError::UnwrapNone) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        assert_eq!(ctx.eval_str("runs").unwrap(), Value::Int(2));
+    }
+}
+
+#[test]
+fn caught_errors_are_forgotten() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(try (unwrap (none)) (catch e 1))").unwrap();
+    assert_eq!(ctx.error_span(), None);
+    assert!(ctx.backtrace().is_none());
+
+    let program = "(let (x (try (unwrap (none)) (catch e 1)))\n  (+ x true))";
+    ctx.eval_str(program).unwrap_err();
+    let start = ctx.error_span().unwrap().start;
+    assert_eq!((start.0, start.1), (2, 3));
+    let names: Vec<_> = ctx.backtrace().unwrap().frames.iter().map(|f| f.name.clone()).collect();
+    assert_eq!(names, vec![Some("+".to_string())]);
+}
+
+#[test]
+fn limits_are_not_caught() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define loop (lambda () (loop)))").unwrap();
+    ctx.set_fuel(Some(1000));
+    match ctx.eval_str("(try (loop) (catch e 1) (finally 2))") {
+        Err(AresError::OutOfFuel) => {}
+        other => panic!("{:?} didn't run out of fuel", other),
+    }
+}
+
+#[test]
+fn malformed_trys_fail() {
+    for program in &["(try)",
+                     "(try (catch e 1))",
+                     "(try 1 (catch :not-a-kind e 1))",
+                     "(try 1 (catch e))",
+                     "(try 1 (finally 2) (catch e 1))",
+                     "(try 1 (catch e 1) 2)"] {
+        match eval(true, program) {
+            Err(AresError::UnexpectedArity { .. }) |
+            Err(AresError::UnexpectedType { .. }) => {}
+            other => panic!("{} gave {:?}", program, other),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+extern crate ares;
+
+use ares::{classify_tokens, ClassifiedToken, TokenClass, Open, Close, FormLike, Position, Span};
+
+fn classes(input: &str) -> Vec<(TokenClass, &str)> {
+    classify_tokens(input).map(|tok| (tok.class, &input[tok.range])).collect()
+}
+
+#[test]
+fn classifies_every_kind_of_token() {
+    let input = "(define [x] '{:k \"v\"}) ; done\n#color `(true ~@false 0x1F \\a #| c |# #_ skip)";
+    assert_eq!(classes(input),
+               vec![(TokenClass::Open(Open::LParen), "("),
+                    (TokenClass::Symbol, "define"),
+                    (TokenClass::Open(Open::LBracket), "["),
+                    (TokenClass::Symbol, "x"),
+                    (TokenClass::Close(Close::RBracket), "]"),
+                    (TokenClass::FormLike(FormLike::Quote), "'"),
+                    (TokenClass::Open(Open::LBrace), "{"),
+                    (TokenClass::Keyword, ":k"),
+                    (TokenClass::String, "\"v\""),
+                    (TokenClass::Close(Close::RBrace), "}"),
+                    (TokenClass::Close(Close::RParen), ")"),
+                    (TokenClass::Comment, "; done"),
+                    (TokenClass::ReaderMacro, "#color"),
+                    (TokenClass::FormLike(FormLike::QuasiQuote), "`"),
+                    (TokenClass::Open(Open::LParen), "("),
+                    (TokenClass::Bool, "true"),
+                    (TokenClass::FormLike(FormLike::UnquoteSplicing), "~@"),
+                    (TokenClass::Bool, "false"),
+                    (TokenClass::Number, "0x1F"),
+                    (TokenClass::Char, "\\a"),
+                    (TokenClass::Comment, "#| c |#"),
+                    (TokenClass::Comment, "#_ skip"),
+                    (TokenClass::Close(Close::RParen), ")")]);
+}
+
+#[test]
+fn tokens_have_spans() {
+    let tokens: Vec<ClassifiedToken> = classify_tokens("(a\n  ; hi\n  bc)").collect();
+    assert_eq!(tokens[1].span, Span::new(Position::new(1, 2), Position::new(1, 3)));
+    assert_eq!(tokens[2].class, TokenClass::Comment);
+    assert_eq!(tokens[2].span, Span::new(Position::new(2, 3), Position::new(2, 7)));
+    assert_eq!(tokens[3].span, Span::new(Position::new(3, 3), Position::new(3, 5)));
+}
+
+#[test]
+fn errors_do_not_stop_tokenizing() {
+    let input = "(foo \\bogus 1.2.x § \"a\\qb\" bar) \"unterminated";
+    let tokens = classes(input);
+    let texts: Vec<_> = tokens.iter().map(|&(_, text)| text).collect();
+    assert_eq!(texts,
+               vec!["(", "foo", "\\bogus", "1.2.x", "§", "\"a\\qb\"", "bar", ")",
+                    "\"unterminated"]);
+    for &i in &[2, 3, 4, 5, 8] {
+        match tokens[i].0 {
+            TokenClass::Error(_) => {}
+            ref other => panic!("{} was {:?}, not an error", texts[i], other),
+        }
+    }
+    assert_eq!(tokens[6].0, TokenClass::Symbol);
+
+    let tokens = classes("x #| never closed");
+    match tokens[1] {
+        (TokenClass::Error(ref message), "#| never closed") => {
+            assert_eq!(message, "Unterminated block comment beginning at line 1, column 3");
+        }
+        ref other => panic!("{:?} is not an unterminated comment", other),
+    }
+}
+
+#[test]
+fn half_typed_escapes_are_errors() {
+    for &input in &["\"\\u{", "\"\\u{]]\"", "\"\\é", "\"\\é\"", "\"\\x4é\""] {
+        let tokens = classes(input);
+        assert_eq!(tokens.len(), 1, "{:?} gave {:?}", input, tokens);
+        match tokens[0] {
+            (TokenClass::Error(_), text) => assert_eq!(text, input),
+            ref other => panic!("{:?} gave {:?}", input, other),
+        }
+    }
+    assert_eq!(classes("\"\\u{e9}\""), vec![(TokenClass::String, "\"\\u{e9}\"")]);
+}
@@ -0,0 +1,144 @@
+extern crate ares;
+
+use ares::{Context, AresError, AresResult, Value};
+
+fn eval(compile: bool, program: &str) -> AresResult<Value> {
+    let mut ctx = Context::new_empty();
+    ctx.set_compile_lambdas(compile);
+    ares::stdlib::load_all(&mut ctx);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str(program)
+}
+
+/// Evaluates `program` with and without compiling lambdas, which should
+/// give the same value.
+fn both(program: &str) -> Value {
+    let walked = eval(false, program).unwrap();
+    let compiled = eval(true, program).unwrap();
+    assert_eq!(walked, compiled);
+    compiled
+}
+
+#[test]
+fn compiled_lambdas_give_the_same_results() {
+    assert_eq!(both("((lambda (x y) (if (= x 0) y (+ x y))) 1 2)"), Value::Int(3));
+    assert_eq!(both("((lambda (x) (let (a 1 b (+ a x)) (list a b))) 5)"),
+               Value::list(vec![Value::Int(1), Value::Int(6)]));
+    assert_eq!(both("((lambda (x) (and true (or false x))) true)"), Value::Bool(true));
+    assert_eq!(both("((lambda () (and)))"), Value::Bool(true));
+    assert_eq!(both("((lambda () (or)))"), Value::Bool(false));
+    match both("((lambda (x) 'x) 1)") {
+        Value::Symbol(_) => {}
+        other => panic!("{:?} isn't a symbol", other),
+    }
+    assert_eq!(both("((lambda (a . rest) rest) 1 2 3)"),
+               Value::list(vec![Value::Int(2), Value::Int(3)]));
+    assert_eq!(both("(map (list 1 2 3) (lambda (x) (* x x)))"),
+               Value::list(vec![Value::Int(1), Value::Int(4), Value::Int(9)]));
+}
+
+#[test]
+fn closures_and_set_see_the_same_variables() {
+    let program = "(define counter (lambda ()
+                     (let (n 0)
+                       (lambda () (set n (+ n 1)) n))))
+                   (define c (counter))
+                   (c)
+                   (c)
+                   (c)";
+    assert_eq!(both(program), Value::Int(3));
+
+    let program = "(define x 1)
+                   (define bump (lambda () (set x (+ x 1))))
+                   (bump)
+                   (bump)
+                   x";
+    assert_eq!(both(program), Value::Int(3));
+}
+
+#[test]
+fn slots_are_shadowed_like_variables() {
+    let ints = |v: &[i64]| Value::list(v.iter().map(|&i| Value::Int(i)).collect());
+    // Defined at runtime in between the slot and the use.
+    assert_eq!(both("((lambda (x) ((lambda () (define x 5) x))) 1)"), Value::Int(5));
+    assert_eq!(both("((lambda () (define y 3) (+ y 1)))"), Value::Int(4));
+    // Bindings of a `let` that haven't happened yet.
+    assert_eq!(both("((lambda (x) (let (y x x 5) (list y x))) 1)"), ints(&[1, 5]));
+    assert_eq!(both("((lambda (x) (let (f (lambda () x) x 5) (f))) 1)"), Value::Int(5));
+    assert_eq!(both("((lambda (x) (let (a 1 b a a 2) (list a b x))) 3)"), ints(&[2, 1, 3]));
+    // The last of the same name wins.
+    assert_eq!(both("((lambda (x x) x) 1 2)"), Value::Int(2));
+    assert_eq!(both("((lambda (x) ((lambda (y) ((lambda (x) (list x y)) 3)) 2)) 1)"),
+               ints(&[3, 2]));
+}
+
+#[test]
+fn special_forms_can_be_shadowed() {
+    assert_eq!(both("((lambda (if) (if 1 2 3)) (lambda (a b c) (+ a b c)))"), Value::Int(6));
+    assert_eq!(both("((lambda (let) (let 1 2)) +)"), Value::Int(3));
+    assert_eq!(both("(define f (lambda () (and 1 2)))
+                     (set and (lambda (a b) (* a b)))
+                     (f)"),
+               Value::Int(2));
+}
+
+#[test]
+fn macros_and_ast_functions_work_in_compiled_lambdas() {
+    let program = "(define-macro unless (lambda (c body) `(if ~c false ~body)))
+                   ((lambda (x) (unless (= x 0) `(a ~x))) 2)";
+    match both(program) {
+        Value::List(ref items) => assert_eq!(items[1], Value::Int(2)),
+        other => panic!("{:?} isn't a list", other),
+    }
+    assert_eq!(both("((lambda () (build-list (lambda (push) (push 1) (push 2)))))"),
+               Value::list(vec![Value::Int(1), Value::Int(2)]));
+}
+
+#[test]
+fn tail_calls_in_lets_dont_grow_the_stack() {
+    let program = "(define loop (lambda (n)
+                     (let (m (- n 1))
+                       (if (= m 0) true (loop m)))))
+                   (loop 100000)";
+    assert_eq!(both(program), Value::Bool(true));
+}
+
+#[test]
+fn compiled_lambdas_fail_the_same_way() {
+    for &compile in &[false, true] {
+        match eval(compile, "((lambda (x) (if x 1 2)) 5)") {
+            Err(AresError::UnexpectedType { .. }) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        match eval(compile, "((lambda () (undefined-thing)))") {
+            Err(AresError::UndefinedName(ref name)) if name == "undefined-thing" => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        match eval(compile, "((lambda () (1 2)))") {
+            Err(AresError::UnexecutableValue(Value::Int(1))) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+    }
+}
+
+#[test]
+fn compiled_lambdas_have_the_same_backtraces() {
+    let program = "(define inner (lambda (x) (let (y x) (+ 1 (+ y true)))))
+                   (define outer (lambda (x) (list (inner x))))
+                   (outer 1)";
+    let mut backtraces = vec![];
+    for &compile in &[false, true] {
+        let mut ctx = Context::new_empty();
+        ctx.set_compile_lambdas(compile);
+        ares::stdlib::load_all(&mut ctx);
+        let mut state = ();
+        let mut ctx = ctx.load(&mut state);
+        ctx.eval_str(program).unwrap_err();
+        backtraces.push(ctx.backtrace().unwrap().clone());
+    }
+    assert_eq!(backtraces[0], backtraces[1]);
+    let names: Vec<_> = backtraces[1].frames.iter().map(|frame| frame.name.clone()).collect();
+    assert_eq!(names[..4].to_vec(),
+               vec![Some("+".to_string()), Some("+".into()), Some("inner".into()), Some("list".into())]);
+}
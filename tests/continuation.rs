@@ -0,0 +1,173 @@
+extern crate ares;
+
+use ares::{Context, AresError, AresResult, Value, ExecutionStatus, user_fn};
+
+fn eval(compile: bool, program: &str) -> AresResult<Value> {
+    let mut ctx = Context::new_empty();
+    ctx.set_compile_lambdas(compile);
+    ares::stdlib::load_all(&mut ctx);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str(program)
+}
+
+/// Evaluates `program` with and without compiling lambdas, which should
+/// give the same value.
+fn both(program: &str) -> Value {
+    let walked = eval(false, program).unwrap();
+    let compiled = eval(true, program).unwrap();
+    assert_eq!(walked, compiled);
+    compiled
+}
+
+fn ints(v: &[i64]) -> Value {
+    Value::list(v.iter().map(|&i| Value::Int(i)).collect())
+}
+
+#[test]
+fn continuations_give_the_value_of_the_call() {
+    for call in &["call/cc", "call/ec"] {
+        let program = format!("(+ 1 ({} (lambda (k) (+ 10 (k 1)))))", call);
+        assert_eq!(both(&program), Value::Int(2));
+        let program = format!("(+ 1 ({} (lambda (k) 5)))", call);
+        assert_eq!(both(&program), Value::Int(6));
+        let program = format!("((lambda (x) (let (y 2) (+ x y ({} (lambda (k) (k 10)))))) 1)",
+                              call);
+        assert_eq!(both(&program), Value::Int(13));
+    }
+}
+
+#[test]
+fn full_continuations_can_be_resumed_more_than_once() {
+    let program = "(let (count 0
+                         again false
+                         n (+ 100 (call/cc (lambda (k) (set again k) 1))))
+                     (set count (+ count 1))
+                     (if (= count 3) (list n count) (again count)))";
+    assert_eq!(both(program), ints(&[102, 3]));
+}
+
+#[test]
+fn backtracking_search() {
+    let program = "((lambda ()
+        (define fail (lambda () false))
+        (define choose (lambda (low high)
+          (call/cc (lambda (k)
+            (define previous fail)
+            (define try (lambda (i)
+              (if (= i high)
+                  (let (x (set fail previous)) (previous))
+                  (let (x (call/cc (lambda (next)
+                                     (set fail (lambda () (next false)))
+                                     (k i))))
+                    (try (+ i 1))))))
+            (try low)))))
+        (let (a (choose 1 20)
+              b (choose a 20)
+              c (choose b 20))
+          (if (= (+ (* a a) (* b b)) (* c c))
+              (list a b c)
+              (fail)))))";
+    assert_eq!(both(program), ints(&[3, 4, 5]));
+}
+
+#[test]
+fn continuations_escape_from_foreign_functions() {
+    for call in &["call/cc", "call/ec"] {
+        let program = format!("({} (lambda (return)
+                                    (for-each (list 1 2 3)
+                                              (lambda (x) (if (= x 2) (return x) false)))
+                                    99))",
+                              call);
+        assert_eq!(both(&program), Value::Int(2));
+        // The list that was being built is thrown away.
+        let program = format!("({} (lambda (return) (build-list (lambda (push) (push 1) (return 5)))))",
+                              call);
+        assert_eq!(both(&program), Value::Int(5));
+    }
+    let program = "(build-list (lambda (push)
+                     (call/ec (lambda (stop)
+                       (for-each (list 1 2 3) (lambda (x) (if (= x 3) (stop x) (push x))))))))";
+    assert_eq!(both(program), ints(&[1, 2]));
+}
+
+#[test]
+fn continuations_only_work_while_their_evaluation_is_going() {
+    for &compile in &[false, true] {
+        // Each top level form is its own evaluation.
+        match eval(compile, "(define e false) (call/ec (lambda (k) (set e k))) (e 1)") {
+            Err(AresError::DeadContinuation) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        match eval(compile, "(define c false) (call/cc (lambda (k) (set c k))) (c 1)") {
+            Err(AresError::DeadContinuation) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        // So is each call that a foreign function makes.
+        let program = "(define saved false)
+                       (let (n (eval (quote (call/cc (lambda (k) (set saved k) 0)))))
+                         (if (= n 0) (saved 1) n))";
+        match eval(compile, program) {
+            Err(AresError::DeadContinuation) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+        // An escape continuation is done once its `call/ec` is.
+        match eval(compile, "(let (e (call/ec (lambda (k) k))) (e 1))") {
+            Err(AresError::DeadContinuation) => {}
+            other => panic!("{:?} didn't fail", other),
+        }
+    }
+}
+
+#[test]
+fn foreign_functions_pass_continuations_on() {
+    let mut ctx = Context::new();
+    ctx.set_fn("call-twice",
+               user_fn("call-twice", |args, ctx| {
+                   try!(ctx.call(&args[0], &[]));
+                   ctx.call(&args[0], &[])
+               }));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let program = "(define calls 0)
+                   (call/ec (lambda (k) (call-twice (lambda () (set calls (+ calls 1)) (k calls)))))";
+    assert_eq!(ctx.eval_str(program).unwrap(), Value::Int(1));
+    assert_eq!(ctx.eval_str("calls").unwrap(), Value::Int(1));
+}
+
+#[test]
+fn errors_after_a_continuation_is_called_are_reported_normally() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let program = "(let (x (call/ec (lambda (k) (k 1))))\n  (+ x true))";
+    ctx.eval_str(program).unwrap_err();
+    let start = ctx.error_span().unwrap().start;
+    assert_eq!((start.0, start.1), (2, 3));
+    let names: Vec<_> = ctx.backtrace().unwrap().frames.iter().map(|f| f.name.clone()).collect();
+    assert_eq!(names, vec![Some("+".to_string())]);
+}
+
+#[test]
+fn continuations_work_in_executions() {
+    let mut ctx: Context<()> = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let program = ares::parse("(let (n 0 k (call/cc (lambda (k) k)))
+                                 (set n (+ n 1))
+                                 (if (= n 3) n (k k)))",
+                              ctx.interner_mut())
+        .unwrap()
+        .remove(0);
+    let mut execution = ctx.start(program).unwrap();
+    loop {
+        match execution.run_for(&mut ctx, 3) {
+            ExecutionStatus::Running => {}
+            ExecutionStatus::Finished(value) => {
+                assert_eq!(value, Value::Int(3));
+                break;
+            }
+            ExecutionStatus::Error(e) => panic!("{:?}", e),
+        }
+    }
+}
@@ -83,3 +83,17 @@ fn is_bool() {
 }
 
 // TODO: is_string is_list is_ident is_lambda is_foreign_fn
+
+#[test]
+fn convert_chars() {
+    eval_ok!("(->int \\a)", 97);
+    eval_ok!("(->char 97)", 'a');
+    eval_ok!("(->char \"a\")", 'a');
+    eval_ok!("(->char \\a)", 'a');
+    eval_ok!("(->string \\a)", "a");
+
+    eval_err!("(->char -1)", IllegalConversion{..});
+    eval_err!("(->char 55296)", IllegalConversion{..});
+    eval_err!("(->char \"ab\")", IllegalConversion{..});
+    eval_err!("(->char 1.0)", IllegalConversion{..});
+}
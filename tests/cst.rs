@@ -0,0 +1,92 @@
+extern crate ares;
+
+use ares::{parse, Cst, NodeKind, Open, FormLike, Position};
+use ares::intern::SymbolIntern;
+
+const PROGRAM: &'static str = "; a program
+(define (f x)   #| block #| nested |# |#
+  [x 'y ~@z `(1 2.50 0)]  #_(ignored form)
+  {\"a\\x41\" \"\\u{2764}\" :b    1e3})
+
+'   quoted
+   ; trailing comment
+";
+
+#[test]
+fn prints_back_byte_for_byte() {
+    for program in &[PROGRAM, "", "   ", "x", "(a)\n", "'", "[ ]", "#_ foo"] {
+        let cst = Cst::parse(program).unwrap();
+        assert_eq!(&cst.to_string(), program);
+    }
+}
+
+#[test]
+fn keeps_trivia_and_shorthands() {
+    let cst = Cst::parse(PROGRAM).unwrap();
+    assert_eq!(cst.forms.len(), 2);
+    assert_eq!(cst.trailing, "\n   ; trailing comment\n");
+
+    let define = &cst.forms[0];
+    assert_eq!(define.leading, "; a program\n");
+    assert_eq!(define.span.start, Position::new(2, 1));
+    let children = match define.kind {
+        NodeKind::Delimited(Open::LParen, ref children, ref trailing) => {
+            assert_eq!(trailing, "");
+            children
+        }
+        ref other => panic!("{:?} is not a list", other),
+    };
+    assert_eq!(children.len(), 4);
+    assert_eq!(children[2].leading, "   #| block #| nested |# |#\n  ");
+    match children[2].kind {
+        NodeKind::Delimited(Open::LBracket, ref items, _) => {
+            assert_eq!(items.len(), 4);
+            match items[2].kind {
+                NodeKind::Prefixed(FormLike::UnquoteSplicing, Some(ref z)) => {
+                    assert_eq!(z.kind, NodeKind::Atom("z".into()))
+                }
+                ref other => panic!("{:?} is not an unquote-splicing", other),
+            }
+        }
+        ref other => panic!("{:?} is not a bracket list", other),
+    }
+    assert_eq!(children[3].leading, "  #_(ignored form)\n  ");
+    match children[3].kind {
+        NodeKind::Delimited(Open::LBrace, ref items, _) => {
+            assert_eq!(items[0].kind, NodeKind::Atom("\"a\\x41\"".into()));
+        }
+        ref other => panic!("{:?} is not a map", other),
+    }
+
+    match cst.forms[1].kind {
+        NodeKind::Prefixed(FormLike::Quote, Some(ref quoted)) => {
+            assert_eq!(quoted.leading, "   ");
+            assert_eq!(cst.forms[1].span.end, quoted.span.end);
+        }
+        ref other => panic!("{:?} is not a quote", other),
+    }
+}
+
+#[test]
+fn converts_to_the_same_values_as_parse() {
+    let program = "(define (f x) [x 'y ~@z `(1 2.50 0)] {\"a\\x41\" \"\\u{2764}\" 'b 1e3})
+                   [1 2 {3 4}] '   quoted";
+    let mut interner = SymbolIntern::new();
+    let parsed = parse(program, &mut interner).unwrap();
+    let cst = Cst::parse(program).unwrap();
+    assert_eq!(cst.to_values(&mut interner).unwrap(), parsed);
+    assert_eq!(cst.forms[2].to_value(&mut interner).unwrap(), parsed[2]);
+}
+
+#[test]
+fn reports_errors() {
+    let mut interner = SymbolIntern::new();
+    assert_eq!(format!("{}", Cst::parse("(a [b)").unwrap_err()),
+               "Extra right delimiter ] at line 1, column 6");
+    assert_eq!(format!("{}", Cst::parse("(a b").unwrap_err()),
+               "Missing right delimiter ) for the form at line 1, column 1");
+    // Conversion errors only show up when building values.
+    let cst = Cst::parse("(a {1 2 3})").unwrap();
+    assert_eq!(format!("{}", cst.to_values(&mut interner).unwrap_err()),
+               "Map literal at line 1, column 4 is malformed");
+}
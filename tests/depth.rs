@@ -0,0 +1,206 @@
+extern crate ares;
+
+use ares::{Context, AresError, ParseError, ReadStatus, Reader, Value, Cst, DEFAULT_MAX_NESTING,
+           parse};
+
+#[macro_use]
+mod util;
+
+/// `depth` calls to `open`, nested inside of each other around `inner`.
+fn nested(open: &str, inner: &str, depth: usize) -> String {
+    let mut program = String::new();
+    for _ in 0..depth {
+        program.push_str("(");
+        program.push_str(open);
+        program.push_str(" ");
+    }
+    program.push_str(inner);
+    for _ in 0..depth {
+        program.push_str(")");
+    }
+    program
+}
+
+#[test]
+fn nested_arguments_and_special_forms_are_evaluated_on_the_heap() {
+    let depth = DEFAULT_MAX_NESTING - 1;
+    eval_ok!(&nested("+ 1", "0", depth), depth as i64);
+    eval_ok!(&nested("and true", "true", depth), true);
+    eval_ok!(&nested("or false", "true", depth), true);
+    eval_ok!(&nested("if false 0", "1", depth), 1);
+    eval_ok!(&nested("let (x 1)", "x", depth), 1);
+}
+
+#[test]
+fn deep_values_are_printed_and_expanded_without_recursion() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let quoted = format!("(quote {})", nested("1", "", DEFAULT_MAX_NESTING - 1));
+    let value = ctx.eval_str(&quoted).unwrap();
+    let printed = ctx.format_value(&value);
+    assert!(printed.starts_with("[1, [1, [1, "));
+    assert!(printed.contains("[1, [1]]]"));
+    assert_eq!(printed.matches('[').count(), DEFAULT_MAX_NESTING - 1);
+
+    let quasi = format!("(quasiquote {})", nested("1", "(unquote (+ 1 1))", DEFAULT_MAX_NESTING - 3));
+    let value = ctx.eval_str(&quasi).unwrap();
+    assert!(ctx.format_value(&value).contains("[1, [1, 2]]"));
+
+    ctx.eval_str("(define-macro twice (lambda (x) (list (quote +) x x)))").unwrap();
+    assert_eq!(ctx.eval_str(&nested("twice", "1", 10)).unwrap(), Value::Int(1024));
+}
+
+#[test]
+fn pathological_nesting_is_a_parse_error() {
+    let program = nested("list", "1", 100000);
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    match ctx.eval_str(&program) {
+        Err(AresError::ParseError(ParseError::TooDeeplyNested(pos, DEFAULT_MAX_NESTING))) => {
+            assert_eq!((pos.0, pos.1), (1, 1 + 6 * DEFAULT_MAX_NESTING));
+        }
+        other => panic!("{:?} is not nested too deeply", other),
+    }
+    match Cst::parse(&program) {
+        Err(ParseError::TooDeeplyNested(..)) => {}
+        other => panic!("{:?} is not nested too deeply", other),
+    }
+    let deepest = nested("list", "1", DEFAULT_MAX_NESTING);
+    let cst = Cst::parse(&deepest).unwrap();
+    assert_eq!(cst.to_string(), deepest);
+    assert_eq!(cst.to_values(ctx.interner_mut()).unwrap(),
+               parse(&deepest, ctx.interner_mut()).unwrap());
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+}
+
+#[test]
+fn the_nesting_limit_can_be_changed() {
+    let mut ctx = Context::new();
+    ctx.set_max_nesting(10);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    assert_eq!(ctx.eval_str(&nested("+ 1", "0", 10)).unwrap(), Value::Int(10));
+    match ctx.eval_str(&nested("+ 1", "0", 11)) {
+        Err(AresError::ParseError(ParseError::TooDeeplyNested(pos, 10))) => {
+            assert_eq!((pos.0, pos.1), (1, 1 + 5 * 10));
+        }
+        other => panic!("{:?} is not nested too deeply", other),
+    }
+
+    let mut reader = Reader::new();
+    reader.push_str(&nested("+ 1", "0", 11));
+    match ctx.read(&mut reader).status {
+        ReadStatus::Error(ParseError::TooDeeplyNested(_, 10)) => {}
+        other => panic!("{:?} is not nested too deeply", other),
+    }
+
+    match Cst::parse_with_max_nesting(&nested("+ 1", "0", 11), 10) {
+        Err(ParseError::TooDeeplyNested(_, 10)) => {}
+        other => panic!("{:?} is not nested too deeply", other),
+    }
+
+    ctx.set_max_nesting(2 * DEFAULT_MAX_NESTING);
+    let depth = 2 * DEFAULT_MAX_NESTING - 1;
+    assert_eq!(ctx.eval_str(&nested("+ 1", "0", depth)).unwrap(), Value::Int(depth as i64));
+}
+
+#[test]
+fn deep_recursion_hits_the_depth_limit() {
+    let mut ctx = Context::new();
+    ctx.set_max_depth(1000);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1))))))").unwrap();
+    assert_eq!(ctx.eval_str("(sum 10)").unwrap(), Value::Int(55));
+    match ctx.eval_str("(sum 100000)") {
+        Err(AresError::DepthLimitExceeded(1000)) => {}
+        other => panic!("{:?} didn't exceed the depth limit", other),
+    }
+    assert!(ctx.error_span().is_some());
+    assert!(ctx.stack.is_empty());
+    assert!(ctx.env_stack.is_empty());
+
+    // The context is still usable, and tail calls don't count.
+    ctx.eval_str("(define loop (lambda (n) (if (= n 0) 0 (loop (- n 1)))))").unwrap();
+    assert_eq!(ctx.eval_str("(loop 10000)").unwrap(), Value::Int(0));
+
+    // Neither do macros that never stop expanding.
+    ctx.eval_str("(define-macro forever (lambda () (list (quote forever))))").unwrap();
+    match ctx.eval_str("(forever)") {
+        Err(AresError::DepthLimitExceeded(1000)) => {}
+        other => panic!("{:?} didn't exceed the depth limit", other),
+    }
+}
+
+#[test]
+fn reentering_from_rust_hits_the_native_depth_limit() {
+    let mut ctx = Context::new();
+    ctx.set_max_native_depth(20);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define down (lambda (n) (if (= n 0) 0 (eval (list (quote down) (- n 1))))))")
+       .unwrap();
+    assert_eq!(ctx.eval_str("(down 10)").unwrap(), Value::Int(0));
+    match ctx.eval_str("(down 30)") {
+        Err(AresError::DepthLimitExceeded(20)) => {}
+        other => panic!("{:?} didn't exceed the native depth limit", other),
+    }
+    assert_eq!(ctx.native_depth(), 0);
+    assert_eq!(ctx.eval_str("(down 10)").unwrap(), Value::Int(0));
+}
+
+#[test]
+fn maps_built_lists_and_unquotes_are_evaluated_on_the_heap() {
+    let mut ctx = Context::new();
+    // Only the top level evaluation is allowed to be in native code.
+    ctx.set_max_native_depth(1);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let depth = 200;
+
+    let mut map = "(+ 1 1)".to_string();
+    for _ in 0..depth {
+        map = format!("{{:k {}}}", map);
+    }
+    let mut value = ctx.eval_str(&map).unwrap();
+    for _ in 0..depth {
+        value = ctx.eval_str("(lambda (m) (unwrap (:k m)))").and_then(|f| ctx.call(&f, &[value]))
+                   .unwrap();
+    }
+    assert_eq!(value, Value::Int(2));
+
+    let mut built = "(+ 1 1)".to_string();
+    for _ in 0..depth {
+        built = format!("(build-list (lambda (add) (add {})))", built);
+    }
+    let printed = ctx.eval_str(&built).map(|value| ctx.format_value(&value)).unwrap();
+    assert!(printed.starts_with("[[[[") && printed.contains("[2]"));
+
+    let mut quasi = "(+ 1 1)".to_string();
+    for _ in 0..depth {
+        quasi = format!("(quasiquote (1 (unquote-splicing (list {}))))", quasi);
+    }
+    let printed = ctx.eval_str(&quasi).map(|value| ctx.format_value(&value)).unwrap();
+    assert!(printed.starts_with("[1, [1, [1, ") && printed.contains("[1, 2]"));
+}
+
+#[test]
+fn list_functions_call_back_on_the_heap() {
+    let mut ctx = Context::new();
+    ctx.set_max_native_depth(1);
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define g (lambda (n)
+                    (if (= n 0)
+                        0
+                        (+ 1 (fold-left (map (list n) (lambda (m) (apply g (list (- m 1)))))
+                                        0
+                                        (lambda (a b) (+ a b)))))))")
+       .unwrap();
+    assert_eq!(ctx.eval_str("(g 500)").unwrap(), Value::Int(500));
+    assert_eq!(ctx.eval_str("(for-each (list 1 2 3) (lambda (x) x))").unwrap(), Value::Int(3));
+    assert_eq!(ctx.eval_str("(map (list 1 2 3) (lambda (x) (* x 2)))").unwrap(),
+               Value::list(vec![Value::Int(2), Value::Int(4), Value::Int(6)]));
+}
@@ -0,0 +1,108 @@
+extern crate ares;
+
+use ares::{Context, AresError, Value, Execution, ExecutionStatus, user_fn};
+
+/// Runs `execution` `steps` steps at a time until it is done, returning
+/// the result and how many times it had to be run.
+fn run_to_end<S: 'static>(execution: &mut Execution,
+                          ctx: &mut ares::LoadedContext<S>,
+                          steps: usize)
+                          -> (Result<Value, AresError>, usize) {
+    let mut runs = 0;
+    loop {
+        runs += 1;
+        match execution.run_for(ctx, steps) {
+            ExecutionStatus::Running => {}
+            ExecutionStatus::Finished(value) => return (Ok(value), runs),
+            ExecutionStatus::Error(e) => return (Err(e), runs),
+        }
+    }
+}
+
+#[test]
+fn executions_run_a_few_steps_at_a_time() {
+    let mut ctx: Context<()> = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define count (lambda (n acc) (if (= n 0) acc (count (- n 1) (+ acc 2)))))")
+       .unwrap();
+    let program = ares::parse("(count 1000 0)", ctx.interner_mut()).unwrap().remove(0);
+
+    let mut execution = ctx.start(program.clone()).unwrap();
+    match execution.step(&mut ctx) {
+        ExecutionStatus::Running => {}
+        other => panic!("{:?} finished after one step", other),
+    }
+    // Other evaluation can happen while an execution is paused.
+    assert_eq!(ctx.eval_str("(count 10 0)").unwrap(), Value::Int(20));
+    let (result, runs) = run_to_end(&mut execution, &mut ctx, 100);
+    assert_eq!(result.unwrap(), Value::Int(2000));
+    assert!(runs > 10);
+    assert!(execution.is_done());
+    assert!(ctx.stack.is_empty() && ctx.env_stack.is_empty());
+
+    match execution.step(&mut ctx) {
+        ExecutionStatus::Error(AresError::InvalidState(_)) => {}
+        other => panic!("{:?} ran after finishing", other),
+    }
+
+    let mut all_at_once = ctx.start(program).unwrap();
+    let (result, runs) = run_to_end(&mut all_at_once, &mut ctx, 1000000);
+    assert_eq!(result.unwrap(), Value::Int(2000));
+    assert_eq!(runs, 1);
+}
+
+#[test]
+fn executions_survive_reloading_the_state() {
+    let mut ctx: Context<Vec<i64>> = Context::new();
+    ctx.set_fn("record",
+               user_fn("record", |args, ctx: &mut ares::LoadedContext<Vec<i64>>| {
+                   let n = match args[0] {
+                       Value::Int(n) => n,
+                       ref other => panic!("{:?}", other),
+                   };
+                   ctx.state().push(n);
+                   Ok(args[0].clone())
+               }));
+
+    let mut first = vec![];
+    let mut execution = {
+        let mut loaded = ctx.load(&mut first);
+        loaded.eval_str("(define each (lambda (n) (if (= n 0) 0 (let (x (record n)) (each (- n 1))))))")
+              .unwrap();
+        let program = ares::parse("(each 50)", loaded.interner_mut()).unwrap().remove(0);
+        let mut execution = loaded.start(program).unwrap();
+        match execution.run_for(&mut loaded, 100) {
+            ExecutionStatus::Running => {}
+            other => panic!("{:?} is not still running", other),
+        }
+        execution
+    };
+
+    let mut second = vec![];
+    {
+        let mut loaded = ctx.load(&mut second);
+        let (result, _) = run_to_end(&mut execution, &mut loaded, 100);
+        assert_eq!(result.unwrap(), Value::Int(0));
+    }
+
+    assert!(!first.is_empty() && !second.is_empty());
+    let all: Vec<i64> = first.iter().chain(second.iter()).cloned().collect();
+    assert_eq!(all, (1..51).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn errors_end_the_execution() {
+    let mut ctx: Context<()> = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let program = ares::parse("(let (x 1) (+ x y))", ctx.interner_mut()).unwrap().remove(0);
+    let mut execution = ctx.start(program).unwrap();
+    match run_to_end(&mut execution, &mut ctx, 3) {
+        (Err(AresError::UndefinedName(ref name)), _) if name == "y" => {}
+        other => panic!("{:?} is not an undefined name", other),
+    }
+    assert!(execution.is_done());
+    assert!(ctx.stack.is_empty() && ctx.env_stack.is_empty());
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+}
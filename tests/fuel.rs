@@ -0,0 +1,78 @@
+extern crate ares;
+
+use std::time::{Duration, Instant};
+
+use ares::{Context, AresError, Value};
+
+const FOREVER: &'static str = "(define forever (lambda (n) (forever (+ n 1))))";
+
+#[test]
+fn running_out_of_fuel_stops_evaluation() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    assert_eq!(ctx.fuel(), None);
+    ctx.eval_str(FOREVER).unwrap();
+
+    ctx.set_fuel(Some(10000));
+    match ctx.eval_str("(forever 0)") {
+        Err(AresError::OutOfFuel) => {}
+        other => panic!("{:?} didn't run out of fuel", other),
+    }
+    assert_eq!(ctx.fuel(), Some(0));
+    assert!(ctx.error_span().is_some());
+    assert!(ctx.stack.is_empty());
+    assert!(ctx.env_stack.is_empty());
+
+    // Running out from inside of a foreign function works the same way.
+    ctx.add_fuel(10000);
+    match ctx.eval_str("(+ 1 (eval (quote (forever 0))))") {
+        Err(AresError::OutOfFuel) => {}
+        other => panic!("{:?} didn't run out of fuel", other),
+    }
+
+    // Once topped up, the context carries on as normal.
+    ctx.add_fuel(100);
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+    let left = ctx.fuel().unwrap();
+    assert!(left > 0 && left < 100);
+
+    ctx.set_fuel(None);
+    ctx.add_fuel(100);
+    assert_eq!(ctx.fuel(), None);
+}
+
+#[test]
+fn every_step_uses_fuel() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.set_fuel(Some(1000));
+    ctx.eval_str("(+ 1 2)").unwrap();
+    let small = 1000 - ctx.fuel().unwrap();
+    ctx.set_fuel(Some(1000));
+    ctx.eval_str("(+ 1 (+ 2 (+ 3 4)))").unwrap();
+    let big = 1000 - ctx.fuel().unwrap();
+    assert!(small > 0);
+    assert!(big > small);
+}
+
+#[test]
+fn deadlines_stop_evaluation() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str(FOREVER).unwrap();
+
+    let started = Instant::now();
+    ctx.set_deadline(Some(started + Duration::from_millis(50)));
+    match ctx.eval_str("(forever 0)") {
+        Err(AresError::Timeout) => {}
+        other => panic!("{:?} didn't time out", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(ctx.stack.is_empty());
+
+    ctx.set_deadline(None);
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+}
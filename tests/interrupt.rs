@@ -0,0 +1,119 @@
+extern crate ares;
+
+use std::thread;
+use std::sync::mpsc::channel;
+
+use ares::{Context, AresError, ExecutionStatus, Value, user_fn};
+
+#[test]
+fn interrupting_from_another_thread_stops_evaluation() {
+    let (send, receive) = channel();
+    let worker = thread::spawn(move || {
+        let mut ctx = Context::new();
+        // Hands out the handle once evaluation has started.
+        ctx.set_fn("started",
+                   user_fn("started", move |_, ctx| {
+                       send.send(ctx.interrupt_handle()).unwrap();
+                       Ok(Value::Int(0))
+                   }));
+        let mut state = ();
+        let mut ctx = ctx.load(&mut state);
+        ctx.eval_str("(define forever (lambda (n) (forever (+ n 1))))").unwrap();
+        let interrupted = match ctx.eval_str("(+ 1 (eval (quote (forever (started)))))") {
+            Err(AresError::Interrupted) => true,
+            _ => false,
+        };
+        assert!(ctx.stack.is_empty());
+        assert!(ctx.env_stack.is_empty());
+        assert_eq!(ctx.native_depth(), 0);
+        assert!(!ctx.interrupt_handle().is_interrupted());
+        assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+        interrupted
+    });
+
+    let handle = receive.recv().unwrap();
+    let other = handle.clone();
+    other.interrupt();
+    assert!(worker.join().unwrap());
+}
+
+#[test]
+fn interrupts_between_top_level_forms_stop_evaluation() {
+    let mut ctx = Context::new();
+    let handle = ctx.interrupt_handle();
+    // Interrupts as the last step of its form, so the next thing that looks
+    // at the interrupt is the form after it.
+    ctx.set_fn("interrupt",
+               user_fn("interrupt", move |_, _| {
+                   handle.interrupt();
+                   Ok(Value::Int(0))
+               }));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define forever (lambda (n) (forever (+ n 1))))").unwrap();
+    match ctx.eval_str("(interrupt) (forever 0)") {
+        Err(AresError::Interrupted) => {}
+        other => panic!("{:?} wasn't interrupted", other),
+    }
+    assert!(!ctx.interrupt_handle().is_interrupted());
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+}
+
+#[test]
+fn interrupts_while_reading_stop_evaluation() {
+    let mut ctx = Context::new();
+    let handle = ctx.interrupt_handle();
+    ctx.set_reader_macro("interrupt", move |form, _| {
+        handle.interrupt();
+        Ok(form)
+    });
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define forever (lambda (n) (forever (+ n 1))))").unwrap();
+    match ctx.eval_str("(forever #interrupt 0)") {
+        Err(AresError::Interrupted) => {}
+        other => panic!("{:?} wasn't interrupted", other),
+    }
+}
+
+#[test]
+fn cleared_interrupts_do_nothing() {
+    let mut ctx = Context::new();
+    let handle = ctx.interrupt_handle();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+
+    handle.interrupt();
+    assert!(handle.is_interrupted());
+    handle.clear();
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+
+    // Interrupts that come while nothing is being evaluated are forgotten.
+    handle.interrupt();
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+    handle.interrupt();
+    let plus = ctx.eval_str("+").unwrap();
+    assert_eq!(ctx.call(&plus, &[Value::Int(1), Value::Int(2)]).unwrap(), Value::Int(3));
+}
+
+#[test]
+fn paused_executions_can_be_interrupted() {
+    let mut ctx = Context::new();
+    let handle = ctx.interrupt_handle();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define forever (lambda (n) (forever (+ n 1))))").unwrap();
+    let program = ares::parse("(forever 0)", ctx.interner_mut()).unwrap().remove(0);
+
+    handle.interrupt();
+    let mut execution = ctx.start(program).unwrap();
+    match execution.run_for(&mut ctx, 100) {
+        ExecutionStatus::Running => {}
+        other => panic!("{:?} isn't running", other),
+    }
+    handle.interrupt();
+    match execution.run_for(&mut ctx, 100) {
+        ExecutionStatus::Error(AresError::Interrupted) => {}
+        other => panic!("{:?} wasn't interrupted", other),
+    }
+}
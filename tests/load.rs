@@ -0,0 +1,145 @@
+extern crate ares;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+
+use ares::{Context, AresError, ParseError, Value, SourceId};
+
+/// A fresh directory to put scripts in.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ares-load-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(path: &PathBuf, contents: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+}
+
+#[test]
+fn load_resolves_relative_to_the_including_file() {
+    let dir = scratch_dir("relative");
+    write(&dir.join("main.ares"), "(load \"lib/util.ares\")\n(triple 5)");
+    write(&dir.join("lib/util.ares"),
+          "(load \"helpers.ares\")\n(define triple (lambda (x) (+ x (double x))))");
+    write(&dir.join("lib/helpers.ares"), "(define double (lambda (x) (* x 2)))");
+
+    let mut ctx = Context::new().with_file_loading();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    assert_eq!(ctx.eval_file(dir.join("main.ares")).unwrap(), Value::Int(15));
+    assert_eq!(ctx.current_file(), None);
+
+    // Outside of any file, paths are used as they are.
+    write(&dir.join("answer.ares"), "42");
+    let loaded = format!("(load \"{}\")", dir.join("answer.ares").display());
+    assert_eq!(ctx.eval_str(&loaded).unwrap(), Value::Int(42));
+}
+
+#[test]
+fn errors_say_which_file_they_are_in() {
+    let dir = scratch_dir("errors");
+    let broken = dir.join("broken.ares");
+    write(&broken, "(define x 1)\n(+ x ]");
+    let failing = dir.join("failing.ares");
+    write(&failing, "(define x 1)\n\n  (+ x (undefined-fn))");
+
+    let mut ctx = Context::new().with_file_loading();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let error = ctx.eval_file(&broken).unwrap_err();
+    match error {
+        AresError::ParseError(ref e@ParseError::ExtraRightDelimiter(..)) => {
+            let source = ctx.source_names_mut().named(broken.to_string_lossy());
+            assert_eq!(e.position().source(), source);
+            assert_eq!(e.to_string(), "Extra right delimiter ) at line 2, column 6");
+            assert_eq!(ctx.source_names().describe(e.position()),
+                       format!("line 2, column 6 of {}", broken.display()));
+        }
+        ref other => panic!("{:?} is not a parse error", other),
+    }
+    assert_eq!(ctx.describe_error(&error),
+               format!("Extra right delimiter ) at line 2, column 6 of {}", broken.display()));
+
+    match ctx.eval_str(&format!("(load \"{}\")", failing.display())) {
+        Err(AresError::UndefinedName(_)) => {}
+        other => panic!("{:?} is not an undefined name", other),
+    }
+    let span = ctx.error_span().unwrap();
+    assert_eq!(ctx.source_names().name(span.start.source()),
+               Some(&*failing.to_string_lossy()));
+    assert_eq!((span.start.0, span.start.1), (3, 8));
+
+    match ctx.eval_file(dir.join("missing.ares")) {
+        Err(AresError::IoError { ref path, .. }) => assert!(path.ends_with("missing.ares")),
+        other => panic!("{:?} is not an io error", other),
+    }
+}
+
+#[test]
+fn runtime_errors_in_loaded_files_name_the_file() {
+    let dir = scratch_dir("runtime");
+    let main = dir.join("main.ares");
+    write(&main, "(load \"inner.ares\")\n(halve 4)");
+    let inner = dir.join("inner.ares");
+    write(&inner, "(define halve (lambda (x)\n  (/ x \"two\")))");
+
+    let mut ctx = Context::new().with_file_loading();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let error = ctx.eval_file(&main).unwrap_err();
+    match error {
+        AresError::UnexpectedType { .. } => {}
+        ref other => panic!("{:?} is not a type error", other),
+    }
+    assert!(ctx.describe_error(&error)
+               .ends_with(&format!(" at line 2, column 3 of {}", inner.display())));
+    let span = ctx.error_span().unwrap();
+    assert_eq!(span.start.source(), ctx.source_names_mut().named(inner.to_string_lossy()));
+    assert_eq!((span.start.0, span.start.1), (2, 3));
+}
+
+#[test]
+fn eval_reader_names_its_source() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let program = Cursor::new("(define y 2)\n(* y 21)");
+    assert_eq!(ctx.eval_reader(program, "inline.ares").unwrap(), Value::Int(42));
+    match ctx.eval_reader(Cursor::new("(1"), "inline.ares") {
+        Err(AresError::ParseError(ref e)) => {
+            assert_eq!(ctx.source_names().name(e.position().source()), Some("inline.ares"))
+        }
+        other => panic!("{:?} is not a parse error", other),
+    }
+    assert!(ctx.source_names().name(SourceId::anonymous()).is_none());
+
+    // Every context has names of its own.
+    let mut other: Context<()> = Context::new();
+    assert!(other.source_names().is_empty());
+    other.source_names_mut().named("elsewhere.ares");
+    assert_eq!(ctx.source_names().len(), 1);
+}
+
+#[test]
+fn scripts_can_only_load_files_if_allowed() {
+    let dir = scratch_dir("allowed");
+    write(&dir.join("answer.ares"), "42");
+    let loaded = format!("(load \"{}\")", dir.join("answer.ares").display());
+
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    match ctx.eval_str(&loaded) {
+        Err(AresError::UndefinedName(ref name)) => assert_eq!(name, "load"),
+        other => panic!("{:?} is not an undefined name", other),
+    }
+    // Hosts can still evaluate files themselves.
+    assert_eq!(ctx.eval_file(dir.join("answer.ares")).unwrap(), Value::Int(42));
+}
@@ -14,3 +14,16 @@ fn test_hashmap() {
     eval_err!("(hash-map [] 2)", AresError::UnexpectedType{..});
     eval_err!("(define x {}) {x 4}", AresError::UnexpectedType{..});
 }
+
+#[test]
+fn keyword_keys() {
+    eval_ok!("(= {:a 1 :b 2} (hash-map :b 2 :a 1))", true);
+    eval_ok!("(:a {:a 1 :b 2})", Some(1));
+    eval_ok!("(:c {:a 1 :b 2})", None::<i64>);
+    eval_ok!("(:c {:a 1 :b 2} 3)", 3);
+    eval_ok!("(:a {:a 1 :b 2} 3)", 1);
+    eval_ok!("(define k :b) (k {:a 1 :b 2})", Some(2));
+    eval_ok!("(:a {'a 1})", None::<i64>);
+    eval_err!("(:a [1 2])", AresError::UnexpectedType{..});
+    eval_err!("(:a)", AresError::UnexpectedArity{..});
+}
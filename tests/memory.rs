@@ -0,0 +1,78 @@
+extern crate ares;
+
+use ares::{Context, AresError, Value, MemoryStats};
+
+#[test]
+fn lists_strings_and_maps_are_accounted_for() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    assert_eq!(ctx.memory_stats(), MemoryStats::default());
+
+    ctx.eval_str("(list 1 2 3)").unwrap();
+    let lists = ctx.memory_stats().lists;
+    assert!(lists > 0);
+    ctx.eval_str("(list 1 2 3 4 5 6 7 8 9 10)").unwrap();
+    assert!(ctx.memory_stats().lists - lists > lists);
+
+    ctx.eval_str("(->string 12345)").unwrap();
+    assert!(ctx.memory_stats().strings > 5);
+    ctx.eval_str("(hash-map 1 2 3 4)").unwrap();
+    assert!(ctx.memory_stats().maps > 0);
+
+    let stats = ctx.memory_stats();
+    assert_eq!(stats.allocated(), stats.lists + stats.strings + stats.maps);
+    ctx.reset_memory_stats();
+    assert_eq!(ctx.memory_stats(), MemoryStats::default());
+}
+
+#[test]
+fn going_over_the_limit_is_an_error() {
+    let mut ctx = Context::new();
+    ctx.set_memory_limit(Some(100000));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define grow (lambda (l) (grow (concat l l))))").unwrap();
+    match ctx.eval_str("(grow (list 1))") {
+        Err(AresError::MemoryLimitExceeded(100000)) => {}
+        other => panic!("{:?} didn't run out of memory", other),
+    }
+    let stats = ctx.memory_stats();
+    assert!(stats.allocated() <= 100000);
+    assert_eq!(stats.limit, Some(100000));
+    assert!(ctx.stack.is_empty());
+    assert!(ctx.env_stack.is_empty());
+
+    // Allocations keep counting until the stats are reset.
+    match ctx.eval_str("(list (grow (list 1)))") {
+        Err(AresError::MemoryLimitExceeded(_)) => {}
+        other => panic!("{:?} didn't run out of memory", other),
+    }
+    assert!(ctx.memory_stats().allocated() >= stats.allocated());
+    assert_eq!(ctx.eval_str("(+ 1 2)").unwrap(), Value::Int(3));
+    ctx.reset_memory_stats();
+    assert_eq!(ctx.memory_stats().limit, Some(100000));
+    ctx.eval_str("(concat (list 1 2) (list 3 4))").unwrap();
+
+    ctx.set_memory_limit(None);
+    ctx.eval_str("(list 1 2 3)").unwrap();
+}
+
+#[test]
+fn scripts_can_ask_for_memory_stats() {
+    let ctx = Context::new();
+    assert!(ctx.get("memory-stats").is_none());
+    let mut ctx = ctx.with_memory_stats();
+    ctx.set_memory_limit(Some(1 << 20));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define stats (memory-stats))").unwrap();
+    assert_eq!(ctx.eval_str("(:limit stats 0)").unwrap(),
+               Value::option(Some(Value::Int(1 << 20))));
+    assert_eq!(ctx.eval_str("(:strings stats -1)").unwrap(), Value::Int(0));
+    ctx.eval_str("(->string 1)").unwrap();
+    match ctx.eval_str("(:strings (memory-stats) -1)").unwrap() {
+        Value::Int(strings) => assert!(strings > 0),
+        other => panic!("{:?} is not a number of bytes", other),
+    }
+}
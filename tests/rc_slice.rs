@@ -0,0 +1,29 @@
+extern crate ares;
+
+use ares::Value;
+use ares::util::RcSlice;
+
+fn ints(v: &[i64]) -> Vec<Value> {
+    v.iter().map(|&i| Value::Int(i)).collect()
+}
+
+#[test]
+fn slices_of_slices() {
+    let slice = RcSlice::new(ints(&[1, 2, 3, 4, 5]));
+    let tail = slice.tail();
+    assert_eq!(tail.get_slice(), &ints(&[2, 3, 4, 5])[..]);
+    assert_eq!(tail.slice(1, 3).get_slice(), &ints(&[3, 4])[..]);
+    assert_eq!(tail.tail().init().get_slice(), &ints(&[3, 4])[..]);
+    assert_eq!(tail.slice(1, 3), slice.slice(2, 4));
+    assert!(tail.slice(0, 2) != slice.slice(0, 2));
+}
+
+#[test]
+fn popping_from_the_front() {
+    let mut slice = RcSlice::new(ints(&[1, 2]));
+    assert_eq!(slice.pop_front(), Some(Value::Int(1)));
+    assert_eq!(slice.len(), 1);
+    assert_eq!(slice.pop_front(), Some(Value::Int(2)));
+    assert!(slice.is_empty());
+    assert_eq!(slice.pop_front(), None);
+}
@@ -0,0 +1,219 @@
+extern crate ares;
+
+use ares::{parse, Context, Position, Reader, ReadResult, ReadStatus, Value, ParseError};
+use ares::intern::SymbolIntern;
+
+fn needs_more(result: &ReadResult) -> bool {
+    match result.status {
+        ReadStatus::NeedsMoreInput => true,
+        _ => false,
+    }
+}
+
+fn complete(result: &ReadResult) -> bool {
+    match result.status {
+        ReadStatus::Complete => true,
+        _ => false,
+    }
+}
+
+#[test]
+fn forms_split_across_chunks() {
+    let mut interner = SymbolIntern::new();
+    let mut reader = Reader::new();
+
+    reader.push_str("(+ 1\n");
+    let result = reader.read(&mut interner);
+    assert!(needs_more(&result));
+    assert!(result.forms.is_empty());
+    assert_eq!(result.consumed, 0);
+    assert!(!reader.is_empty());
+
+    reader.push_str("   2) (foo");
+    let result = reader.read(&mut interner);
+    assert!(needs_more(&result));
+    assert_eq!(result.forms.len(), 1);
+    assert_eq!(result.consumed, "(+ 1\n   2)".len());
+    assert_eq!(reader.buffered(), " (foo");
+
+    reader.push_str(")\n");
+    let result = reader.read(&mut interner);
+    assert!(complete(&result));
+    assert_eq!(result.forms.len(), 1);
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn atoms_at_the_end_of_a_chunk_wait_for_more() {
+    let mut interner = SymbolIntern::new();
+    let mut reader = Reader::new();
+
+    reader.push_str("fo");
+    assert!(needs_more(&reader.read(&mut interner)));
+    reader.push_str("o 12");
+    let result = reader.read(&mut interner);
+    assert!(needs_more(&result));
+    assert_eq!(result.forms.len(), 1);
+    reader.push_str("3 'bar");
+    let result = reader.read(&mut interner);
+    assert_eq!(result.forms.len(), 1);
+    assert_eq!(result.forms[0].0, Value::Int(123));
+
+    let result = reader.finish(&mut interner);
+    assert!(complete(&result));
+    assert_eq!(result.forms.len(), 1);
+    assert!(reader.buffered().is_empty());
+}
+
+#[test]
+fn unterminated_strings_and_comments_need_more_input() {
+    let mut interner = SymbolIntern::new();
+    let mut reader = Reader::new();
+
+    reader.push_str("\"abc");
+    assert!(needs_more(&reader.read(&mut interner)));
+    reader.push_str("def\"\n");
+    let result = reader.read(&mut interner);
+    assert!(complete(&result));
+    assert_eq!(result.forms[0].0, Value::String(std::rc::Rc::new("abcdef".into())));
+
+    reader.push_str("#| a comment");
+    assert!(needs_more(&reader.read(&mut interner)));
+    reader.push_str(" |# 5\n");
+    let result = reader.read(&mut interner);
+    assert!(complete(&result));
+    assert_eq!(result.forms[0].0, Value::Int(5));
+}
+
+#[test]
+fn strings_at_the_end_of_a_chunk_wait_for_a_delimiter() {
+    let mut interner = SymbolIntern::new();
+    let mut reader = Reader::new();
+
+    // Whatever comes next has to be a delimiter, or the string is an error.
+    reader.push_str("\"abc\"");
+    assert!(needs_more(&reader.read(&mut interner)));
+    reader.push_str("x");
+    match reader.read(&mut interner).status {
+        ReadStatus::Error(ParseError::UnexpectedChar('x', _, _)) => {}
+        other => panic!("expected an error, got {:?}", other),
+    }
+    reader.clear();
+
+    // Symbols can have quotes in them.
+    reader.push_str("a\"");
+    assert!(needs_more(&reader.read(&mut interner)));
+    reader.push_str("b ");
+    let result = reader.read(&mut interner);
+    assert!(complete(&result));
+    assert_eq!(result.forms[0].0, Value::Symbol(interner.intern("a\"b")));
+}
+
+#[test]
+fn prefixes_at_the_end_of_a_chunk_wait_for_their_form() {
+    for prefix in &["'", "`", "~", "~@"] {
+        let mut interner = SymbolIntern::new();
+        let mut reader = Reader::new();
+
+        reader.push_str(prefix);
+        reader.push_str("\n");
+        let result = reader.read(&mut interner);
+        assert!(needs_more(&result), "{:?} after {}", result.status, prefix);
+        assert!(result.forms.is_empty());
+
+        reader.push_str("(a b)\n");
+        let result = reader.read(&mut interner);
+        assert!(complete(&result));
+        assert_eq!(result.forms.len(), 1);
+        let mut whole = parse(&format!("{}\n(a b)", prefix), &mut interner).unwrap();
+        assert_eq!(result.forms[0].0, whole.remove(0));
+
+        reader.push_str(prefix);
+        reader.push_str("\n");
+        match reader.finish(&mut interner).status {
+            ReadStatus::Error(ParseError::MissingQuotedForm(..)) => {}
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn positions_continue_across_chunks() {
+    let mut interner = SymbolIntern::new();
+    let mut reader = Reader::new();
+
+    reader.push_str("(a)\n");
+    reader.read(&mut interner);
+    reader.push_str("  (b\n");
+    reader.read(&mut interner);
+    reader.push_str("c)\n");
+    let result = reader.read(&mut interner);
+    let span = result.forms[0].1;
+    assert_eq!(span.start, Position::new(2, 3));
+    assert_eq!(span.end, Position::new(3, 3));
+}
+
+#[test]
+fn errors_are_reported_and_can_be_cleared() {
+    let mut interner = SymbolIntern::new();
+    let mut reader = Reader::new();
+
+    reader.push_str("(a) b)\n");
+    let result = reader.read(&mut interner);
+    assert_eq!(result.forms.len(), 2);
+    match result.status {
+        ReadStatus::Error(ParseError::ExtraRightDelimiter(_, pos)) => {
+            assert_eq!(pos, Position::new(1, 6))
+        }
+        other => panic!("expected an error, got {:?}", other),
+    }
+    assert_eq!(reader.buffered(), ")\n");
+    reader.clear();
+    assert!(reader.is_empty());
+
+    reader.push_str("(a");
+    match reader.finish(&mut interner).status {
+        ReadStatus::Error(ParseError::MissingRightDelimiter(..)) => {}
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+fn contexts_evaluate_what_they_read() {
+    let mut ctx = Context::new();
+    let mut dummy = ();
+    let mut ctx = ctx.load(&mut dummy);
+    let mut reader = Reader::new();
+
+    reader.push_str("(define x\n");
+    let result = ctx.read(&mut reader);
+    assert!(needs_more(&result));
+    reader.push_str("  5)\n(+ x 1)\n");
+    let result = ctx.read(&mut reader);
+    assert!(complete(&result));
+    assert_eq!(ctx.eval_forms(result.forms).unwrap(), Value::Int(6));
+}
+
+#[test]
+fn unfinished_escapes_wait_for_more() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let mut reader = Reader::new();
+
+    reader.push_str("(list \"\\u{");
+    assert!(needs_more(&ctx.read(&mut reader)));
+    assert!(!reader.is_empty());
+    reader.push_str("41}\")");
+    let result = ctx.read(&mut reader);
+    assert!(complete(&result));
+    assert_eq!(result.forms.len(), 1);
+    let forms = result.forms;
+    assert_eq!(ctx.eval_forms(forms).unwrap(), Value::list(vec![Value::string("A")]));
+
+    reader.push_str("(list \"\\é\")");
+    match ctx.read(&mut reader).status {
+        ReadStatus::Error(ParseError::BadEscape(_, ref escape)) => assert_eq!(escape, "\\é"),
+        ref other => panic!("{:?} is not a bad escape", other),
+    }
+}
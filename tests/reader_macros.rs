@@ -0,0 +1,138 @@
+extern crate ares;
+
+use std::rc::Rc;
+
+use ares::{Context, Value, AresError, ParseError, ReaderMacros, Reader, Cst, parse,
+           parse_with_macros, parse_recovering, parse_recovering_with_macros,
+           DEFAULT_MAX_NESTING};
+use ares::intern::SymbolIntern;
+
+fn color(form: Value, _: &mut SymbolIntern) -> Result<Value, String> {
+    match form {
+        Value::String(ref s) if s.len() == 6 => {
+            i64::from_str_radix(s, 16).map(Value::Int).map_err(|e| e.to_string())
+        }
+        other => Err(format!("expected six hex digits, found {:?}", other)),
+    }
+}
+
+fn context() -> Context<()> {
+    let mut ctx = Context::new();
+    ctx.set_reader_macro("color", color);
+    ctx.set_reader_macro("vec3", |form, interner| {
+        match form {
+            // `[1 2 3]` reads as `(quote (1 2 3))`.
+            Value::List(ref quoted) if quoted.len() == 2 => Ok(Value::List(Rc::new(vec![
+                Value::Symbol(interner.intern("list")),
+                Value::String(Rc::new("vec3".into())),
+                Value::List(quoted.clone()),
+            ]))),
+            _ => Err("expected three coordinates".into()),
+        }
+    });
+    ctx
+}
+
+#[test]
+fn tagged_forms_are_read_by_their_macro() {
+    let mut ctx = context();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    assert_eq!(ctx.eval_str("#color \"ff00aa\"").unwrap(), Value::Int(0xff00aa));
+    assert_eq!(ctx.eval_str("(+ 1 #color \"000001\")").unwrap(), Value::Int(2));
+    let v = ctx.eval_str("#vec3 [1 2 3]").unwrap();
+    assert_eq!(ctx.format_value(&v), "[vec3, [1, 2, 3]]");
+}
+
+#[test]
+fn unknown_tags_and_failing_macros_are_errors() {
+    let mut ctx = context();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    match ctx.eval_str("(list #colour \"ff00aa\")") {
+        Err(AresError::ParseError(ref e@ParseError::UnknownReaderMacro(..))) => {
+            assert_eq!(e.to_string(), "Unknown reader macro #colour at line 1, column 7");
+        }
+        other => panic!("{:?} is not an unknown reader macro", other),
+    }
+    match ctx.eval_str("#color 12") {
+        Err(AresError::ParseError(ref e@ParseError::ReaderMacroFailed(..))) => {
+            assert_eq!(e.to_string(),
+                       "Reader macro #color at line 1, column 1 failed: expected six hex \
+                        digits, found Int(12)");
+        }
+        other => panic!("{:?} is not a failed reader macro", other),
+    }
+    match ctx.eval_str("(#color)") {
+        Err(AresError::ParseError(ParseError::ExtraRightDelimiter(..))) => {}
+        other => panic!("{:?} is not an extra delimiter", other),
+    }
+}
+
+#[test]
+fn macros_without_a_context() {
+    let mut interner = SymbolIntern::new();
+    let mut macros = ReaderMacros::new();
+    macros.insert("color", color);
+    assert!(macros.contains("color"));
+
+    let forms = parse_with_macros("'(#color \"0000ff\")", &mut interner, None, &macros, DEFAULT_MAX_NESTING).unwrap();
+    assert_eq!(forms.len(), 1);
+    assert!(format!("{:?}", forms[0].0).contains("Int(255)"));
+
+    match parse("#color \"0000ff\"", &mut interner) {
+        Err(ParseError::UnknownReaderMacro(ref tag, _)) => assert_eq!(tag, "color"),
+        other => panic!("{:?} is not an unknown reader macro", other),
+    }
+    match parse_with_macros("#color", &mut interner, None, &macros, DEFAULT_MAX_NESTING) {
+        Err(ref e@ParseError::MissingTaggedForm(..)) => assert!(e.is_incomplete()),
+        other => panic!("{:?} is not a missing form", other),
+    }
+}
+
+#[test]
+fn tagged_forms_across_chunks() {
+    let mut ctx = context();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    let mut reader = Reader::new();
+    reader.push_str("#color\n");
+    let result = ctx.read(&mut reader);
+    assert!(result.forms.is_empty());
+    reader.push_str("\"00ff00\"\n");
+    let result = ctx.read(&mut reader);
+    assert_eq!(result.forms.len(), 1);
+    assert_eq!(ctx.eval_forms(result.forms).unwrap(), Value::Int(0x00ff00));
+}
+
+#[test]
+fn tagged_forms_in_the_syntax_tree() {
+    let program = "(draw #color  \"ff00aa\")";
+    let cst = Cst::parse(program).unwrap();
+    assert_eq!(cst.to_string(), program);
+}
+
+#[test]
+fn recovering_parses_know_reader_macros_if_given_them() {
+    let mut interner = SymbolIntern::new();
+    let mut macros = ReaderMacros::new();
+    macros.insert("color", color);
+
+    let program = "(a #color \"0000ff\") (b #color 5) (c";
+    let recovered = parse_recovering_with_macros(program, &mut interner, &macros);
+    assert_eq!(recovered.forms.len(), 2);
+    assert!(format!("{:?}", recovered.forms[0].0).contains("Int(255)"));
+    assert_eq!(recovered.errors.len(), 2);
+    match recovered.errors[0] {
+        ParseError::ReaderMacroFailed(ref tag, _, _) => assert_eq!(tag, "color"),
+        ref other => panic!("{:?} is not a failed reader macro", other),
+    }
+
+    // Without them, every tagged form is unknown.
+    let recovered = parse_recovering(program, &mut interner);
+    assert_eq!(recovered.errors.len(), 3);
+    match recovered.errors[0] {
+        ParseError::UnknownReaderMacro(ref tag, _) => assert_eq!(tag, "color"),
+        ref other => panic!("{:?} is not an unknown reader macro", other),
+    }
+}
@@ -0,0 +1,120 @@
+extern crate ares;
+
+use ares::{Context, AresError, AresResult, Position, Value, Span, SpanTable, parse_with_spans,
+           user_fn};
+use ares::intern::SymbolIntern;
+
+fn error_position(program: &str) -> (AresError, Option<Position>) {
+    let mut ctx = Context::new();
+    let mut dummy = ();
+    let mut ctx = ctx.load(&mut dummy);
+    let err = ctx.eval_str(program).unwrap_err();
+    (err, ctx.error_span().map(|span| span.start))
+}
+
+#[test]
+fn parsed_lists_have_spans() {
+    let mut interner = SymbolIntern::new();
+    let mut spans = SpanTable::new();
+    let forms = parse_with_spans("(a\n  (b c))", &mut interner, &mut spans).unwrap();
+    assert_eq!(forms.len(), 1);
+    let (ref outer, outer_span) = forms[0];
+    assert_eq!(outer_span.start, Position::new(1, 1));
+    let outer = match outer {
+        &Value::List(ref outer) => outer,
+        other => panic!("{:?} is not a list", other),
+    };
+    assert_eq!(spans.get(outer), Some(outer_span));
+    assert_eq!(spans.item(outer, 0).map(|s| s.start), Some(Position::new(1, 2)));
+    match outer[1] {
+        Value::List(ref inner) => {
+            assert_eq!(spans.get(inner).map(|s| s.start), Some(Position::new(2, 3)));
+            assert_eq!(spans.item(inner, 1).map(|s| s.start), Some(Position::new(2, 6)));
+        }
+        ref other => panic!("{:?} is not a list", other),
+    }
+}
+
+#[test]
+fn undefined_names() {
+    match error_position("(define x 1)\n(+ x\n   (foo 2))") {
+        (AresError::UndefinedName(_), Some(pos)) => assert_eq!(pos, Position::new(3, 4)),
+        other => panic!("{:?}", other),
+    }
+    match error_position("(define x 1)\n  undefined") {
+        (AresError::UndefinedName(_), Some(pos)) => assert_eq!(pos, Position::new(2, 3)),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn errors_inside_lambdas() {
+    let program = "(define f (lambda (a)
+                       (+ a \"b\")))
+                   (f 1)";
+    match error_position(program) {
+        (AresError::UnexpectedType { .. }, Some(pos)) => assert_eq!(pos, Position::new(2, 24)),
+        other => panic!("{:?}", other),
+    }
+    match error_position("(define f (lambda (a) a))\n(f)") {
+        (AresError::UnexpectedArity { .. }, Some(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn macro_expansions_use_the_call_site() {
+    let program = "(define-macro broken (lambda (x) `(+ ~x \"a\")))
+                   (define y 1)
+                   (broken y)";
+    match error_position(program) {
+        (AresError::UnexpectedType { .. }, Some(pos)) => assert_eq!(pos, Position::new(3, 20)),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn spans_are_reset_between_evaluations() {
+    let mut ctx = Context::new();
+    let mut dummy = ();
+    let mut ctx = ctx.load(&mut dummy);
+    assert!(ctx.eval_str("(foo)").is_err());
+    assert!(ctx.error_span().is_some());
+    assert!(ctx.eval_str("(+ 1 2)").is_ok());
+    assert_eq!(ctx.error_span(), None::<Span>);
+}
+
+#[test]
+fn errors_keep_their_span_through_foreign_functions() {
+    let mut ctx = Context::new();
+    ctx.set_fn("check",
+               user_fn("check", |args, ctx| -> AresResult<Value> {
+                   let err = ctx.eval(&args[0]).unwrap_err();
+                   assert_eq!(ctx.error_span().map(|span| span.start), Some(Position::new(1, 15)));
+                   Err(err)
+               }));
+    let mut dummy = ();
+    let mut ctx = ctx.load(&mut dummy);
+    match ctx.eval_str("(check (quote (+ 1 \"a\")))") {
+        Err(AresError::UnexpectedType { .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(ctx.error_span().map(|span| span.start), Some(Position::new(1, 15)));
+}
+
+#[test]
+fn errors_that_foreign_functions_handle_are_forgotten() {
+    let mut ctx = Context::new();
+    ctx.set_fn("ignore",
+               user_fn("ignore", |args, ctx| -> AresResult<Value> {
+                   assert!(ctx.eval(&args[0]).is_err());
+                   ctx.eval(&Value::Int(1))
+               }));
+    let mut dummy = ();
+    let mut ctx = ctx.load(&mut dummy);
+    match ctx.eval_str("(list (ignore (quote (+ 1 \"a\")))\n      (+ 5 \"b\"))") {
+        Err(AresError::UnexpectedType { .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(ctx.error_span().map(|span| span.start), Some(Position::new(2, 7)));
+}
@@ -0,0 +1,116 @@
+extern crate ares;
+
+use ares::{Context, Value, user_fn};
+
+#[macro_use]
+mod util;
+
+#[test]
+fn self_recursion_in_if_branches() {
+    eval_ok!("(define loop (lambda (n) (if (= n 0) 0 (loop (- n 1)))))
+              (loop 1000000)",
+             0);
+}
+
+#[test]
+fn mutual_recursion() {
+    eval_ok!("(define even? (lambda (n) (if (= n 0) true (odd? (- n 1)))))
+              (define odd? (lambda (n) (if (= n 0) false (even? (- n 1)))))
+              (even? 100001)",
+             false);
+}
+
+#[test]
+fn let_bodies_and_multiple_lambda_bodies() {
+    eval_ok!("(define count (lambda (n acc)
+                (let (m (- n 1))
+                  n
+                  (if (= n 0) acc (count m (+ acc 1))))))
+              (count 100000 0)",
+             100000);
+    eval_ok!("(define spin (lambda (n)
+                (+ 1 2)
+                (if (= n 0) n (spin (- n 1)))))
+              (spin 100000)",
+             0);
+}
+
+/// How deep the evaluator's stacks are when `stack-depth` is called.
+fn depth_at_the_bottom(n: i64) -> Value {
+    let mut ctx = Context::new();
+    ctx.set_fn("stack-depth",
+               user_fn("stack-depth", |_, ctx| {
+                   Ok(Value::Int((ctx.stack.len() + ctx.env_stack.len()) as i64))
+               }));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define down (lambda (n)
+                    (if (= n 0)
+                        (stack-depth)
+                        (let (m (- n 1)) (down m)))))")
+       .unwrap();
+    ctx.eval_str(&format!("(down {})", n)).unwrap()
+}
+
+#[test]
+fn tail_calls_run_in_constant_space() {
+    assert_eq!(depth_at_the_bottom(1), depth_at_the_bottom(1000));
+}
+
+#[test]
+fn calls_that_are_not_in_tail_position_still_return() {
+    eval_ok!("(define sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1))))))
+              (sum 20)",
+             210);
+    eval_ok!("(let (x 1) (+ x (let (y 2) (+ x y))))", 4);
+}
+
+#[test]
+fn foreign_functions_can_ask_for_tail_evaluation() {
+    let mut ctx = Context::new();
+    // Asks for its argument to be evaluated, then calls back into Ares
+    // before returning.
+    ctx.set_fn("later",
+               user_fn("later", |args, ctx| {
+                   let later = ctx.eval_in_tail_position(vec![args[0].clone()], None);
+                   try!(ctx.eval_str("(+ 1 2)"));
+                   later
+               }));
+    // Asks for something to be evaluated, but fails instead.
+    ctx.set_fn("fail-later",
+               user_fn("fail-later", |_, ctx| {
+                   try!(ctx.eval_in_tail_position(vec![Value::Int(1)], None));
+                   Err(ares::AresError::NoProgram)
+               }));
+    // Swallows the failure of `fail-later`.
+    ctx.set_fn("recover",
+               user_fn("recover", |args, ctx| {
+                   Ok(ctx.call(&args[0], &[]).unwrap_or(Value::Int(2)))
+               }));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    assert_eq!(ctx.eval_str("(later '(+ 10 20))").unwrap(), Value::Int(30));
+    assert_eq!(ctx.eval_str("(recover fail-later)").unwrap(), Value::Int(2));
+}
+
+#[test]
+fn tail_evaluations_are_only_done_once() {
+    let mut ctx = Context::new();
+    // Asks for a tail evaluation, but hides what it was given in a list.
+    ctx.set_fn("hide",
+               user_fn("hide", |args, ctx| {
+                   let later = try!(ctx.eval_in_tail_position(vec![args[0].clone()], None));
+                   Ok(Value::list(vec![later]))
+               }));
+    // Returns the first thing in a list.
+    ctx.set_fn("reveal",
+               user_fn("reveal", |args, _| Ok(args[0].as_list().unwrap()[0].clone())));
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+    ctx.eval_str("(define hidden (hide '(+ 10 20)))").unwrap();
+    assert_eq!(ctx.eval_str("(reveal hidden)").unwrap(), Value::Int(30));
+    match ctx.eval_str("(reveal hidden)") {
+        Err(ares::AresError::InvalidState(_)) => {}
+        other => panic!("{:?} is not an invalid state", other),
+    }
+}
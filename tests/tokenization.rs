@@ -42,9 +42,9 @@ macro_rules! parse_ok {
 
 #[test]
 fn parentheses() {
-    parse_fail!("(x y(", "Missing right delimiter )");
+    parse_fail!("(x y(", "Missing right delimiter ) for the form at line 1, column 5");
     parse_fail!("(x y) ()) z", "Extra right delimiter ) at line 1, column 9");
-    parse_fail!("(x (y (z) \"())))))\"", "Missing right delimiter )");
+    parse_fail!("(x (y (z) \"())))))\"", "Missing right delimiter ) for the form at line 1, column 4");
     parse_ok!("(x (y (z \"())))\")))");
 }
 
@@ -67,11 +67,46 @@ sdf", "Unterminated string beginning at line 2, column 2");
     parse_fail!("(->int \"10\"x 5)", "Unexpected character x at line 1, column 12, while parsing a string starting at line 1, column 8");
 }
 
-#[allow(overflowing_literals)]
+#[test]
+fn raw_strings() {
+    parse_ok!(r#"r"C:\dir\n""#, r"C:\dir\n");
+    parse_ok!(r###"r#"say "hi""#"###, r#"say "hi""#);
+    parse_ok!(r###"r##"a "# b"##"###, r##"a "# b"##);
+    parse_ok!(r#"r"""#, "");
+    parse_ok!("r\"line one\nline two\"", "line one\nline two");
+    parse_ok!("(r \"not raw\")");
+    parse_fail!(r#"r#"abc"#, "Unterminated string beginning at line 1, column 1");
+    parse_fail!(r###"r#"abc"##"###, "Unexpected character # at line 1, column 9, while parsing a raw string starting at line 1, column 1");
+    parse_fail!("r\"a\nb\" 3z", "Unexpected character z at line 2, column 5, while parsing a number starting at line 2, column 4");
+}
+
+#[test]
+fn multiline_strings() {
+    parse_ok!("\"\"\"
+        usage: thing [options]
+          -v   \\verbose
+
+          -q   \"quiet\"
+        \"\"\"", "usage: thing [options]\n  -v   \\verbose\n\n  -q   \"quiet\"");
+    parse_ok!("\"\"\"
+      one
+    two
+      \"\"\"", "  one\ntwo");
+    parse_ok!("\"\"\"
+    one\"\"\"", "one");
+    parse_ok!("\"\"\"   \r\n  x\r\n  \"\"\"", "x");
+    parse_ok!("\"\"\"\r\n    one\r\n\r\n      two\r\n    \"\"\"", "one\n\n  two");
+    parse_ok!("\"\"\"\n\"\"\"", "");
+    parse_fail!("\"\"\"x\n\"\"\"", "Unexpected character x at line 1, column 4, while parsing a multi-line string starting at line 1, column 1");
+    parse_fail!("(a \"\"\"\n  b\")", "Unterminated string beginning at line 1, column 4");
+    parse_fail!("\"\"\"\n  a\n  \"\"\" 3z", "Unexpected character z at line 3, column 8, while parsing a number starting at line 3, column 7");
+}
+
 #[test]
 fn numbers() {
-    parse_fail!("(+ 3 32.e.)", "Could not convert 32.e.: invalid float literal");
-    parse_ok!("-500e400", -500e400);
+    parse_fail!("(+ 3 32.e.)", "Could not convert 32.e. at line 1, column 6: invalid float literal");
+    parse_fail!("-500e400", "Could not convert -500e400 at line 1, column 1: number too large to fit in a float");
+    parse_fail!("1e400", "Could not convert 1e400 at line 1, column 1: number too large to fit in a float");
     parse_ok!("-500e4", -500e4);
     // TODO: -5.123 is parsed as -5.1229999999999999
     // parse_ok!("-5.123", -5.123);
@@ -81,6 +116,50 @@ fn numbers() {
     parse_fail!("(+ 3z)", "Unexpected character z at line 1, column 5, while parsing a number starting at line 1, column 4");
 }
 
+#[test]
+fn extended_numbers() {
+    parse_ok!("0xFF", 255);
+    parse_ok!("0xdead_BEEF", 0xdeadbeef as i64);
+    parse_ok!("-0x10", -16);
+    parse_ok!("+0x10", 16);
+    parse_ok!("0b1010", 10);
+    parse_ok!("0b1111_0000", 0xf0);
+    parse_ok!("0o777", 511);
+    parse_ok!("1_000_000", 1000000);
+    parse_ok!("-1_000", -1000);
+    parse_ok!("1_000.000_5", 1000.0005);
+    parse_ok!("1e-5", 1e-5);
+    parse_ok!("-2.5E+3", -2500.0);
+    parse_ok!("0x7fff_ffff_ffff_ffff", i64::max_value());
+    parse_ok!("-0x8000_0000_0000_0000", i64::min_value());
+
+    parse_fail!("0x10000000000000000", "Could not convert 0x10000000000000000 at line 1, column 1: number too large to fit in target type");
+    parse_fail!("99999999999999999999", "Could not convert 99999999999999999999 at line 1, column 1: number too large to fit in target type");
+    parse_fail!("0b102", "Could not convert 0b102 at line 1, column 1: invalid digit found in string");
+    parse_fail!("0x", "Could not convert 0x at line 1, column 1: cannot parse integer from empty string");
+    for bad in &["1_", "1__0", "0x_FF", "0xFF_", "1_.5", "1._5", "1_e5"] {
+        parse_fail!(bad, format!("Could not convert {} at line 1, column 1: _ is only allowed between two digits", bad));
+    }
+    parse_fail!("0xFG", "Unexpected character G at line 1, column 4, while parsing a number starting at line 1, column 1");
+    parse_fail!("1e5-3", "Unexpected character - at line 1, column 4, while parsing a number starting at line 1, column 1");
+}
+
+#[test]
+fn chars() {
+    parse_ok!("\\a", 'a');
+    parse_ok!("\\(", '(');
+    parse_ok!("\\ ", ' ');
+    parse_ok!("\\tab", '\t');
+    parse_ok!("\\u{2764}", '❤');
+    parse_ok!("(\\a \\b)");
+    parse_ok!("[\\u{41}]");
+    parse_fail!("\\foo", "Unknown character literal \\foo at line 1, column 1");
+    parse_fail!("\\x80", "Unknown character literal \\x80 at line 1, column 1");
+    parse_fail!("\\u{110000}", "Unknown character literal \\u{110000} at line 1, column 1");
+    parse_fail!("(\\u{41 x)", "Unknown character literal \\u{41 at line 1, column 2");
+    parse_fail!("\\", "Unknown character literal \\ at line 1, column 1");
+}
+
 #[test]
 fn symbols()
 {
@@ -94,7 +173,7 @@ fn quote()
     parse_ok!("(foo '(1 (2 3) \"df)\"))");
     // these are admittedly weird error messages.
     parse_fail!("(foo ')", "Extra right delimiter ) at line 1, column 7");
-    parse_fail!("(foo '", "Missing right delimiter )");
+    parse_fail!("(foo '", "Missing right delimiter ) for the form at line 1, column 1");
 }
 
 
@@ -106,6 +185,7 @@ fn mapliteral()
     parse_fail!("{1 2 [1 2] 4}", "Map literal at line 1, column 1 is malformed");
     parse_ok!("{}", HashMap::<Value, Value>::new());
     parse_ok!("{\"1\" 2 'a 4 1.0 {1 2}}");
+    parse_ok!("{:a 1 :b \"c\"}");
 }
 
 
@@ -126,3 +206,114 @@ fn has_expected_quotes() {
                  Value::list(vec![1.into(), Value::Symbol(interner.intern("a"))])]), &mut interner);
     parse_ok!("{'a 'b}", hashmap!(Value::Symbol(interner.intern("a")) => Value::Symbol(interner.intern("b"))), &mut interner);
 }
+
+#[test]
+fn line_comments() {
+    let mut interner = SymbolIntern::new();
+    parse_ok!("; leading comment
+               (1 2) ; trailing comment", vec![1, 2]);
+    parse_ok!("(1 ; one
+                2 ; two
+               )", vec![1, 2]);
+    parse_ok!("(foo;comment\n)", Value::list(vec![Value::Symbol(interner.intern("foo"))]), &mut interner);
+    parse_ok!("(\"a;b\")", Value::list(vec!["a;b".into()]));
+    parse_ok!("[1 ; two\n 3]", Value::list(
+            vec![Value::Symbol(interner.intern("quote")),
+                 Value::list(vec![1.into(), 3.into()])]), &mut interner);
+    parse_ok!("{1 ; comment\n 2}", hashmap!(Value::Int(1) => Value::Int(2)));
+    parse_ok!("5;comment", 5);
+}
+
+#[test]
+fn block_comments() {
+    let mut interner = SymbolIntern::new();
+    parse_ok!("#| comment |# 5", 5);
+    parse_ok!("(1 #| nested #| comments |# are ok |# 2)", vec![1, 2]);
+    parse_ok!("(1 #|
+                  spanning lines
+               |# 2)", vec![1, 2]);
+    parse_ok!("{1 #| x |# 2}", hashmap!(Value::Int(1) => Value::Int(2)));
+    parse_ok!("[1 #| x |# 2]", Value::list(
+            vec![Value::Symbol(interner.intern("quote")),
+                 Value::list(vec![1.into(), 2.into()])]), &mut interner);
+    parse_fail!("(1 #| 2)", "Unterminated block comment beginning at line 1, column 4");
+    parse_fail!("x
+  #| #| |#", "Unterminated block comment beginning at line 2, column 3");
+}
+
+#[test]
+fn datum_comments() {
+    let mut interner = SymbolIntern::new();
+    parse_ok!("#_ 1 2", 2);
+    parse_ok!("(1 #_(2 (3 4)) 5)", vec![1, 5]);
+    parse_ok!("(1 #_ #_ 2 3 4)", vec![1, 4]);
+    parse_ok!("(1 #_'(2 3) 4)", vec![1, 4]);
+    parse_ok!("(1 #_\"str\" 4)", vec![1, 4]);
+    parse_ok!("[1 #_[2 3] 4]", Value::list(
+            vec![Value::Symbol(interner.intern("quote")),
+                 Value::list(vec![1.into(), 4.into()])]), &mut interner);
+    parse_ok!("{1 #_{3 4} 2}", hashmap!(Value::Int(1) => Value::Int(2)));
+    parse_fail!("(1 #_)", "Datum comment at line 1, column 4 has nothing to comment out");
+    parse_fail!("1 #_", "Datum comment at line 1, column 3 has nothing to comment out");
+    parse_fail!("#_ '", "Datum comment at line 1, column 1 has nothing to comment out");
+    parse_fail!("(1 #_(2 3]", "Extra right delimiter ) at line 1, column 10");
+    parse_fail!("(1 #_(2 3", "Missing right delimiter ) for the form at line 1, column 6");
+}
+
+#[test]
+fn comments_after_form_likes() {
+    let mut interner = SymbolIntern::new();
+    parse_ok!("'; comment
+               a", Value::list(vec![Value::Symbol(interner.intern("quote")),
+                                    Value::Symbol(interner.intern("a"))]), &mut interner);
+    parse_ok!("`#| comment |# a", Value::list(vec![Value::Symbol(interner.intern("quasiquote")),
+                                                    Value::Symbol(interner.intern("a"))]),
+              &mut interner);
+    parse_ok!("'#_ a b", Value::list(vec![Value::Symbol(interner.intern("quote")),
+                                          Value::Symbol(interner.intern("b"))]), &mut interner);
+    parse_ok!("`(1 ~#_ a b)", Value::list(vec![
+        Value::Symbol(interner.intern("quasiquote")),
+        Value::list(vec![1.into(),
+                         Value::list(vec![Value::Symbol(interner.intern("unquote")),
+                                          Value::Symbol(interner.intern("b"))])])]),
+              &mut interner);
+}
+
+fn recovered_errors(prog: &str) -> (usize, Vec<(String, ares::Position)>) {
+    let mut interner = SymbolIntern::new();
+    let recovered = ares::parse_recovering(prog, &mut interner);
+    let errors = recovered.errors
+                          .iter()
+                          .map(|e| (format!("{}", e), e.position()))
+                          .collect();
+    (recovered.forms.len(), errors)
+}
+
+#[test]
+fn recovering_reports_every_error() {
+    let (forms, errors) = recovered_errors("(a 3z b) (c \"\\w\" d)\n(e ]\n(f)");
+    assert_eq!(forms, 4);
+    assert_eq!(errors.iter().map(|e| e.1).collect::<Vec<_>>(),
+               vec![ares::Position::new(1, 5), ares::Position::new(1, 14), ares::Position::new(2, 4)]);
+
+    let (forms, errors) = recovered_errors("(foo ') 1.2.3 (bar) {1 2 3} ))");
+    assert_eq!(forms, 2);
+    assert_eq!(errors.len(), 5);
+    assert_eq!(errors[1].0, "Could not convert 1.2.3 at line 1, column 9: invalid float literal");
+    assert_eq!(errors[1].1, ares::Position::new(1, 9));
+}
+
+#[test]
+fn recovering_from_unclosed_forms() {
+    // The next form starting in the first column ends the unclosed one.
+    let (forms, errors) = recovered_errors("(define x (+ 1 2)\n(define y 5)\n  (g 1)\n");
+    assert_eq!(forms, 2);
+    assert_eq!(errors,
+               vec![("Missing right delimiter ) for the form at line 1, column 1".into(),
+                     ares::Position::new(1, 1))]);
+
+    // But only when something is actually unclosed.
+    let (forms, errors) = recovered_errors("(define (f)\n(g))");
+    assert_eq!(forms, 1);
+    assert!(errors.is_empty());
+}
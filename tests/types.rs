@@ -1,4 +1,5 @@
 extern crate ares;
+use std::collections::HashMap;
 
 #[macro_use]
 mod util;
@@ -13,3 +14,30 @@ fn unit_types() {
     eval_ok!("false", false);
     eval_ok!("\"foobar\"", "foobar");
 }
+
+#[test]
+fn chars() {
+    eval_ok!("\\a", 'a');
+    eval_ok!("\\newline", '\n');
+    eval_ok!("\\space", ' ');
+    eval_ok!("\\u{1F600}", '\u{1F600}');
+    eval_ok!("\\x41", 'A');
+    eval_ok!("(char? \\a \\()", true);
+    eval_ok!("(char? \\a \"a\")", false);
+    eval_ok!("(= \\a \\a)", true);
+    eval_ok!("(= \\a \\b)", false);
+    eval_ok!("{\\a \\b \\c \\d}", hashmap!('a' => 'b', 'c' => 'd'));
+}
+
+#[test]
+fn keywords() {
+    eval_ok!("(keyword? :a :b-c)", true);
+    eval_ok!("(keyword? :a 'a)", false);
+    eval_ok!("(= :a :a)", true);
+    eval_ok!("(= :a 'a)", false);
+    eval_ok!("(= :a (->keyword \"a\"))", true);
+    eval_ok!("(= :a (->keyword 'a))", true);
+    eval_ok!("(->string :foo)", ":foo");
+    eval_ok!("(->string [:a 'b])", "[:a, 'b]");
+    eval_ok!("(->string ':)", "':");
+}
@@ -0,0 +1,44 @@
+extern crate ares;
+
+use std::mem::size_of;
+
+use ares::{Context, Value};
+
+#[test]
+fn values_are_two_words() {
+    assert!(size_of::<Value>() <= 2 * size_of::<usize>());
+}
+
+#[test]
+fn accessors_see_through_the_representation() {
+    let mut ctx = Context::new();
+    let mut state = ();
+    let mut ctx = ctx.load(&mut state);
+
+    assert_eq!(ctx.eval_str("1").unwrap().as_int(), Some(1));
+    assert_eq!(ctx.eval_str("1.5").unwrap().as_float(), Some(1.5));
+    assert_eq!(ctx.eval_str("true").unwrap().as_bool(), Some(true));
+    assert_eq!(ctx.eval_str("\"hi\"").unwrap().as_str(), Some("hi"));
+    assert_eq!(ctx.eval_str("1").unwrap().as_str(), None);
+
+    let list = ctx.eval_str("(list 1 2)").unwrap();
+    assert_eq!(list.as_list(), Some(&[Value::Int(1), Value::Int(2)][..]));
+
+    let some = ctx.eval_str("(some 1)").unwrap();
+    assert_eq!(some.as_option(), Some(Some(&Value::Int(1))));
+    assert_eq!(ctx.eval_str("(none)").unwrap().as_option(), Some(None));
+    assert_eq!(some, Value::option(Some(Value::Int(1))));
+
+    let lambda = ctx.eval_str("(define f (lambda (x) x)) f").unwrap();
+    let (procedure, is_macro) = lambda.as_lambda().unwrap();
+    assert_eq!(procedure.name, Some("f".to_string()));
+    assert!(!is_macro);
+
+    let plus = ctx.eval_str("+").unwrap();
+    assert_eq!(plus.as_foreign_fn().unwrap().name, "+");
+    assert!(plus.as_lambda().is_none());
+
+    let data = Value::user_data(5u32);
+    assert_eq!(data.as_user_data::<u32>(), Some(&5));
+    assert_eq!(data.as_user_data::<i32>(), None);
+}